@@ -1,27 +1,62 @@
 use bevy::prelude::*;
 
-use bevy_integrator::{SimTime, Solver};
+use bevy_integrator::{recorder::Recorder, SimTime, Solver};
 use car::{
     build::{build_car, car_startup_system},
-    environment::build_environment,
-    setup::{camera_setup, simulation_setup},
+    environment::{build_environment, terrain_startup_system},
+    setup::{camera_setup, simulation_setup, traction_control_setup, trim_settings},
+};
+use rigid_body::{
+    headless::{floating_joints, joint_states, run_headless},
+    plugin::RigidBodyPlugin,
 };
-use rigid_body::plugin::RigidBodyPlugin;
 
 // Main function
 fn main() {
+    // `cargo run --example car -- --headless` simulates 10 seconds without a window
+    let headless = std::env::args().any(|arg| arg == "--headless");
+    let end_time = if headless { Some(10.) } else { None };
+
     let car_definition = build_car();
     // Create App
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, None),
-            solver: Solver::RK4,
-            simulation_setup: vec![simulation_setup],
-            environment_setup: vec![camera_setup],
-            name: "car_demo".to_string(),
-        })
-        .insert_resource(car_definition)
-        .add_systems(Startup, car_startup_system)
-        .add_systems(Startup, build_environment)
-        .run();
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, end_time),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup, traction_control_setup],
+        environment_setup: vec![camera_setup],
+        name: "car_demo".to_string(),
+        headless,
+    })
+    .insert_resource(car_definition)
+    // start at the static ride height
+    .insert_resource(trim_settings())
+    .add_systems(Startup, car_startup_system);
+
+    // `--record` saves the joint states and controls (at 100 Hz) when the app exits
+    if std::env::args().any(|arg| arg == "--record") {
+        app.insert_resource(
+            Recorder::new(5)
+                .with_csv("car_recording.csv")
+                .with_binary("car_recording.bin"),
+        );
+    }
+
+    if headless {
+        app.add_systems(Startup, terrain_startup_system);
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+        for joint in floating_joints(&mut app.world) {
+            let (roll, pitch, yaw) = joint.rotation.euler_angles();
+            let (p, v) = (joint.position, joint.rotation * joint.v.v);
+            println!(
+                "{}: position = ({:.6}, {:.6}, {:.6}), rpy = ({:.6}, {:.6}, {:.6}), velocity = ({:.6}, {:.6}, {:.6})",
+                joint.name, p.x, p.y, p.z, roll, pitch, yaw, v.x, v.y, v.z
+            );
+        }
+    } else {
+        app.add_systems(Startup, build_environment).run();
+    }
 }
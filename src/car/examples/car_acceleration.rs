@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{
+    events::{CrossingAction, CrossingDirection, ZeroCrossingAppExt, ZeroCrossings},
+    SimTime, Solver,
+};
+use car::{
+    build::{build_car, car_startup_system, CarDefinition},
+    control::{user_control_system, CarControl},
+    environment::terrain_startup_system,
+    setup::{simulation_setup, trim_settings},
+};
+use rigid_body::{headless::run_headless, joint::FloatingJoint, plugin::RigidBodyPlugin};
+
+const TARGET_SPEED: f64 = 60. / 3.6; // m/s
+const MAX_ROLL: f64 = 60.; // degrees
+
+// `cargo run --release --example car_acceleration` runs a full throttle acceleration test. It stops
+// when the car reaches 60 km/h (or rolls over), and prints the time to 60 km/h and to 100 m.
+fn main() {
+    let max_roll = MAX_ROLL.to_radians();
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(30.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup: vec![],
+        name: "car_acceleration".to_string(),
+        headless: true,
+    })
+    .insert_resource(build_car())
+    .insert_resource(trim_settings())
+    .add_zero_crossing(
+        "speed_60kph",
+        |world| speed(world) - TARGET_SPEED,
+        CrossingDirection::Rising,
+        CrossingAction::Stop,
+    )
+    .add_zero_crossing(
+        "rollover",
+        move |world| roll(world).abs() - max_roll,
+        CrossingDirection::Rising,
+        CrossingAction::Stop,
+    )
+    .add_zero_crossing(
+        "distance_100m",
+        |world| distance(world) - 100.,
+        CrossingDirection::Rising,
+        CrossingAction::Log,
+    )
+    .add_systems(Startup, (car_startup_system, terrain_startup_system))
+    .add_systems(Update, full_throttle.after(user_control_system));
+
+    run_headless(&mut app);
+
+    let crossings = app.world.resource::<ZeroCrossings>();
+    for event in crossings.occurred() {
+        println!("{}: t = {:.4} s", event.name, event.time);
+    }
+    if crossings.stop_time().is_none() {
+        println!("the car did not reach 60 km/h within 30 s");
+    }
+}
+
+fn full_throttle(mut control: ResMut<CarControl>) {
+    control.throttle = 1.0;
+}
+
+// the floating joint of the chassis
+fn chassis(world: &mut World) -> FloatingJoint {
+    world
+        .query::<&FloatingJoint>()
+        .iter(world)
+        .find(|body| body.name == "chassis")
+        .unwrap()
+        .clone()
+}
+
+// horizontal speed
+fn speed(world: &mut World) -> f64 {
+    let chassis = chassis(world);
+    let velocity = chassis.rotation * chassis.v.v;
+    velocity.x.hypot(velocity.y)
+}
+
+// straight line distance from the start
+fn distance(world: &mut World) -> f64 {
+    let start = world.resource::<CarDefinition>().chassis.initial_position;
+    let position = chassis(world).position;
+    (position.x - start[0]).hypot(position.y - start[1])
+}
+
+fn roll(world: &mut World) -> f64 {
+    chassis(world).rotation.euler_angles().0
+}
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{integrator_schedule, recorder::Recorder, SimTime, Solver};
+use car::{
+    build::{build_car, car_startup_system, CarDefinition},
+    control::{user_control_system, CarControl},
+    environment::terrain_startup_system,
+    physics::DriveType,
+    setup::{simulation_setup, trim_settings},
+};
+use rigid_body::{batch::Batch, joint::FloatingJoint, plugin::RigidBodyPlugin};
+
+// `cargo run --release --example car_batch` sweeps the suspension stiffness and the drive torque,
+// with a random tire friction, and writes one row per run (and the statistics of every metric) to
+// car_batch.csv
+fn main() {
+    let results = Batch::new(build_car(), car_app, 5.)
+        .with_values(
+            "suspension_stiffness",
+            set_suspension_stiffness,
+            &[15000., 25000., 40000.],
+        )
+        .with_values("drive_torque_scale", set_drive_torque_scale, &[0.5, 1.0])
+        .with_normal("coefficient_of_friction", set_friction, 0.8, 0.05)
+        .with_samples(4)
+        .with_metric("distance", distance)
+        .with_metric("speed", speed)
+        .with_metric("max_roll", max_roll)
+        .run();
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => {
+            println!("could not run the batch: {error}");
+            return;
+        }
+    };
+
+    for summary in results.summary() {
+        println!(
+            "{}: mean = {:.4}, std_dev = {:.4}, min = {:.4}, max = {:.4}",
+            summary.name, summary.mean, summary.std_dev, summary.min, summary.max
+        );
+    }
+    for (index, run) in results.runs.iter().enumerate() {
+        if let Some(error) = &run.error {
+            println!("run {index} failed: {error}");
+        }
+    }
+    if let Err(error) = results.write_csv("car_batch.csv") {
+        println!("could not write car_batch.csv: {error}");
+    }
+}
+
+fn car_app(car: CarDefinition) -> App {
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, None), // the end time is set by the batch
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup],
+        environment_setup: vec![],
+        name: "car_batch".to_string(),
+        headless: true,
+    })
+    .insert_resource(car)
+    .insert_resource(trim_settings())
+    .insert_resource(Recorder::new(5).with_selection(&["chassis.q*"]))
+    .add_systems(Startup, (car_startup_system, terrain_startup_system))
+    // the controls are set before the step of the same update
+    .add_systems(
+        Update,
+        full_throttle_turn
+            .after(user_control_system)
+            .before(integrator_schedule),
+    );
+    app
+}
+
+// every run drives the same maneuver
+fn full_throttle_turn(mut control: ResMut<CarControl>) {
+    control.throttle = 1.0;
+    control.steering = 0.5;
+}
+
+fn set_suspension_stiffness(car: &mut CarDefinition, stiffness: f64) {
+    for suspension in car.suspension.iter_mut() {
+        suspension.stiffness = stiffness;
+    }
+}
+
+fn set_drive_torque_scale(car: &mut CarDefinition, scale: f64) {
+    for drive in car.drives.iter_mut() {
+        if let DriveType::DrivenWheelLookup(lookup) = drive {
+            lookup.scale_torque(scale);
+        }
+    }
+}
+
+fn set_friction(car: &mut CarDefinition, coefficient_of_friction: f64) {
+    car.wheel.coefficient_of_friction = coefficient_of_friction;
+}
+
+// the floating joint of the chassis
+fn chassis(world: &mut World) -> FloatingJoint {
+    world
+        .query::<&FloatingJoint>()
+        .iter(world)
+        .find(|body| body.name == "chassis")
+        .unwrap()
+        .clone()
+}
+
+// horizontal speed
+fn speed(world: &mut World) -> f64 {
+    let chassis = chassis(world);
+    let velocity = chassis.rotation * chassis.v.v;
+    velocity.x.hypot(velocity.y)
+}
+
+// straight line distance from the start
+fn distance(world: &mut World) -> f64 {
+    let start = world.resource::<CarDefinition>().chassis.initial_position;
+    let position = chassis(world).position;
+    (position.x - start[0]).hypot(position.y - start[1])
+}
+
+fn max_roll(world: &mut World) -> f64 {
+    let recording = world.resource::<Recorder>().recording();
+    let channel = |name| recording.channel(name).map(|channel| &channel.values);
+    let (Some(qw), Some(qx), Some(qy), Some(qz)) = (
+        channel("chassis.qw"),
+        channel("chassis.qx"),
+        channel("chassis.qy"),
+        channel("chassis.qz"),
+    ) else {
+        return f64::NAN;
+    };
+    // the roll of the ZYX euler angles of the recorded quaternion
+    (0..qw.len()).fold(0., |max: f64, i| {
+        let roll =
+            (2. * (qw[i] * qx[i] + qy[i] * qz[i])).atan2(1. - 2. * (qx[i] * qx[i] + qy[i] * qy[i]));
+        max.max(roll.abs())
+    })
+}
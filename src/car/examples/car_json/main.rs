@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use car::{
+    build::{build_car, car_startup_system, CarDefinition},
+    environment::{build_environment, terrain_startup_system},
+    setup::{camera_setup, simulation_setup, traction_control_setup, trim_settings},
+};
+use rigid_body::{
+    headless::{floating_joints, joint_states, run_headless},
+    plugin::RigidBodyPlugin,
+};
+
+const DEFAULT_CAR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/car_json/car.json");
+
+// The car demo, with the car loaded from a JSON definition file:
+// `cargo run --example car_json -- car/examples/car_json/car.json`
+// `cargo run --example car_json -- --save my_car.json` writes the definition of `build_car` to a file
+// (to start a new setup from), and exits.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--save") {
+        let Some(path) = args.get(index + 1) else {
+            eprintln!("--save needs a file name");
+            std::process::exit(1);
+        };
+        if let Err(error) = build_car().save(path) {
+            eprintln!("could not save the car definition: {error}");
+            std::process::exit(1);
+        }
+        println!("saved the car definition to {path}");
+        return;
+    }
+
+    // `--headless` simulates 10 seconds without a window
+    let headless = args.iter().any(|arg| arg == "--headless");
+    let end_time = if headless { Some(10.) } else { None };
+    let path = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .map_or(DEFAULT_CAR, |path| path.as_str());
+
+    let car_definition = match CarDefinition::load(path) {
+        Ok(car_definition) => car_definition,
+        Err(error) => {
+            eprintln!("could not load the car definition: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, end_time),
+        solver: Solver::RK4,
+        simulation_setup: vec![simulation_setup, traction_control_setup],
+        environment_setup: vec![camera_setup],
+        name: "car_json".to_string(),
+        headless,
+    })
+    .insert_resource(car_definition)
+    // start at the static ride height
+    .insert_resource(trim_settings())
+    .add_systems(Startup, car_startup_system);
+
+    if headless {
+        app.add_systems(Startup, terrain_startup_system);
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+        for joint in floating_joints(&mut app.world) {
+            let (roll, pitch, yaw) = joint.rotation.euler_angles();
+            let (p, v) = (joint.position, joint.rotation * joint.v.v);
+            println!(
+                "{}: position = ({:.6}, {:.6}, {:.6}), rpy = ({:.6}, {:.6}, {:.6}), velocity = ({:.6}, {:.6}, {:.6})",
+                joint.name, p.x, p.y, p.z, roll, pitch, yaw, v.x, v.y, v.z
+            );
+        }
+    } else {
+        app.add_systems(Startup, build_environment).run();
+    }
+}
@@ -1,27 +1,198 @@
+use std::{fs, io, path::Path};
+
 use bevy::prelude::*;
+use nalgebra::UnitQuaternion;
+use serde::{Deserialize, Serialize};
 
 use cameras::control::CameraParentList;
 use rigid_body::{
     definitions::{MeshDef, MeshTypeDef, TransformDef},
     joint::{Base, Joint},
+    limits::JointLimit,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
 };
 
 use crate::{
     physics::{
-        BrakeWheel, DriveType, DrivenWheelLookup, SteeringCurvature, SteeringType,
-        SuspensionComponent,
+        check_torque_curve, BrakeWheel, DriveType, DrivenWheelLookup, SteeringCurvature,
+        SteeringType, SuspensionComponent,
     },
     tire::PointTire,
 };
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct CarDefinition {
-    chassis: Chassis,
-    suspension: Vec<Suspension>,
-    wheel: Wheel,
-    drives: Vec<DriveType>,
-    brake: Brake,
+    pub chassis: Chassis,
+    pub suspension: Vec<Suspension>,
+    pub wheel: Wheel,
+    pub drives: Vec<DriveType>,
+    pub brake: Brake,
+}
+
+// Car definitions are saved as JSON files, so a library of vehicle setups can be kept next to the
+// code (see the car_json example)
+impl CarDefinition {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    // read and validate a car definition
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let car: Self = serde_json::from_reader(file)
+            .map_err(|error| invalid_data(format!("{}: {error}", path.display())))?;
+        car.validate()
+            .map_err(|error| invalid_data(format!("{}: {error}", path.display())))?;
+        Ok(car)
+    }
+
+    // finite values, positive masses and wheel radius and width, non-negative suspension
+    // stiffnesses and dampings and brake torques, at least four corners (the first two are the
+    // front corners, ahead of the others), a drive for every corner, and increasing torque curve
+    // speeds
+    pub fn validate(&self) -> Result<(), String> {
+        let chassis = &self.chassis;
+        check_finite(
+            "the chassis",
+            &[
+                &[chassis.mass][..],
+                &chassis.cg_position,
+                &chassis.moi,
+                &chassis.dimensions,
+                &chassis.position,
+                &chassis.initial_position,
+                &chassis.initial_orientation,
+            ]
+            .concat(),
+        )?;
+        let wheel = &self.wheel;
+        check_finite(
+            "the wheel",
+            &[
+                &[
+                    wheel.mass,
+                    wheel.radius,
+                    wheel.width,
+                    wheel.moi_y,
+                    wheel.moi_xz,
+                    wheel.damping,
+                    wheel.coefficient_of_friction,
+                    wheel.rolling_radius,
+                    wheel.low_speed,
+                    wheel.normalized_slip_stiffness,
+                    wheel.filter_time,
+                ][..],
+                &wheel.stiffness,
+            ]
+            .concat(),
+        )?;
+        let brake = &self.brake;
+        check_finite("the brake", &[brake.front_torque, brake.rear_torque])?;
+        if chassis.mass <= 0. {
+            return Err("the chassis mass must be positive".to_string());
+        }
+        if wheel.mass <= 0. {
+            return Err("the wheel mass must be positive".to_string());
+        }
+        if wheel.radius <= 0. || wheel.width <= 0. {
+            return Err("the wheel radius and width must be positive".to_string());
+        }
+        if brake.front_torque < 0. || brake.rear_torque < 0. {
+            return Err("the brake torques must not be negative".to_string());
+        }
+        if self.suspension.len() < 4 {
+            return Err(format!(
+                "a car needs four or more corners, found {}",
+                self.suspension.len()
+            ));
+        }
+        for suspension in self.suspension.iter() {
+            let steering = match &suspension.steering {
+                SteeringType::None => vec![],
+                SteeringType::Curvature(steering) => {
+                    vec![steering.x, steering.y, steering.max_curvature]
+                }
+                SteeringType::Angle(steering) => vec![steering.max_angle],
+            };
+            check_finite(
+                &format!("suspension {}", suspension.name),
+                &[
+                    &[
+                        suspension.mass,
+                        suspension.stiffness,
+                        suspension.damping,
+                        suspension.preload,
+                        suspension.moi,
+                    ][..],
+                    &suspension.location,
+                    &steering,
+                ]
+                .concat(),
+            )?;
+            if suspension.mass <= 0. {
+                return Err(format!(
+                    "suspension {}: the mass must be positive",
+                    suspension.name
+                ));
+            }
+            if suspension.stiffness < 0. || suspension.damping < 0. {
+                return Err(format!(
+                    "suspension {}: the stiffness and damping must not be negative",
+                    suspension.name
+                ));
+            }
+        }
+        let (front, rear) = self.suspension.split_at(2);
+        let front_x = front
+            .iter()
+            .map(|suspension| suspension.location[0])
+            .fold(f64::INFINITY, f64::min);
+        if let Some(suspension) = rear.iter().find(|suspension| suspension.location[0] >= front_x) {
+            return Err(format!(
+                "suspension {}: the first two corners (the front corners) must be ahead of it",
+                suspension.name
+            ));
+        }
+        if self.drives.len() != self.suspension.len() {
+            return Err(format!(
+                "there are {} drives for {} corners",
+                self.drives.len(),
+                self.suspension.len()
+            ));
+        }
+        for (drive, suspension) in self.drives.iter().zip(self.suspension.iter()) {
+            let name = format!("drive of corner {}", suspension.name);
+            match drive {
+                DriveType::None => {}
+                DriveType::DrivenWheel(driven) => check_finite(
+                    &name,
+                    &[driven.max_torque, driven.max_speed, driven.max_power],
+                )?,
+                DriveType::DrivenWheelLookup(driven) => {
+                    let (speeds, torques) = (driven.torque_lookup.x(), driven.torque_lookup.y());
+                    check_finite(&name, &[speeds, torques].concat())?;
+                    check_torque_curve(speeds, torques)
+                        .map_err(|error| format!("{name}: {error}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_finite(name: &str, values: &[f64]) -> Result<(), String> {
+    if values.iter().all(|value| value.is_finite()) {
+        Ok(())
+    } else {
+        Err(format!("{name}: the values must be finite"))
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
 
 const CHASSIS_MASS: f64 = 1000.;
@@ -57,6 +228,9 @@ pub fn build_car() -> CarDefinition {
     let suspension_damping = 0.25 * 2. * (suspension_stiffness * (1000. / 4.) as f64).sqrt();
     let suspension_preload = mass * (GRAVITY / 4.);
     let suspension_moi = (2. / 3.) * suspension_mass * suspension_size.powi(2);
+    // bump stops at 0.1 m of bump and rebound travel, much stiffer than the spring
+    let suspension_limit =
+        JointLimit::compliant(-0.1, 0.1, 10. * suspension_stiffness, suspension_damping);
 
     let suspension_names = ["fl", "fr", "rl", "rr"].map(|name| name.to_string());
     let suspension_locations = [
@@ -92,6 +266,7 @@ pub fn build_car() -> CarDefinition {
                 preload: suspension_preload,
                 moi: suspension_moi,
                 location: *location,
+                limit: Some(suspension_limit.clone()),
             }
         })
         .collect();
@@ -154,25 +329,24 @@ pub fn build_wheel() -> Wheel {
     }
 }
 
-pub fn car_startup_system(mut commands: Commands, asset_server: Res<AssetServer>, car: ResMut<CarDefinition>) {
+// the asset server is missing when running headless, then no models are loaded
+pub fn car_startup_system(mut commands: Commands, asset_server: Option<Res<AssetServer>>, car: ResMut<CarDefinition>) {
     //Motion here is for gravity   (9.81 m/s)  
     let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
     let base_id = commands.spawn((base, Base)).id();
 
     // Chassis
-    let chassis_ids = car
+    let chassis_id = car
         .chassis
-        .build(&mut commands, &asset_server, Color::rgb(0.9, 0.1, 0.2), base_id);
-    let chassis_id = chassis_ids[3]; // ids are not ordered by parent child order!!! "3" is rx, the last joint in the chain
+        .build(&mut commands, asset_server.as_deref(), Color::rgb(0.9, 0.1, 0.2), base_id);
+
+    // follows the position and yaw of the chassis, but not its roll and pitch
+    let follower_id = commands.spawn((ChassisFollower(chassis_id), SpatialBundle::default())).id();
 
     let camera_parent_list = vec![
-        chassis_ids[5], // follow x, y and z and yaw of chassis
-        // chassis_ids[0], // only follow x of chassis (why would you do that?)
-        chassis_ids[1], // follow x and y of chassis
-        chassis_ids[2], // follow x, y and z of chassis
-        chassis_ids[3], // follow all motion of chassis
-        base_id,        // stationary camera
-                        // chassis_ids[4],
+        follower_id, // follow x, y and z and yaw of chassis
+        chassis_id,  // follow all motion of chassis
+        base_id,     // stationary camera
     ];
 
     commands.insert_resource(CameraParentList {
@@ -195,7 +369,7 @@ pub fn car_startup_system(mut commands: Commands, asset_server: Res<AssetServer>
         let id_susp = susp.build(&mut commands, chassis_id, &susp.location);
         let _wheel_id = car.wheel.build(
             &mut commands,
-            &asset_server,
+            asset_server.as_deref(),
             ind,
             &susp.name,
             id_susp,
@@ -206,7 +380,25 @@ pub fn car_startup_system(mut commands: Commands, asset_server: Res<AssetServer>
     }
 }
 
-#[derive(Clone)]
+// A camera parent that follows the position and yaw of a chassis (the entity)
+#[derive(Component)]
+pub struct ChassisFollower(pub Entity);
+
+pub fn chassis_follower_system(
+    chassis_query: Query<&GlobalTransform>,
+    mut follower_query: Query<(&ChassisFollower, &mut Transform)>,
+) {
+    for (follower, mut transform) in follower_query.iter_mut() {
+        if let Ok(chassis) = chassis_query.get(follower.0) {
+            let (_, rotation, translation) = chassis.to_scale_rotation_translation();
+            let (yaw, _, _) = rotation.to_euler(EulerRot::ZYX);
+            transform.translation = translation;
+            transform.rotation = Quat::from_rotation_z(yaw);
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Chassis {
     pub mass: f64,
     pub cg_position: [f64; 3],
@@ -219,44 +411,10 @@ pub struct Chassis {
 }
 
 impl Chassis {
-    pub fn build(&self, commands: &mut Commands, asset_server: &Res<AssetServer>, color: Color, parent_id: Entity) -> Vec<Entity> {
-        // x degree of freedom (absolute coordinate system, not relative to car)
-        let mut px = Joint::px("chassis_px".to_string(), Inertia::zero(), Xform::identity());
-        px.q = self.initial_position[0];
-        let mut px_e = commands.spawn((px,));
-        px_e.set_parent(parent_id);
-        let px_id = px_e.id();
-
-        // y degree of freedom (absolute coordinate system, not relative to car)
-        let mut py = Joint::py("chassis_py".to_string(), Inertia::zero(), Xform::identity());
-        py.q = self.initial_position[1];
-        let mut py_e = commands.spawn((py,));
-        py_e.set_parent(px_id);
-        let py_id = py_e.id();
-
-        // z degree of freedom (always points "up", relative to absolute coordinate system)
-        let mut pz = Joint::pz("chassis_pz".to_string(), Inertia::zero(), Xform::identity());
-        pz.q = self.initial_position[2];
-        let mut pz_e = commands.spawn((pz,));
-        pz_e.set_parent(py_id);
-        let pz_id = pz_e.id();
-
-        // yaw degree of freedom (rotation around z axis)
-        let mut rz = Joint::rz("chassis_rz".to_string(), Inertia::zero(), Xform::identity());
-        rz.q = self.initial_orientation[2];
-        let mut rz_e = commands.spawn((rz,));
-        rz_e.set_parent(pz_id);
-        let rz_id = rz_e.id();
-
-        // pitch degree of freedom (rotation around y axis)
-        let mut ry = Joint::ry("chassis_ry".to_string(), Inertia::zero(), Xform::identity());
-        ry.q = self.initial_orientation[1];
-        let mut ry_e = commands.spawn((ry,));
-        ry_e.set_parent(rz_id);
-        let ry_id = ry_e.id();
-
-        // roll degree of freedom (rotation around x axis)
-        // this is the body of the car!
+    // The chassis is a floating joint named "chassis" (its state is recorded as `chassis.x`, ...,
+    // `chassis.vz`, see FloatingJoint). The initial orientation is roll, pitch and yaw (applied in
+    // the order yaw, pitch, roll).
+    pub fn build(&self, commands: &mut Commands, asset_server: Option<&AssetServer>, color: Color, parent_id: Entity) -> Entity {
         let mass = self.mass;
         let cg_position = self.cg_position;
         let moi = self.moi;
@@ -268,22 +426,24 @@ impl Chassis {
             Matrix::from_diagonal(&Vector::new(moi[0], moi[1], moi[2])),
         );
 
-        let mut rx = Joint::rx("chassis_rx".to_string(), inertia, Xform::identity());
-        rx.q = self.initial_orientation[0];
-        let mut rx_e = commands.spawn((rx,));
-        rx_e.set_parent(ry_id);
-        let rx_id = rx_e.id();
-        
-        //Insert the car chassis into the rx roll degree of freedom joint entity.
-        if let Some(_chassis_file) = &self.mesh_file {
+        let (joint, mut floating) = Joint::floating("chassis".to_string(), inertia, Xform::identity());
+        let [x, y, z] = self.initial_position;
+        floating.position = Vector::new(x, y, z);
+        let [roll, pitch, yaw] = self.initial_orientation;
+        floating.rotation = UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+        let mut chassis_e = commands.spawn((joint, floating));
+        chassis_e.set_parent(parent_id);
+
+        //Insert the car chassis into the chassis joint entity.
+        if let (Some(_chassis_file), Some(asset_server)) = (&self.mesh_file, asset_server) {
             println!("mesh");
-             rx_e.insert(SceneBundle {
+             chassis_e.insert(SceneBundle {
                 transform: (&TransformDef::from_position(position)).into(),
                 scene: asset_server.load("models/vehicle/chassis/car_chassis.glb#Scene0"),
                 ..default()
             });
         } else {
-            rx_e.insert(MeshDef {
+            chassis_e.insert(MeshDef {
                 mesh_type: MeshTypeDef::Box {
                     dimensions: [
                         dimensions[0] as f32,
@@ -296,13 +456,11 @@ impl Chassis {
             });
         };
 
-        let chassis_ids = vec![px_id, py_id, pz_id, rx_id, ry_id, rz_id];
-        // return id the last joint in the chain. It will be the parent of the suspension / wheels
-        chassis_ids
+        chassis_e.id()
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Suspension {
     pub name: String,
     pub mass: f64,
@@ -312,6 +470,7 @@ pub struct Suspension {
     pub preload: f64,
     pub moi: f64,
     pub location: [f64; 3],
+    pub limit: Option<JointLimit>, // travel limits (bump stops) of the suspension joint
 }
 
 impl Suspension {
@@ -368,12 +527,15 @@ impl Suspension {
             SuspensionComponent::new(self.stiffness, self.damping, self.preload),
         ));
         susp_e.set_parent(parent_id);
+        if let Some(limit) = &self.limit {
+            susp_e.insert(limit.clone());
+        }
 
         susp_e.id()
     }
 }
 
-#[derive(Resource, Clone)]
+#[derive(Resource, Clone, Serialize, Deserialize)]
 pub struct Wheel {
     pub mass: f64,
     pub radius: f64,
@@ -393,7 +555,7 @@ impl Wheel {
     pub fn build(
         &self,
         commands: &mut Commands,
-        asset_server: &Res<AssetServer>,
+        asset_server: Option<&AssetServer>,
         index: usize,
         corner_name: &String,
         parent_id: Entity,
@@ -413,31 +575,21 @@ impl Wheel {
         let mut ry = Joint::ry(name, inertia, Xform::identity());
         ry.qd = initial_speed;
 
-        let mut wheel_e;
+        let mut wheel_e = commands.spawn((ry,));
         //Check which side this wheel model should be displayed as depending on index number at setup (Left or Right)
-        if index == 1 || index == 3 {
-            wheel_e = commands.spawn((
-                ry,
-                //Assign the mesh of the wheel model
-                SceneBundle {
-                    transform: (&TransformDef::Identity).into(),
-                    scene: asset_server.load("models/vehicle/wheel/wheelR.glb#Scene0"),
-                    ..default()
-                }
-            ));
-        }
-        else {
-            wheel_e = commands.spawn((
-                ry,
-                //Assign the mesh of the wheel model
-                SceneBundle {
-                    transform: (&TransformDef::Identity).into(),
-                    scene: asset_server.load("models/vehicle/wheel/wheelL.glb#Scene0"),
-                    ..default()
-                }
-            ));
+        if let Some(asset_server) = asset_server {
+            let scene = if index == 1 || index == 3 {
+                "models/vehicle/wheel/wheelR.glb#Scene0"
+            } else {
+                "models/vehicle/wheel/wheelL.glb#Scene0"
+            };
+            //Assign the mesh of the wheel model
+            wheel_e.insert(SceneBundle {
+                transform: (&TransformDef::Identity).into(),
+                scene: asset_server.load(scene),
+                ..default()
+            });
         }
-        
 
         // add driven and braked components
         match driven_wheel {
@@ -480,7 +632,8 @@ impl Wheel {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Brake {
-    front_torque: f64,
-    rear_torque: f64,
+    pub front_torque: f64,
+    pub rear_torque: f64,
 }
@@ -1,6 +1,11 @@
 use bevy::prelude::*;
+use bevy_integrator::recorder::ChannelWriter;
+use rigid_body::joint::{FloatingJoint, Joint};
+use serde::{Deserialize, Serialize};
 
-#[derive(Resource, Default)]
+use crate::{build::CarDefinition, physics::DrivenWheelLookup};
+
+#[derive(Resource, Default, Serialize, Deserialize)]
 pub struct CarControl {
     pub throttle: f32,
     pub steering: f32,
@@ -102,3 +107,66 @@ pub fn user_control_system(
         }
     }
 }
+
+// Recorder source for the car control inputs
+pub fn record_car_control(world: &mut World, writer: &mut ChannelWriter) {
+    let control = world.resource::<CarControl>();
+    writer.write("control", "throttle", control.throttle as f64);
+    writer.write("control", "steering", control.steering as f64);
+    writer.write("control", "brake", control.brake as f64);
+}
+
+// Traction control, run as a sampled controller (see `setup::traction_control_setup`). The drive
+// torque is scaled down while the driven wheels spin faster than the ground speed. The torque scale
+// is the output, which is held between samples.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct TractionControl {
+    pub slip_limit: f64, // slip ratio of the fastest driven wheel at which the torque is reduced
+    pub gain: f64,       // reduction of the torque scale per unit of slip above the limit
+    pub torque_scale: f64,
+}
+
+impl Default for TractionControl {
+    fn default() -> Self {
+        TractionControl {
+            slip_limit: 0.1,
+            gain: 5.,
+            torque_scale: 1.,
+        }
+    }
+}
+
+pub fn traction_control_system(
+    bodies: Query<&FloatingJoint>,
+    driven_wheels: Query<&Joint, With<DrivenWheelLookup>>,
+    car: Res<CarDefinition>,
+    mut traction_control: ResMut<TractionControl>,
+) {
+    // the horizontal speed of the chassis
+    let ground_speed = bodies
+        .iter()
+        .find(|body| body.name == "chassis")
+        .map_or(0., |chassis| {
+            let velocity = chassis.rotation * chassis.v.v;
+            velocity.x.hypot(velocity.y)
+        });
+    let wheel_speed = driven_wheels
+        .iter()
+        .map(|joint| joint.qd.abs() * car.wheel.rolling_radius)
+        .fold(0., f64::max);
+
+    // the slip is relative to at least 1 m/s, so it stays bounded when starting from rest
+    let slip = (wheel_speed - ground_speed) / ground_speed.max(1.);
+    traction_control.torque_scale =
+        (1. - traction_control.gain * (slip - traction_control.slip_limit).max(0.)).clamp(0., 1.);
+}
+
+// Recorder source for the traction control output
+pub fn record_traction_control(world: &mut World, writer: &mut ChannelWriter) {
+    let traction_control = world.resource::<TractionControl>();
+    writer.write(
+        "traction_control",
+        "torque_scale",
+        traction_control.torque_scale,
+    );
+}
@@ -48,6 +48,19 @@ pub fn build_environment(
 
     commands.insert_resource(DirectionalLightShadowMap { size: 4 * 1024 });
 
+    let grid_terrain = build_terrain();
+    let empty_parent = commands.spawn(SpatialBundle::default()).id();
+
+    grid_terrain.build_meshes(&mut commands, &mut meshes, &mut materials, empty_parent);
+    commands.insert_resource(grid_terrain);
+}
+
+// terrain only, without meshes or lights (for headless simulations)
+pub fn terrain_startup_system(mut commands: Commands) {
+    commands.insert_resource(build_terrain());
+}
+
+pub fn build_terrain() -> GridTerrain {
     let size = 160.0; // must be the same for all grid elements
 
     let height = 2.;
@@ -67,9 +80,5 @@ pub fn build_environment(
     //elements.extend(step_elements);
     //elements.extend(perlin_elements);
 
-    let grid_terrain = GridTerrain::new(elements, [size, size]);
-    let empty_parent = commands.spawn(SpatialBundle::default()).id();
-
-    grid_terrain.build_meshes(&mut commands, &mut meshes, &mut materials, empty_parent);
-    commands.insert_resource(grid_terrain);
+    GridTerrain::new(elements, [size, size])
 }
@@ -30,6 +30,20 @@ impl Interpolator1D {
         let slope = (y1 - y0) / (x1 - x0);
         y0 + slope * (x - x0)
     }
+
+    pub fn x(&self) -> &[f64] {
+        &self.x
+    }
+
+    pub fn y(&self) -> &[f64] {
+        &self.y
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for y in self.y.iter_mut() {
+            *y *= factor;
+        }
+    }
 }
 
 fn bin_search(x: &Vec<f64>, target: f64) -> usize {
@@ -1,12 +1,14 @@
 use std::collections::HashMap;
 
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use rigid_body::joint::Joint;
 
 use crate::interpolate::Interpolator1D;
 
-use super::control::CarControl;
+use super::control::{CarControl, TractionControl};
 
 #[derive(Component)]
 pub struct SuspensionComponent {
@@ -32,14 +34,14 @@ pub fn suspension_system(mut joints: Query<(&mut Joint, &SuspensionComponent)>)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum SteeringType {
     None,
     Curvature(SteeringCurvature),
     Angle(Steering),
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Steering {
     pub max_angle: f64,
 }
@@ -56,7 +58,7 @@ pub fn steering_system(mut joints: Query<(&mut Joint, &Steering)>, control: Res<
     }
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct SteeringCurvature {
     pub x: f64,
     pub y: f64,
@@ -85,14 +87,14 @@ pub fn steering_curvature_system(
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum DriveType {
     None,
     DrivenWheel(DrivenWheel),
     DrivenWheelLookup(DrivenWheelLookup),
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct DrivenWheel {
     pub max_torque: f64,
     pub max_speed: f64,
@@ -124,7 +126,9 @@ pub fn driven_wheel_system(
     }
 }
 
-#[derive(Component, Clone)]
+// In files, the torque curve is written as its speeds and torques (see `TorqueCurve`)
+#[derive(Component, Clone, Serialize, Deserialize)]
+#[serde(try_from = "TorqueCurve", into = "TorqueCurve")]
 pub struct DrivenWheelLookup {
     pub name: String,
     pub torque_lookup: Interpolator1D,
@@ -163,15 +167,66 @@ impl DrivenWheelLookup {
         };
         limit_torque
     }
+
+    // scale the whole torque curve, e.g. to sweep the drive torque
+    pub fn scale_torque(&mut self, factor: f64) {
+        self.torque_lookup.scale(factor);
+        self.max_speed_power *= factor;
+    }
+}
+
+// The drive torque (Nm) at increasing wheel speeds (rad/s), as written in car definition files
+#[derive(Serialize, Deserialize)]
+pub struct TorqueCurve {
+    pub name: String,
+    pub speeds: Vec<f64>,
+    pub torques: Vec<f64>,
+}
+
+// a torque at every speed, and the speeds in increasing order
+pub fn check_torque_curve(speeds: &[f64], torques: &[f64]) -> Result<(), String> {
+    if speeds.is_empty() || speeds.len() != torques.len() {
+        return Err(format!(
+            "the torque curve has {} speeds and {} torques",
+            speeds.len(),
+            torques.len()
+        ));
+    }
+    if speeds.windows(2).any(|pair| pair[1] <= pair[0]) {
+        return Err("the torque curve speeds are not increasing".to_string());
+    }
+    Ok(())
 }
 
+impl TryFrom<TorqueCurve> for DrivenWheelLookup {
+    type Error = String;
+
+    fn try_from(curve: TorqueCurve) -> Result<Self, String> {
+        check_torque_curve(&curve.speeds, &curve.torques)?;
+        Ok(DrivenWheelLookup::new(curve.name, curve.speeds, curve.torques))
+    }
+}
+
+impl From<DrivenWheelLookup> for TorqueCurve {
+    fn from(driven_wheel: DrivenWheelLookup) -> Self {
+        TorqueCurve {
+            name: driven_wheel.name,
+            speeds: driven_wheel.torque_lookup.x().to_vec(),
+            torques: driven_wheel.torque_lookup.y().to_vec(),
+        }
+    }
+}
+
+// the drive torque is scaled by the traction control, if there is one
 pub fn driven_wheel_lookup_system(
     mut joints: Query<(&mut Joint, &mut DrivenWheelLookup)>,
     control: Res<CarControl>,
+    traction_control: Option<Res<TractionControl>>,
 ) {
+    let torque_scale = traction_control.map_or(1., |traction_control| traction_control.torque_scale);
     for (mut joint, mut driven_wheel) in joints.iter_mut() {
         let torque_limit = driven_wheel.limit_torque(joint.qd).abs();
-        let commanded_torque = control.throttle as f64 * torque_limit;
+        let commanded_torque = control.throttle as f64 * torque_scale * torque_limit;
         joint.tau += commanded_torque;
         driven_wheel
             .outputs
@@ -182,6 +237,31 @@ pub fn driven_wheel_lookup_system(
     }
 }
 
+// checkpoint of the driven wheel outputs, by wheel joint name
+pub fn save_driven_wheel_outputs(world: &mut World) -> Value {
+    let outputs: HashMap<String, HashMap<String, f64>> = world
+        .query::<(&Joint, &DrivenWheelLookup)>()
+        .iter(world)
+        .map(|(joint, driven_wheel)| (joint.name.clone(), driven_wheel.outputs.clone()))
+        .collect();
+    serde_json::to_value(outputs).unwrap()
+}
+
+pub fn restore_driven_wheel_outputs(world: &mut World, value: &Value) {
+    let Ok(mut outputs) = HashMap::<String, HashMap<String, f64>>::deserialize(value) else {
+        warn!("could not restore the driven wheel outputs");
+        return;
+    };
+    for (joint, mut driven_wheel) in world
+        .query::<(&Joint, &mut DrivenWheelLookup)>()
+        .iter_mut(world)
+    {
+        if let Some(wheel_outputs) = outputs.remove(&joint.name) {
+            driven_wheel.outputs = wheel_outputs;
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct BrakeWheel {
     pub max_torque: f64,
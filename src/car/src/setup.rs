@@ -1,15 +1,26 @@
 #![allow(dead_code)]
 
 use bevy::prelude::*;
-use bevy_integrator::{PhysicsSchedule, PhysicsSet};
+use bevy_integrator::{
+    checkpoint::CheckpointAppExt,
+    input::InputAppExt,
+    recorder::RecorderAppExt,
+    sampled::{SampledAppExt, SampledController},
+    trim::TrimSettings,
+    PhysicsSchedule, PhysicsSet, StepSchedule,
+};
 
 use crate::{
-    control::user_control_system,
+    build::chassis_follower_system,
+    control::{
+        record_car_control, record_traction_control, traction_control_system, user_control_system,
+        TractionControl,
+    },
     physics::{
-        brake_wheel_system, driven_wheel_lookup_system, steering_curvature_system, steering_system,
-        suspension_system,
+        brake_wheel_system, driven_wheel_lookup_system, restore_driven_wheel_outputs,
+        save_driven_wheel_outputs, steering_curvature_system, steering_system, suspension_system,
     },
-    tire::point_tire_system,
+    tire::{point_tire_system, restore_tire_filters, save_tire_filters, tire_filter_system},
 };
 
 use super::control::CarControl;
@@ -33,8 +44,70 @@ pub fn simulation_setup(app: &mut App) {
         )
             .in_set(PhysicsSet::Evaluate),
     )
+    .add_systems(StepSchedule, tire_filter_system)
     .add_systems(Update, (user_control_system,))
     .init_resource::<CarControl>();
+
+    // car state that is not part of the joint states
+    app.add_checkpoint_resource::<CarControl>("car_control")
+        .add_checkpoint("tire_filters", save_tire_filters, restore_tire_filters)
+        .add_checkpoint(
+            "driven_wheel_outputs",
+            save_driven_wheel_outputs,
+            restore_driven_wheel_outputs,
+        )
+        .add_recorder_source(record_car_control);
+
+    // control inputs, e.g. for linearization
+    app.add_input(
+        "control.throttle",
+        |world| world.resource::<CarControl>().throttle as f64,
+        |world, value| world.resource_mut::<CarControl>().throttle = value as f32,
+    )
+    .add_input(
+        "control.steering",
+        |world| world.resource::<CarControl>().steering as f64,
+        |world, value| world.resource_mut::<CarControl>().steering = value as f32,
+    )
+    .add_input(
+        "control.brake",
+        |world| world.resource::<CarControl>().brake as f64,
+        |world, value| world.resource_mut::<CarControl>().brake = value as f32,
+    );
+}
+
+// Traction control of the drive torque, sampled at 100 Hz like an ECU, with its output applied 4 ms
+// after each sample
+pub fn traction_control_setup(app: &mut App) {
+    app.init_resource::<TractionControl>()
+        .add_checkpoint_resource::<TractionControl>("traction_control")
+        .add_recorder_source(record_traction_control)
+        .add_input(
+            "traction_control.torque_scale",
+            |world| world.resource::<TractionControl>().torque_scale,
+            |world, value| world.resource_mut::<TractionControl>().torque_scale = value,
+        )
+        .add_sampled_controller(
+            SampledController::new("traction_control", 0.01)
+                .with_latency(0.004)
+                .with_outputs(&["traction_control.torque_scale"]),
+            traction_control_system,
+        );
+}
+
+// Trim to the static ride height. The height, roll and pitch of the chassis are searched (its
+// position and heading are held), and the wheels and steering are held, as they have no equilibrium
+// position of their own.
+pub fn trim_settings() -> TrimSettings {
+    TrimSettings::new(&[
+        "wheel_fl", "wheel_fr", "wheel_rl", "wheel_rr", "steer_fl", "steer_fr",
+    ])
+    // near level, the roll and pitch change qx and qy of the chassis quaternion
+    .with_pairs(&[
+        ("chassis.z", "chassis.vz"),
+        ("chassis.qx", "chassis.wx"),
+        ("chassis.qy", "chassis.wy"),
+    ])
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -52,5 +125,12 @@ pub fn camera_setup(app: &mut App) {
             camera_az_el::UpDirection::Z,
         ),
     )
-    .add_systems(Update, (camera_az_el::az_el_camera, camera_parent_system)); // setup the camera
+    .add_systems(
+        Update,
+        (
+            camera_az_el::az_el_camera,
+            chassis_follower_system,
+            camera_parent_system,
+        ),
+    ); // setup the camera
 }
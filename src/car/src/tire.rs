@@ -1,9 +1,15 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
+use bevy_integrator::{SimTime, StepDerivative};
 use grid_terrain::GridTerrain;
 use rigid_body::{
     joint::Joint,
+    kinematics::{world_velocity, world_xform},
     sva::{Force, Vector},
 };
+use serde::Deserialize;
+use serde_json::Value;
 
 #[derive(Component)]
 pub struct PointTire {
@@ -18,6 +24,7 @@ pub struct PointTire {
     low_speed: f64,
     filter_time: f64,
     my_filtered: f64,
+    my_unfiltered: f64, // from the latest evaluation, the input to the filter
     activation_length: f64,
 }
 
@@ -79,6 +86,7 @@ impl PointTire {
             low_speed,
             filter_time,
             my_filtered: 0.,
+            my_unfiltered: 0.,
             activation_length,
         }
     }
@@ -92,6 +100,40 @@ impl PointTire {
     }
 }
 
+// checkpoint of the tire filter states, by wheel joint name
+pub fn save_tire_filters(world: &mut World) -> Value {
+    let mut tires = world.query::<&PointTire>();
+    let mut joints = world.query::<&Joint>();
+    let filters: HashMap<String, f64> = tires
+        .iter(world)
+        .filter_map(|tire| {
+            let joint = joints.get(world, tire.joint_entity).ok()?;
+            Some((joint.name.clone(), tire.my_filtered))
+        })
+        .collect();
+    serde_json::to_value(filters).unwrap()
+}
+
+pub fn restore_tire_filters(world: &mut World, value: &Value) {
+    let Ok(filters) = HashMap::<String, f64>::deserialize(value) else {
+        warn!("could not restore the tire filters");
+        return;
+    };
+    let names: HashMap<Entity, String> = world
+        .query::<(Entity, &Joint)>()
+        .iter(world)
+        .map(|(entity, joint)| (entity, joint.name.clone()))
+        .collect();
+    for mut tire in world.query::<&mut PointTire>().iter_mut(world) {
+        if let Some(filtered) = names
+            .get(&tire.joint_entity)
+            .and_then(|name| filters.get(name))
+        {
+            tire.my_filtered = *filtered;
+        }
+    }
+}
+
 pub fn point_tire_system(
     mut tire_query: Query<&mut PointTire>,
     mut query_joints: Query<&mut Joint>,
@@ -103,10 +145,10 @@ pub fn point_tire_system(
             query_joints.get_many_mut([tire.joint_entity, tire.joint_parent])
         {
             let mut f_ext = Force::zero();
-            let x0i = joint.x.inverse(); // spatial transform from the wheel joint to absolute coordinates
-            let v0 = x0i * joint.v; // spatial velocity of the wheel joint in absolute coordinates
-            let xp0 = parent.x.inverse(); // spatial transform from the parent joint to absolute coordinates
-            let vp0 = xp0 * parent.v; // spatial velocity of the parent joint in absolute coordinates
+            let x0i = world_xform(&joint); // spatial transform from the wheel joint to absolute coordinates
+            let v0 = world_velocity(&joint); // spatial velocity of the wheel joint in absolute coordinates
+            let xp0 = world_xform(&parent); // spatial transform from the parent joint to absolute coordinates
+            let vp0 = world_velocity(&parent); // spatial velocity of the parent joint in absolute coordinates
             let center_abs = xp0.transform_point(Vector::zeros()); // center of the tire in absolute coordinates
             let lateral_abs = x0i * Vector::y(); // tire lateral direction in absolute coordinates
 
@@ -193,10 +235,10 @@ pub fn point_tire_system(
                 f_ext += Force::force_point(force, contact.position);
             }
 
-            // Y Moment Filter (otherwise the wheel oscillates, it is too stiff for the solver)
+            // Y Moment Filter (otherwise the wheel oscillates, it is too stiff for the solver). The
+            // filtered moment is updated once per step, by `tire_filter_system`.
             let mut f_ext_parent = parent.x * f_ext; // resolve the force about the axle
-            let weight = 0.5_f64.powf(1. / (tire.filter_time / (0.002 / 4.))); // hard coded time step
-            tire.my_filtered = tire.my_filtered * weight + f_ext_parent.m.y * (1. - weight);
+            tire.my_unfiltered = f_ext_parent.m.y;
             f_ext_parent.m.y = tire.my_filtered;
             f_ext = parent.x.inverse() * f_ext_parent;
 
@@ -205,3 +247,20 @@ pub fn point_tire_system(
         }
     }
 }
+
+// Update the Y moment filters with the moment at the end of the step. `filter_time` is the half-life
+// of the filter. The tire forces change with the filters, so the derivative at the end of the step
+// can't be reused for the next one.
+pub fn tire_filter_system(
+    mut tire_query: Query<&mut PointTire>,
+    time: Res<SimTime>,
+    derivative: Option<ResMut<StepDerivative>>,
+) {
+    if let Some(mut derivative) = derivative {
+        derivative.invalidate();
+    }
+    for mut tire in tire_query.iter_mut() {
+        let weight = 0.5_f64.powf(time.dt / tire.filter_time);
+        tire.my_filtered = tire.my_filtered * weight + tire.my_unfiltered * (1. - weight);
+    }
+}
@@ -0,0 +1,56 @@
+use car::{
+    build::{build_car, CarDefinition},
+    physics::{DriveType, DrivenWheelLookup},
+};
+
+fn to_json(car: &CarDefinition) -> serde_json::Value {
+    serde_json::to_value(car).unwrap()
+}
+
+fn assert_invalid(car: &CarDefinition, message: &str) {
+    let error = car.validate().unwrap_err();
+    assert!(error.contains(message), "{error}");
+}
+
+#[test]
+fn car_round_trip() {
+    let car = build_car();
+    car.validate().unwrap();
+    let path = std::env::temp_dir().join(format!("car_test_{}.json", std::process::id()));
+    car.save(&path).unwrap();
+    let loaded = CarDefinition::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(to_json(&loaded.unwrap()), to_json(&car));
+}
+
+#[test]
+fn invalid_cars() {
+    let mut car = build_car();
+    car.wheel.radius = 0.;
+    assert_invalid(&car, "wheel radius");
+
+    let mut car = build_car();
+    car.suspension[1].damping = -1.;
+    assert_invalid(&car, "suspension fr");
+
+    let mut car = build_car();
+    car.suspension[3].stiffness = f64::NAN;
+    assert_invalid(&car, "suspension rr: the values must be finite");
+
+    let mut car = build_car();
+    car.brake.rear_torque = -400.;
+    assert_invalid(&car, "brake");
+
+    // the rear corners first
+    let mut car = build_car();
+    car.suspension.rotate_left(2);
+    assert_invalid(&car, "front corners");
+
+    let mut car = build_car();
+    car.drives[2] = DriveType::DrivenWheelLookup(DrivenWheelLookup::new(
+        "rl".to_string(),
+        vec![0., 25., 50., 75.],
+        vec![1000., f64::INFINITY, 600., 250.],
+    ));
+    assert_invalid(&car, "drive of corner rl");
+}
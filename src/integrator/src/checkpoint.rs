@@ -0,0 +1,286 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs, io,
+    path::Path,
+};
+
+use crate::{PhysicsState, SimTime, StateVector, Stateful, StepDerivative};
+
+// A snapshot of the complete simulation state. Every registered part of the simulation is stored
+// under its own key, and entities are identified by name (not by Entity), so a checkpoint saved to
+// disk can be restored into a newly built simulation.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Checkpoint {
+    pub time: f64,
+    pub entries: BTreeMap<String, Value>,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+#[derive(Clone)]
+struct CheckpointEntry {
+    key: String,
+    save: fn(&mut World) -> Value,
+    restore: fn(&mut World, &Value),
+}
+
+// Everything that is saved in a checkpoint (in addition to the simulation time)
+#[derive(Resource, Clone, Default)]
+pub struct CheckpointRegistry {
+    entries: Vec<CheckpointEntry>,
+}
+
+impl CheckpointRegistry {
+    fn register(&mut self, entry: CheckpointEntry) {
+        self.entries.retain(|existing| existing.key != entry.key);
+        self.entries.push(entry);
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .map(|entry| entry.key.as_str())
+            .collect()
+    }
+}
+
+pub trait CheckpointAppExt {
+    // save and restore a resource with the simulation
+    fn add_checkpoint_resource<R: Resource + Serialize + DeserializeOwned>(
+        &mut self,
+        key: &str,
+    ) -> &mut Self;
+
+    // save and restore with custom functions, e.g. for state that is held in components
+    fn add_checkpoint(
+        &mut self,
+        key: &str,
+        save: fn(&mut World) -> Value,
+        restore: fn(&mut World, &Value),
+    ) -> &mut Self;
+}
+
+impl CheckpointAppExt for App {
+    fn add_checkpoint_resource<R: Resource + Serialize + DeserializeOwned>(
+        &mut self,
+        key: &str,
+    ) -> &mut Self {
+        self.add_checkpoint(key, save_resource::<R>, restore_resource::<R>)
+    }
+
+    fn add_checkpoint(
+        &mut self,
+        key: &str,
+        save: fn(&mut World) -> Value,
+        restore: fn(&mut World, &Value),
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(CheckpointRegistry::default)
+            .register(CheckpointEntry {
+                key: key.to_string(),
+                save,
+                restore,
+            });
+        self
+    }
+}
+
+pub fn save_checkpoint(world: &mut World) -> Checkpoint {
+    let registry = world
+        .get_resource::<CheckpointRegistry>()
+        .cloned()
+        .unwrap_or_default();
+
+    let mut checkpoint = Checkpoint {
+        time: world.resource::<SimTime>().time(),
+        entries: BTreeMap::new(),
+    };
+    for entry in registry.entries.iter() {
+        let value = (entry.save)(world);
+        checkpoint.entries.insert(entry.key.clone(), value);
+    }
+    checkpoint
+}
+
+pub fn restore_checkpoint(world: &mut World, checkpoint: &Checkpoint) {
+    let registry = world
+        .get_resource::<CheckpointRegistry>()
+        .cloned()
+        .unwrap_or_default();
+
+    // anything the derivative depends on may change
+    if let Some(mut derivative) = world.get_resource_mut::<StepDerivative>() {
+        derivative.invalidate();
+    }
+
+    // the step index is recalculated, so the checkpoint doesn't depend on the time step
+    let mut time = world.resource_mut::<SimTime>();
+    time.index = ((checkpoint.time - time.start_time) / time.dt)
+        .round()
+        .max(0.) as usize;
+
+    for entry in registry.entries.iter() {
+        match checkpoint.entries.get(&entry.key) {
+            Some(value) => (entry.restore)(world, value),
+            None => warn!("checkpoint has no entry for \"{}\"", entry.key),
+        }
+    }
+    for key in checkpoint.entries.keys() {
+        if !registry.entries.iter().any(|entry| &entry.key == key) {
+            warn!("checkpoint entry \"{key}\" is not registered, and was not restored");
+        }
+    }
+}
+
+fn save_resource<R: Resource + Serialize>(world: &mut World) -> Value {
+    serde_json::to_value(world.resource::<R>()).unwrap()
+}
+
+fn restore_resource<R: Resource + DeserializeOwned>(world: &mut World, value: &Value) {
+    match R::deserialize(value) {
+        Ok(resource) => world.insert_resource(resource),
+        Err(error) => warn!("could not restore {}: {error}", std::any::type_name::<R>()),
+    }
+}
+
+// states are stored by the name of the Stateful component
+pub(crate) fn save_physics_state<T: Component + Stateful>(world: &mut World) -> Value {
+    let mut query = world.query::<&T>();
+    let physics_state = world.resource::<PhysicsState<T>>();
+
+    let mut states = BTreeMap::new();
+    for (slot, entity) in physics_state.entities().iter().enumerate() {
+        if let Ok(component) = query.get(world, *entity) {
+            let values = physics_state.states[PhysicsState::<T>::range(slot)].to_vec();
+            states.insert(component.get_name(), values);
+        }
+    }
+    serde_json::to_value(states).unwrap()
+}
+
+pub(crate) fn restore_physics_state<T: Component + Stateful>(world: &mut World, value: &Value) {
+    let states = match BTreeMap::<String, Vec<f64>>::deserialize(value) {
+        Ok(states) => states,
+        Err(error) => {
+            warn!("could not restore {}: {error}", T::CHECKPOINT_KEY);
+            return;
+        }
+    };
+
+    let mut query = world.query::<&mut T>();
+    world.resource_scope(
+        |world: &mut World, mut physics_state: Mut<PhysicsState<T>>| {
+            for slot in 0..physics_state.entities().len() {
+                let entity = physics_state.entities()[slot];
+                let Ok(mut component) = query.get_mut(world, entity) else {
+                    continue;
+                };
+                let name = component.get_name();
+                match states.get(&name) {
+                    Some(values) if values.len() == <T::State as StateVector>::SIZE => {
+                        // update the component too, so it is consistent before the next step
+                        let state = T::State::from_values(values);
+                        component.set_state(&state);
+                        let range = PhysicsState::<T>::range(slot);
+                        state.to_values(&mut physics_state.states[range]);
+                    }
+                    _ => warn!("checkpoint has no valid state for \"{name}\""),
+                }
+            }
+        },
+    );
+}
+
+// Checkpoints kept in memory, so that the simulation can be rewound
+#[derive(Resource, Clone, Debug)]
+pub struct CheckpointHistory {
+    pub interval: f64,    // simulation time between checkpoints
+    pub duration: f64,    // how much simulation time is kept
+    pub rewind_time: f64, // how far `rewind` goes back
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+impl CheckpointHistory {
+    pub fn new(interval: f64, duration: f64, rewind_time: f64) -> Self {
+        CheckpointHistory {
+            interval,
+            duration,
+            rewind_time,
+            checkpoints: VecDeque::new(),
+        }
+    }
+
+    pub fn checkpoints(&self) -> &VecDeque<Checkpoint> {
+        &self.checkpoints
+    }
+
+    fn is_due(&self, time: f64) -> bool {
+        match self.checkpoints.back() {
+            Some(last) => time >= last.time + self.interval - 1e-9,
+            None => true,
+        }
+    }
+
+    fn push(&mut self, checkpoint: Checkpoint) {
+        let oldest = checkpoint.time - self.duration;
+        self.checkpoints.push_back(checkpoint);
+        while self
+            .checkpoints
+            .front()
+            .is_some_and(|front| front.time < oldest)
+        {
+            self.checkpoints.pop_front();
+        }
+    }
+
+    // the latest checkpoint at or before the given time (or the oldest one), dropping everything
+    // after it
+    fn rewind_to(&mut self, time: f64) -> Option<Checkpoint> {
+        while self.checkpoints.len() > 1 && self.checkpoints.back().unwrap().time > time {
+            self.checkpoints.pop_back();
+        }
+        self.checkpoints.back().cloned()
+    }
+}
+
+impl Default for CheckpointHistory {
+    fn default() -> Self {
+        CheckpointHistory::new(0.1, 30., 5.)
+    }
+}
+
+// add a checkpoint to the history every `interval` of simulation time
+pub fn record_checkpoint_system(world: &mut World) {
+    let time = world.resource::<SimTime>().time();
+    let due = world
+        .get_resource::<CheckpointHistory>()
+        .is_some_and(|history| history.is_due(time));
+    if due {
+        let checkpoint = save_checkpoint(world);
+        world.resource_mut::<CheckpointHistory>().push(checkpoint);
+    }
+}
+
+// Restore the simulation to `rewind_time` before the current time. Returns the time rewound to.
+pub fn rewind(world: &mut World) -> Option<f64> {
+    let time = world.resource::<SimTime>().time();
+    let mut history = world.get_resource_mut::<CheckpointHistory>()?;
+    let target = time - history.rewind_time;
+    let checkpoint = history.rewind_to(target)?;
+    restore_checkpoint(world, &checkpoint);
+    Some(checkpoint.time)
+}
@@ -0,0 +1,303 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::{
+    checkpoint::CheckpointAppExt,
+    solvers::{evaluate_state, step, SolverError, SolverResources, SolverWorkspace},
+    ExitEvent, Solver, StateEvaluator,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossingDirection {
+    Rising,  // from negative to zero or positive
+    Falling, // from positive to zero or negative
+    Either,
+}
+
+impl CrossingDirection {
+    fn crossed(&self, start: f64, end: f64) -> bool {
+        let rising = start < 0. && end >= 0.;
+        let falling = start > 0. && end <= 0.;
+        match self {
+            CrossingDirection::Rising => rising,
+            CrossingDirection::Falling => falling,
+            CrossingDirection::Either => rising || falling,
+        }
+    }
+}
+
+// What happens at a zero crossing. Every crossing is sent as a ZeroCrossingEvent and kept in
+// `ZeroCrossings::occurred`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossingAction {
+    Event, // nothing else
+    Log,
+    // log, and stop the simulation at the crossing (the integration stops and an ExitEvent is sent)
+    Stop,
+}
+
+pub type EventFunction = Arc<dyn Fn(&mut World) -> f64 + Send + Sync>;
+
+// An event function of the simulation state, e.g. the chassis roll minus 60 degrees (a closure, so
+// it can capture its threshold). It is evaluated on the world after each step, and a sign change
+// (in `direction`) is a crossing.
+#[derive(Clone)]
+pub struct ZeroCrossing {
+    pub name: String,
+    pub function: EventFunction,
+    pub direction: CrossingDirection,
+    pub action: CrossingAction,
+}
+
+#[derive(Event, Clone, Debug, Serialize, Deserialize)]
+pub struct ZeroCrossingEvent {
+    pub name: String,
+    pub time: f64,
+    pub rising: bool,
+}
+
+// The event functions, and the crossings that have occurred. A crossing within a step is located by
+// bisection: the step is taken again from its start state with shorter time steps, until the
+// crossing time is known within `tolerance` times the time step (about 14 re-steps per crossing
+// with the default of 1e-4). The solver statistics are those of the steps that were kept.
+#[derive(Resource, Clone)]
+pub struct ZeroCrossings {
+    pub tolerance: f64, // fraction of the time step
+    functions: Vec<ZeroCrossing>,
+    occurred: Vec<ZeroCrossingEvent>,
+    stop_time: Option<f64>,
+    previous: Option<(usize, Vec<f64>)>, // step index, and the function values at that step
+    start: Vec<f64>,                     // state at the start of the current step
+    solver: SolverResources, // at the start of the step, so the bisection doesn't change them
+}
+
+impl Default for ZeroCrossings {
+    fn default() -> Self {
+        ZeroCrossings {
+            tolerance: 1e-4,
+            functions: Vec::new(),
+            occurred: Vec::new(),
+            stop_time: None,
+            previous: None,
+            start: Vec::new(),
+            solver: SolverResources::default(),
+        }
+    }
+}
+
+impl ZeroCrossings {
+    pub fn functions(&self) -> &[ZeroCrossing] {
+        &self.functions
+    }
+
+    pub fn occurred(&self) -> &[ZeroCrossingEvent] {
+        &self.occurred
+    }
+
+    // the time of the crossing that stopped the simulation
+    pub fn stop_time(&self) -> Option<f64> {
+        self.stop_time
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop_time.is_some()
+    }
+
+    // Called before a step. The function values at the start of the step are the values after the
+    // previous step, unless the state has been changed since (e.g. by a checkpoint restore).
+    pub(crate) fn begin_step(
+        &mut self,
+        physics: &mut StateEvaluator,
+        ws: &mut SolverWorkspace,
+        index: usize,
+        time: f64,
+    ) {
+        if !matches!(&self.previous, Some((previous, _)) if *previous == index) {
+            evaluate_state(physics, ws, time);
+            ws.start_derivative = true;
+            let values = self.evaluate(physics.world);
+            self.previous = Some((index, values));
+        }
+        self.start.clone_from(&ws.state);
+        self.solver = SolverResources::save(physics.world);
+    }
+
+    // Called after a step, with the world at the new state. If any function crossed zero, the
+    // crossing times are located, and the world is left at the end of the step (or at the crossing
+    // that stopped the simulation).
+    pub(crate) fn end_step(
+        &mut self,
+        physics: &mut StateEvaluator,
+        ws: &mut SolverWorkspace,
+        solver: Solver,
+        index: usize,
+        time: f64,
+        dt: f64,
+    ) -> Result<(), SolverError> {
+        let Some((_, start_values)) = self.previous.take() else {
+            return Ok(());
+        };
+        let mut values = self.evaluate(physics.world);
+        let crossed: Vec<usize> = (0..self.functions.len())
+            .filter(|i| {
+                self.functions[*i]
+                    .direction
+                    .crossed(start_values[*i], values[*i])
+            })
+            .collect();
+
+        if !crossed.is_empty() {
+            // the end of the step, in case it is kept
+            let end_state = ws.state.clone();
+            let end_solver = SolverResources::save(physics.world);
+
+            // the fraction of the step at which each function crossed
+            let mut crossings: Vec<(usize, f64)> = Vec::new();
+            for i in crossed {
+                let function = &self.functions[i];
+                let (mut before, mut after) = (0., 1.);
+                while after - before > self.tolerance {
+                    let middle = 0.5 * (before + after);
+                    self.restep(physics, ws, solver, time, middle * dt)?;
+                    let value = (function.function)(physics.world);
+                    if function.direction.crossed(start_values[i], value) {
+                        after = middle;
+                    } else {
+                        before = middle;
+                    }
+                }
+                crossings.push((i, after));
+            }
+            crossings.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+            // crossings after a stop don't happen
+            let stop = crossings
+                .iter()
+                .position(|(i, _)| self.functions[*i].action == CrossingAction::Stop);
+            if let Some(stop) = stop {
+                crossings.truncate(stop + 1);
+            }
+            match stop {
+                Some(stop) => self.restep(physics, ws, solver, time, crossings[stop].1 * dt)?,
+                None => {
+                    ws.state.copy_from_slice(&end_state);
+                    end_solver.restore(physics.world);
+                    evaluate_state(physics, ws, time + dt);
+                }
+            }
+            values = self.evaluate(physics.world);
+
+            for (i, fraction) in crossings {
+                let function = &self.functions[i];
+                let event = ZeroCrossingEvent {
+                    name: function.name.clone(),
+                    time: time + fraction * dt,
+                    rising: start_values[i] < 0.,
+                };
+                if function.action != CrossingAction::Event {
+                    info!("{} crossed zero at t = {:.6} s", event.name, event.time);
+                }
+                if function.action == CrossingAction::Stop {
+                    info!("simulation stopped at t = {:.6} s", event.time);
+                    self.stop_time = Some(event.time);
+                    physics.world.send_event(ExitEvent);
+                }
+                physics.world.send_event(event.clone());
+                self.occurred.push(event);
+            }
+        }
+        self.previous = Some((index, values));
+        Ok(())
+    }
+
+    // take the step again from its start state, with a (shorter) time step
+    fn restep(
+        &self,
+        physics: &mut StateEvaluator,
+        ws: &mut SolverWorkspace,
+        solver: Solver,
+        time: f64,
+        dt: f64,
+    ) -> Result<(), SolverError> {
+        ws.state.copy_from_slice(&self.start);
+        self.solver.restore(physics.world);
+        step(solver, physics, ws, time, dt)
+    }
+
+    fn evaluate(&self, world: &mut World) -> Vec<f64> {
+        self.functions
+            .iter()
+            .map(|function| (function.function)(world))
+            .collect()
+    }
+}
+
+pub trait ZeroCrossingAppExt {
+    fn add_zero_crossing(
+        &mut self,
+        name: &str,
+        function: impl Fn(&mut World) -> f64 + Send + Sync + 'static,
+        direction: CrossingDirection,
+        action: CrossingAction,
+    ) -> &mut Self;
+}
+
+impl ZeroCrossingAppExt for App {
+    fn add_zero_crossing(
+        &mut self,
+        name: &str,
+        function: impl Fn(&mut World) -> f64 + Send + Sync + 'static,
+        direction: CrossingDirection,
+        action: CrossingAction,
+    ) -> &mut Self {
+        let mut crossings = self
+            .world
+            .get_resource_or_insert_with(ZeroCrossings::default);
+        crossings.functions.retain(|existing| existing.name != name);
+        crossings.functions.push(ZeroCrossing {
+            name: name.to_string(),
+            function: Arc::new(function),
+            direction,
+            action,
+        });
+        crossings.previous = None;
+        self.add_event::<ZeroCrossingEvent>().add_checkpoint(
+            "zero_crossings",
+            save_zero_crossings,
+            restore_zero_crossings,
+        )
+    }
+}
+
+// The crossings that have occurred, as saved in checkpoints
+#[derive(Serialize, Deserialize)]
+struct CrossingsCheckpoint {
+    occurred: Vec<ZeroCrossingEvent>,
+    stop_time: Option<f64>,
+}
+
+fn save_zero_crossings(world: &mut World) -> Value {
+    let crossings = world.resource::<ZeroCrossings>();
+    serde_json::to_value(CrossingsCheckpoint {
+        occurred: crossings.occurred.clone(),
+        stop_time: crossings.stop_time,
+    })
+    .unwrap()
+}
+
+// the function values at the start of the next step are evaluated again, as the state has changed
+fn restore_zero_crossings(world: &mut World, value: &Value) {
+    let checkpoint = match CrossingsCheckpoint::deserialize(value) {
+        Ok(checkpoint) => checkpoint,
+        Err(error) => {
+            warn!("could not restore the zero crossings: {error}");
+            return;
+        }
+    };
+    let mut crossings = world.resource_mut::<ZeroCrossings>();
+    crossings.occurred = checkpoint.occurred;
+    crossings.stop_time = checkpoint.stop_time;
+    crossings.previous = None;
+}
@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+
+// A named scalar input to the simulation (e.g. a control input), accessed through its get and set
+// functions.
+#[derive(Clone)]
+pub struct InputSignal {
+    pub name: String,
+    pub get: fn(&World) -> f64,
+    pub set: fn(&mut World, f64),
+}
+
+// The inputs that can be perturbed or driven from outside the simulation (e.g. by the linearization)
+#[derive(Resource, Clone, Default)]
+pub struct InputRegistry {
+    inputs: Vec<InputSignal>,
+}
+
+impl InputRegistry {
+    fn register(&mut self, input: InputSignal) {
+        self.inputs.retain(|existing| existing.name != input.name);
+        self.inputs.push(input);
+    }
+
+    pub fn inputs(&self) -> &[InputSignal] {
+        &self.inputs
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InputSignal> {
+        self.inputs.iter().find(|input| input.name == name)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.inputs.iter().map(|input| input.name.clone()).collect()
+    }
+}
+
+pub trait InputAppExt {
+    fn add_input(
+        &mut self,
+        name: &str,
+        get: fn(&World) -> f64,
+        set: fn(&mut World, f64),
+    ) -> &mut Self;
+}
+
+impl InputAppExt for App {
+    fn add_input(
+        &mut self,
+        name: &str,
+        get: fn(&World) -> f64,
+        set: fn(&mut World, f64),
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(InputRegistry::default)
+            .register(InputSignal {
+                name: name.to_string(),
+                get,
+                set,
+            });
+        self
+    }
+}
@@ -1,11 +1,22 @@
 // pub mod integrator;
-// pub mod recorder;
+pub mod checkpoint;
+pub mod events;
+pub mod input;
+pub mod linearize;
+pub mod recorder;
+pub mod sampled;
+mod solvers;
+pub mod trim;
 
 use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
-use std::{
-    collections::HashMap,
-    ops::{Add, Mul},
+use std::{any::TypeId, collections::HashMap, marker::PhantomData};
+
+use checkpoint::{restore_physics_state, save_physics_state, CheckpointAppExt};
+use events::ZeroCrossings;
+use solvers::{
+    evaluate_state, restore_solver_state, save_solver_state, step, SolverWorkspace, StateLayout,
 };
+pub use solvers::{AdaptiveStep, ImplicitSettings, Solver, SolverError, StepDerivative};
 
 #[derive(Event)]
 pub struct ExitEvent;
@@ -14,6 +25,13 @@ pub struct ExitEvent;
 #[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
 pub struct PhysicsSchedule;
 
+// Runs once after each step (of SimTime::dt), with the final state distributed to the components
+// and evaluated once more, so everything in the world is consistent with it. Unlike the
+// PhysicsSchedule, which runs on every solver stage, this is the place for side effects: logging,
+// discrete filters, event detection and sampled controllers.
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct StepSchedule;
+
 // Define physics system sets, which are used to group systems together, and define the order in which they are run
 #[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
 pub enum PhysicsSet {
@@ -30,7 +48,10 @@ enum SolverSet {
     Post,
 }
 
-pub struct StateMap<T: Stateful>(pub HashMap<Entity, T::State>);
+// The startup systems that set up the state storage (in PostStartup). Systems that read or modify the
+// initial state, such as the trim, run after this set.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub struct InitializeStateSet;
 
 #[derive(Resource, Clone)]
 pub struct SimTime {
@@ -70,110 +91,194 @@ impl SimTime {
     }
 }
 
-// wrapper over HashMap<Entity, T::State> to implement Add and Mul
-impl<T: Stateful> StateMap<T> {
-    pub fn new() -> Self {
-        StateMap(HashMap::new())
-    }
-    pub fn get(&self, entity: &Entity) -> Option<&T::State> {
-        self.0.get(entity)
-    }
+// The time of the state currently being evaluated. SimTime only changes once per step, so systems in
+// the PhysicsSchedule should use this to evaluate time varying inputs (the solver stages of a step
+// are evaluated at different times).
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct StageTime {
+    pub time: f64,
+    // index of the evaluation within the current step (0 is the start of the step, which is skipped
+    // when the derivative at the end of the last step is reused, see StepDerivative)
+    pub stage: usize,
+}
 
-    pub fn insert(&mut self, entity: Entity, state: T::State) {
-        self.0.insert(entity, state);
-    }
+// Runs the physics for a given state. Holds the world and the registered Stateful types for the
+// duration of a step, so that the solvers don't need to look them up for each stage.
+pub(crate) struct StateEvaluator<'a> {
+    pub(crate) world: &'a mut World,
+    registry: &'a StatefulRegistry,
+    stage: usize,
 }
 
-impl<T: Stateful> Clone for StateMap<T> {
-    fn clone(&self) -> Self {
-        StateMap(self.0.clone())
+impl StateEvaluator<'_> {
+    // Assign the state, run the physics, and write the state derivative. The state is the combined
+    // state of all registered Stateful types.
+    pub(crate) fn evaluate(&mut self, state: &[f64], t: f64, dstate: &mut [f64]) {
+        // assign the state and time
+        self.registry.write_states(self.world, state);
+        self.world.insert_resource(StageTime {
+            time: t,
+            stage: self.stage,
+        });
+        self.stage += 1;
+
+        // run the physics
+        self.world.run_schedule(PhysicsSchedule);
+
+        // collect the state derivative
+        self.registry.read_dstates(self.world, dstate);
     }
 }
 
-impl<T: Stateful> Mul<f64> for &StateMap<T> {
-    type Output = StateMap<T>;
-
-    fn mul(self, rhs: f64) -> Self::Output {
-        let mut result = HashMap::new();
-        for (entity, state) in self.0.iter() {
-            result.insert(*entity, state.clone() * rhs);
-        }
-        StateMap(result)
+// Take one step with the selected solver. All registered Stateful types are integrated together.
+pub fn integrator_schedule(world: &mut World) {
+    // nothing is integrated after a zero crossing stopped the simulation, or the solver failed
+    if world
+        .get_resource::<ZeroCrossings>()
+        .is_some_and(|crossings| crossings.is_stopped())
+        || world.contains_resource::<SolverFailure>()
+    {
+        return;
     }
-}
 
-impl<T: Stateful> Add for &StateMap<T> {
-    type Output = StateMap<T>;
+    // get time and increment. The step size comes from SimTime (it matches the fixed time step when
+    // running in real time, and is also available when stepping headless). The solvers step from the
+    // time at the start of the step.
+    let mut time_resource = world.get_resource_mut::<SimTime>().unwrap();
+    let time_step = time_resource.dt;
+    let time = time_resource.time();
+    let index = time_resource.index;
+    time_resource.increment();
+    let end_time = time_resource.time();
 
-    fn add(self, rhs: Self) -> Self::Output {
-        let mut result = HashMap::new();
-        for (entity, state) in self.0.iter() {
-            result.insert(*entity, state.clone() + rhs.0.get(entity).unwrap().clone());
-        }
-        StateMap(result)
+    // get Solver resource from world
+    let solver = *world.get_resource::<Solver>().unwrap();
+
+    // the event functions are checked on every step
+    let mut crossings = world.remove_resource::<ZeroCrossings>();
+
+    world.init_resource::<SolverWorkspace>();
+    world.init_resource::<StepDerivative>();
+    let result = world.resource_scope(|world: &mut World, registry: Mut<StatefulRegistry>| {
+        world.resource_scope(|world: &mut World, mut workspace: Mut<SolverWorkspace>| {
+            world.resource_scope(|world: &mut World, mut derivative: Mut<StepDerivative>| {
+                let ws = workspace.as_mut();
+                ws.read_states(world, &registry);
+                derivative.load(world, ws, time);
+                let physics = &mut StateEvaluator {
+                    world,
+                    registry: &registry,
+                    // the first stage is skipped when its derivative is reused
+                    stage: ws.start_derivative as usize,
+                };
+                if let Some(crossings) = crossings.as_mut() {
+                    crossings.begin_step(physics, ws, index, time);
+                }
+                step(solver, physics, ws, time, time_step)?;
+                if let Some(crossings) = crossings.as_mut() {
+                    crossings.end_step(physics, ws, solver, index + 1, time, time_step)?;
+                }
+                // a stopped simulation ends at the crossing, not at the end of the step
+                if crossings
+                    .as_ref()
+                    .is_some_and(|crossings| crossings.is_stopped())
+                {
+                    derivative.invalidate();
+                } else {
+                    derivative.store(physics.world, ws, end_time);
+                }
+                Ok(())
+            })
+        })
+    });
+
+    if let Some(crossings) = crossings {
+        world.insert_resource(crossings);
     }
+    if let Err(error) = result {
+        error!("the simulation stopped: {error}");
+        world.insert_resource(SolverFailure(error));
+        world.send_event(ExitEvent);
+        return;
+    }
+    world.try_run_schedule(StepSchedule).ok();
 }
 
-fn evaluate_state<T: Stateful>(world: &mut World, state: &StateMap<T>, _t: f64) -> StateMap<T> {
-    // assign the state
-    world.resource_scope(
-        |_world: &mut World, mut physics_state: Mut<PhysicsState<T>>| {
-            physics_state.states = state.clone();
-        },
-    );
-
-    // run the physics
-    world.run_schedule(PhysicsSchedule);
-
-    // return the state derivative
-    let mut dstates = StateMap(HashMap::new());
-    world.resource_scope(|_world: &mut World, physics_state: Mut<PhysicsState<T>>| {
-        dstates = physics_state.dstates.clone();
+// Inserted when a step fails. The integration stops, and an ExitEvent is sent.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SolverFailure(pub SolverError);
+
+// Evaluate the initial state, so everything in the world is consistent with it before the first step
+// (like after every step), e.g. for the first sample of the recorder. Runs in PostStartup, after
+// the state is initialized and trimmed.
+pub fn initial_evaluation_system(world: &mut World) {
+    if !world.contains_resource::<StatefulRegistry>() {
+        return;
+    }
+    let time = world.resource::<SimTime>().time();
+    world.init_resource::<SolverWorkspace>();
+    world.resource_scope(|world: &mut World, registry: Mut<StatefulRegistry>| {
+        world.resource_scope(|world: &mut World, mut workspace: Mut<SolverWorkspace>| {
+            let ws = workspace.as_mut();
+            ws.read_states(world, &registry);
+            let physics = &mut StateEvaluator {
+                world,
+                registry: &registry,
+                stage: 0,
+            };
+            evaluate_state(physics, ws, time);
+            // the first step starts from this derivative
+            let mut derivative = physics
+                .world
+                .remove_resource::<StepDerivative>()
+                .unwrap_or_default();
+            derivative.store(physics.world, ws, time);
+            physics.world.insert_resource(derivative);
+        });
     });
-    dstates
 }
 
-pub fn integrator_schedule<T: Stateful>(world: &mut World) {
-    // get the initial state
-    let state_0 = world
-        .get_resource::<PhysicsState<T>>()
-        .unwrap()
-        .states
-        .clone();
-
-    // get step size
-    let time_step = world
-        .get_resource::<Time<Fixed>>()
-        .unwrap()
-        .delta()
-        .as_secs_f64();
-
-    // get time and increment
-    let mut time_resource = world.get_resource_mut::<SimTime>().unwrap();
-    time_resource.increment();
-    let time = time_resource.time();
-
-    // get Solver resource from world
-    let solver = world.get_resource::<Solver>().unwrap();
+// Conversion between a state and a fixed number of named scalar values. Each entity's state occupies
+// a slot of SIZE values in the dense state buffer of its PhysicsState, and the solvers only work on
+// these values. The state derivative uses the same representation.
+pub trait StateVector: Sized {
+    const SIZE: usize;
+    // What each value is, used by the symplectic solvers. The k-th position is paired with the k-th
+    // velocity (the derivative of the position). Empty if the state has no such structure.
+    const KINDS: &'static [StateKind] = &[];
+    // names of the values, e.g. for the recorder and linearization (the index is used if empty)
+    const NAMES: &'static [&'static str] = &[];
+    fn to_values(&self, values: &mut [f64]);
+    fn from_values(values: &[f64]) -> Self;
+
+    // Advance the values of one state by `scale` times a state derivative (values += scale * dvalues
+    // by default). States on a manifold override this to stay on it, e.g. a unit quaternion is
+    // normalized after the increment is added (a projection back onto the unit sphere, which keeps
+    // the order of accuracy of the solver).
+    fn increment(values: &mut [f64], scale: f64, dvalues: &[f64]) {
+        for (value, dvalue) in values.iter_mut().zip(dvalues.iter()) {
+            *value += scale * dvalue;
+        }
+    }
 
-    let state = match solver {
-        Solver::Euler => euler::<T>(world, &state_0, time, time_step),
-        Solver::Heun => heun::<T>(world, &state_0, time, time_step),
-        Solver::Midpoint => midpoint::<T>(world, &state_0, time, time_step),
-        Solver::RK4 => rk4::<T>(world, &state_0, time, time_step),
-    };
+    // index of a named value
+    fn index(name: &str) -> Option<usize> {
+        Self::NAMES.iter().position(|field| *field == name)
+    }
+}
 
-    let mut physics_state = world.get_resource_mut::<PhysicsState<T>>().unwrap();
-    physics_state.states = state;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateKind {
+    Position,
+    Velocity,
+    Other,
 }
 
 pub trait Stateful: std::fmt::Debug + 'static {
-    type State: Add<Output = Self::State>
-        + Mul<f64, Output = Self::State>
-        + Clone
-        + Sync
-        + Send
-        + Into<f64>;
+    type State: Clone + Sync + Send + StateVector;
+    // The key of the states of this type in checkpoints. It is part of the checkpoint file format, so
+    // it should not change (unlike the type name, which depends on the module path and compiler).
+    const CHECKPOINT_KEY: &'static str;
 
     fn get_state(&self) -> Self::State;
     fn set_state(&mut self, state: &Self::State);
@@ -183,31 +288,278 @@ pub trait Stateful: std::fmt::Debug + 'static {
     fn get_name(&self) -> String;
 }
 
+// Dense storage of the states (and state derivatives) of all entities with the Stateful component T.
+// Each entity is assigned a slot when the state is initialized, and keeps it for the whole
+// simulation, so the solvers can work directly on the contiguous buffers.
 #[derive(Resource)]
 pub struct PhysicsState<T: Stateful> {
-    pub states: StateMap<T>,
-    pub dstates: StateMap<T>,
+    pub states: Vec<f64>,
+    pub dstates: Vec<f64>,
+    entities: Vec<Entity>,
+    slots: HashMap<Entity, usize>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Stateful> PhysicsState<T> {
+    pub fn new(entities: Vec<Entity>) -> Self {
+        let len = entities.len() * <T::State as StateVector>::SIZE;
+        let slots = entities
+            .iter()
+            .enumerate()
+            .map(|(slot, entity)| (*entity, slot))
+            .collect();
+        PhysicsState {
+            states: vec![0.; len],
+            dstates: vec![0.; len],
+            entities,
+            slots,
+            marker: PhantomData,
+        }
+    }
+
+    // entities in slot order
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn slot(&self, entity: &Entity) -> Option<usize> {
+        self.slots.get(entity).copied()
+    }
+
+    fn range(slot: usize) -> std::ops::Range<usize> {
+        let size = <T::State as StateVector>::SIZE;
+        slot * size..(slot + 1) * size
+    }
+
+    pub fn get_state(&self, entity: &Entity) -> Option<T::State> {
+        let slot = self.slot(entity)?;
+        Some(T::State::from_values(&self.states[Self::range(slot)]))
+    }
+
+    pub fn get_dstate(&self, entity: &Entity) -> Option<T::State> {
+        let slot = self.slot(entity)?;
+        Some(T::State::from_values(&self.dstates[Self::range(slot)]))
+    }
+
+    pub fn set_state(&mut self, entity: &Entity, state: &T::State) {
+        if let Some(slot) = self.slot(entity) {
+            state.to_values(&mut self.states[Self::range(slot)]);
+        }
+    }
+
+    // the state values of one entity (named by StateVector::NAMES)
+    pub fn values(&self, entity: &Entity) -> Option<&[f64]> {
+        let slot = self.slot(entity)?;
+        Some(&self.states[Self::range(slot)])
+    }
+
+    pub fn values_mut(&mut self, entity: &Entity) -> Option<&mut [f64]> {
+        let slot = self.slot(entity)?;
+        Some(&mut self.states[Self::range(slot)])
+    }
+
+    pub fn dvalues(&self, entity: &Entity) -> Option<&[f64]> {
+        let slot = self.slot(entity)?;
+        Some(&self.dstates[Self::range(slot)])
+    }
+
+    // a single named value of an entity's state, e.g. "q"
+    pub fn value(&self, entity: &Entity, name: &str) -> Option<f64> {
+        let index = <T::State as StateVector>::index(name)?;
+        Some(self.values(entity)?[index])
+    }
+}
+
+// Type erased access to the PhysicsState of one registered Stateful type
+#[derive(Clone)]
+struct StatefulEntry {
+    type_id: TypeId,
+    len: fn(&World) -> usize,
+    read_states: fn(&World, &mut [f64]),
+    write_states: fn(&mut World, &[f64]),
+    read_dstates: fn(&World, &mut [f64]),
+    layout: fn(&World, usize, &mut StateLayout),
+    names: fn(&mut World, &mut Vec<String>),
+}
+
+impl StatefulEntry {
+    fn new<T: Component + Stateful>() -> Self {
+        StatefulEntry {
+            type_id: TypeId::of::<T>(),
+            len: |world| world.resource::<PhysicsState<T>>().states.len(),
+            read_states: |world, values| {
+                values.copy_from_slice(&world.resource::<PhysicsState<T>>().states);
+            },
+            write_states: |world, values| {
+                let mut physics_state = world.resource_mut::<PhysicsState<T>>();
+                physics_state.states.copy_from_slice(values);
+            },
+            read_dstates: |world, values| {
+                values.copy_from_slice(&world.resource::<PhysicsState<T>>().dstates);
+            },
+            layout: |world, start, layout| {
+                let size = <T::State as StateVector>::SIZE;
+                let kinds = <T::State as StateVector>::KINDS;
+                let count = world.resource::<PhysicsState<T>>().entities().len();
+                for slot in 0..count {
+                    layout.add(start + slot * size, size, kinds);
+                }
+                layout.add_increment(start, count * size, increment_states::<T>);
+            },
+            names: |world, names| {
+                let mut query = world.query::<&T>();
+                let physics_state = world.resource::<PhysicsState<T>>();
+                let fields = <T::State as StateVector>::NAMES;
+                for entity in physics_state.entities() {
+                    let name = match query.get(world, *entity) {
+                        Ok(component) if !component.get_name().is_empty() => component.get_name(),
+                        _ => format!("{entity:?}"),
+                    };
+                    for index in 0..<T::State as StateVector>::SIZE {
+                        match fields.get(index) {
+                            Some(field) => names.push(format!("{name}.{field}")),
+                            None => names.push(format!("{name}.{index}")),
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+// StateVector::increment for every slot of a PhysicsState<T>
+fn increment_states<T: Stateful>(values: &mut [f64], scale: f64, dvalues: &[f64]) {
+    let size = <T::State as StateVector>::SIZE;
+    for (values, dvalues) in values.chunks_mut(size).zip(dvalues.chunks(size)) {
+        <T::State as StateVector>::increment(values, scale, dvalues);
+    }
+}
+
+// The Stateful types integrated by the solver. Their states are concatenated (in registration order)
+// into one buffer, so that every type is advanced by the same solver stages.
+#[derive(Resource, Clone, Default)]
+pub struct StatefulRegistry {
+    entries: Vec<StatefulEntry>,
+}
+
+impl StatefulRegistry {
+    // returns false if the type was already registered
+    fn register<T: Component + Stateful>(&mut self) -> bool {
+        if self.contains::<T>() {
+            return false;
+        }
+        self.entries.push(StatefulEntry::new::<T>());
+        true
+    }
+
+    pub fn contains<T: Stateful>(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.type_id == TypeId::of::<T>())
+    }
+
+    // total number of state values
+    pub fn len(&self, world: &World) -> usize {
+        self.entries.iter().map(|entry| (entry.len)(world)).sum()
+    }
+
+    pub fn read_states(&self, world: &World, state: &mut [f64]) {
+        let mut start = 0;
+        for entry in self.entries.iter() {
+            let len = (entry.len)(world);
+            (entry.read_states)(world, &mut state[start..start + len]);
+            start += len;
+        }
+    }
+
+    pub fn write_states(&self, world: &mut World, state: &[f64]) {
+        let mut start = 0;
+        for entry in self.entries.iter() {
+            let len = (entry.len)(world);
+            (entry.write_states)(world, &state[start..start + len]);
+            start += len;
+        }
+    }
+
+    // names of all state values, in the order of the combined state
+    pub fn names(&self, world: &mut World) -> Vec<String> {
+        let mut names = Vec::new();
+        for entry in self.entries.iter() {
+            (entry.names)(world, &mut names);
+        }
+        names
+    }
+
+    pub(crate) fn layout(&self, world: &World) -> StateLayout {
+        let mut layout = StateLayout::default();
+        let mut start = 0;
+        for entry in self.entries.iter() {
+            (entry.layout)(world, start, &mut layout);
+            start += (entry.len)(world);
+        }
+        layout
+    }
+
+    pub fn read_dstates(&self, world: &World, dstate: &mut [f64]) {
+        let mut start = 0;
+        for entry in self.entries.iter() {
+            let len = (entry.len)(world);
+            (entry.read_dstates)(world, &mut dstate[start..start + len]);
+            start += len;
+        }
+    }
+}
+
+pub trait StatefulAppExt {
+    // integrate the components of type T together with all other registered Stateful types
+    fn add_stateful<T: Component + Stateful>(&mut self) -> &mut Self;
+}
+
+impl StatefulAppExt for App {
+    fn add_stateful<T: Component + Stateful>(&mut self) -> &mut Self {
+        let registered = self
+            .world
+            .get_resource_or_insert_with(StatefulRegistry::default)
+            .register::<T>();
+        if !registered {
+            return self;
+        }
+
+        self.add_systems(
+            PhysicsSchedule,
+            (
+                distribute_state::<T>.in_set(SolverSet::Pre),
+                collect_state_derivatives::<T>.in_set(SolverSet::Post),
+            ),
+        )
+        .add_systems(
+            PostStartup,
+            initialize_state::<T>.in_set(InitializeStateSet),
+        )
+        .init_resource::<StageTime>()
+        .add_checkpoint(
+            T::CHECKPOINT_KEY,
+            save_physics_state::<T>,
+            restore_physics_state::<T>,
+        )
+        .add_checkpoint("solver", save_solver_state, restore_solver_state)
+    }
 }
 
 pub trait PhysicsScheduleExt {
-    fn add_physics_systems<T, MInit, MFinal>(
+    fn add_physics_systems<MInit, MFinal>(
         &mut self,
         systems_init: impl IntoSystemConfigs<MInit>,
         systems_final: impl IntoSystemConfigs<MFinal>,
-    ) -> &mut Self
-    where
-        T: Component + Stateful;
+    ) -> &mut Self;
 }
 
 impl PhysicsScheduleExt for Schedule {
-    fn add_physics_systems<T, MInit, MFinal>(
+    fn add_physics_systems<MInit, MFinal>(
         &mut self,
         systems_init: impl IntoSystemConfigs<MInit>,
         systems_final: impl IntoSystemConfigs<MFinal>,
-    ) -> &mut Self
-    where
-        T: Component + Stateful,
-    {
+    ) -> &mut Self {
         self.configure_sets(
             (
                 SolverSet::Pre,
@@ -222,96 +574,64 @@ impl PhysicsScheduleExt for Schedule {
             )
                 .chain(), // This defines the ordering of the system sets
         )
-        .add_systems(distribute_state::<T>.in_set(SolverSet::Pre))
         .add_systems(systems_init.in_set(PhysicsSet::Initialize))
-        .add_systems(systems_final.in_set(PhysicsSet::Finalize))
-        .add_systems(collect_state_derivatives::<T>.in_set(SolverSet::Post));
+        .add_systems(systems_final.in_set(PhysicsSet::Finalize));
 
         self
     }
 }
 
+// Assigns a slot to every entity with the component T (in entity order), and copies in the
+// initial states.
 pub fn initialize_state<T: Component + Stateful>(
     mut commands: Commands,
     joint_query: Query<(Entity, &T)>,
 ) {
-    let mut states = StateMap::<T>::new();
-    let mut dstates = StateMap::<T>::new();
-    for (entity, joint) in joint_query.iter() {
-        states.insert(entity, joint.get_state());
-        dstates.insert(entity, joint.get_dstate());
+    let mut entities: Vec<Entity> = joint_query.iter().map(|(entity, _)| entity).collect();
+    entities.sort();
+
+    let mut physics_state = PhysicsState::<T>::new(entities);
+    let size = <T::State as StateVector>::SIZE;
+    for (slot, entity) in physics_state.entities.iter().enumerate() {
+        let joint = joint_query.get(*entity).unwrap().1;
+        let range = slot * size..(slot + 1) * size;
+        joint
+            .get_state()
+            .to_values(&mut physics_state.states[range.clone()]);
+        joint
+            .get_dstate()
+            .to_values(&mut physics_state.dstates[range]);
     }
-    commands.insert_resource(PhysicsState::<T> { states, dstates });
+    commands.insert_resource(physics_state);
 }
 
 fn distribute_state<T: Component + Stateful>(
-    mut joint_query: Query<(Entity, &mut T)>,
+    mut joint_query: Query<&mut T>,
     physics_state: Res<PhysicsState<T>>,
 ) {
-    for (entity, mut joint) in joint_query.iter_mut() {
-        if let Some(state) = physics_state.states.get(&entity) {
-            joint.set_state(state);
+    let size = <T::State as StateVector>::SIZE;
+    for (slot, entity) in physics_state.entities.iter().enumerate() {
+        if let Ok(mut joint) = joint_query.get_mut(*entity) {
+            let values = &physics_state.states[slot * size..(slot + 1) * size];
+            joint.set_state(&T::State::from_values(values));
             joint.reset();
         }
     }
 }
 
 fn collect_state_derivatives<T: Component + Stateful>(
-    mut joint_query: Query<(Entity, &mut T)>,
+    joint_query: Query<&T>,
     mut physics_state: ResMut<PhysicsState<T>>,
 ) {
-    for (entity, joint) in joint_query.iter_mut() {
-        let joint_state = joint.get_dstate();
-        physics_state.dstates.insert(entity, joint_state);
+    let size = <T::State as StateVector>::SIZE;
+    let PhysicsState {
+        entities, dstates, ..
+    } = physics_state.as_mut();
+    for (slot, entity) in entities.iter().enumerate() {
+        if let Ok(joint) = joint_query.get(*entity) {
+            joint
+                .get_dstate()
+                .to_values(&mut dstates[slot * size..(slot + 1) * size]);
+        }
     }
 }
-
-#[derive(Resource, Clone, Copy)]
-pub enum Solver {
-    Euler,
-    Heun,
-    Midpoint,
-    RK4,
-}
-
-fn euler<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let updated_state = state + &(&state_derivative * dt);
-    updated_state
-}
-
-fn heun<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(world, &mut (state + &(&state_derivative * dt)), t + dt);
-    state + &(&(&state_derivative + &state_derivative2) * (dt * 0.5))
-}
-
-fn midpoint<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    state + &(&state_derivative2 * dt)
-}
-
-fn rk4<T: Stateful>(world: &mut World, state: &StateMap<T>, t: f64, dt: f64) -> StateMap<T> {
-    let state_derivative = evaluate_state(world, &mut state.clone(), t);
-    let state_derivative2 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    let state_derivative3 = evaluate_state(
-        world,
-        &mut (state + &(&state_derivative2 * (dt * 0.5))),
-        t + dt * 0.5,
-    );
-    let state_derivative4 =
-        evaluate_state(world, &mut (state + &(&state_derivative3 * dt)), t + dt);
-    let state_change = &(&(&state_derivative + &(&state_derivative2 * 2.))
-        + &(&state_derivative3 * 2.))
-        + &state_derivative4;
-    state + &(&state_change * (dt / 6.))
-}
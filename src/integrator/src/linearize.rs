@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+
+use crate::{input::InputRegistry, SimTime, StateEvaluator, StatefulRegistry};
+
+// Linear model of the simulation about an operating point:
+// dx/dt = dstate + a * (x - state) + b * (u - input)
+#[derive(Clone, Debug)]
+pub struct Linearization {
+    pub a: DMatrix<f64>,
+    pub b: DMatrix<f64>,
+    pub states: Vec<String>, // names of the rows/columns of a (and the rows of b)
+    pub inputs: Vec<String>, // names of the columns of b
+    pub state: DVector<f64>,
+    pub input: DVector<f64>,
+    pub dstate: DVector<f64>, // state derivative at the operating point (zero at an equilibrium)
+}
+
+// Linearize the simulation about the current state (of all registered Stateful types) and inputs
+// (registered with `add_input`), using central finite differences. The perturbation is relative to
+// the size of each value (with a minimum of 1). The world is left at the operating point.
+// The states are perturbed one value at a time, so states on a manifold (with a custom
+// `StateVector::increment`, like the unit quaternions of floating and spherical joints) can't be
+// linearized, and are an error.
+pub fn linearize(world: &mut World, perturbation: f64) -> Result<Linearization, String> {
+    let inputs = world
+        .get_resource::<InputRegistry>()
+        .cloned()
+        .unwrap_or_default();
+    let time = world.resource::<SimTime>().time();
+
+    world.resource_scope(|world: &mut World, registry: Mut<StatefulRegistry>| {
+        let states = registry.names(world);
+        let n = registry.len(world);
+        let m = inputs.inputs().len();
+
+        let mut state = vec![0.; n];
+        registry.read_states(world, &mut state);
+
+        // a perturbation of one value, added with the increment of the state, must only change
+        // that value
+        let layout = registry.layout(world);
+        let mut unit = vec![0.; n];
+        let mut perturbed = state.clone();
+        for j in 0..n {
+            unit[j] = 1.;
+            layout.increment(&mut perturbed, perturbation, &unit);
+            unit[j] = 0.;
+            let changed = (0..n).any(|i| {
+                let expected = if i == j {
+                    state[i] + perturbation
+                } else {
+                    state[i]
+                };
+                perturbed[i] != expected
+            });
+            if changed {
+                return Err(format!(
+                    "the state {} is on a manifold (e.g. the unit quaternion of a floating or \
+                     spherical joint), and can't be linearized",
+                    states[j]
+                ));
+            }
+            perturbed.copy_from_slice(&state);
+        }
+        let input: Vec<f64> = inputs
+            .inputs()
+            .iter()
+            .map(|input| (input.get)(world))
+            .collect();
+
+        let mut physics = StateEvaluator {
+            world,
+            registry: &registry,
+            stage: 0,
+        };
+        let mut dstate = vec![0.; n];
+        let mut plus = vec![0.; n];
+        let mut minus = vec![0.; n];
+
+        let mut a = DMatrix::zeros(n, n);
+        for j in 0..n {
+            let delta = perturbation * state[j].abs().max(1.);
+            perturbed[j] = state[j] + delta;
+            physics.evaluate(&perturbed, time, &mut plus);
+            perturbed[j] = state[j] - delta;
+            physics.evaluate(&perturbed, time, &mut minus);
+            perturbed[j] = state[j];
+            for i in 0..n {
+                a[(i, j)] = (plus[i] - minus[i]) / (2. * delta);
+            }
+        }
+
+        let mut b = DMatrix::zeros(n, m);
+        for (j, (registered, value)) in inputs.inputs().iter().zip(input.iter()).enumerate() {
+            let delta = perturbation * value.abs().max(1.);
+            // the applied values are read back, in case the input is stored with less precision
+            (registered.set)(physics.world, value + delta);
+            let value_plus = (registered.get)(physics.world);
+            physics.evaluate(&state, time, &mut plus);
+            (registered.set)(physics.world, value - delta);
+            let value_minus = (registered.get)(physics.world);
+            physics.evaluate(&state, time, &mut minus);
+            (registered.set)(physics.world, *value);
+
+            if value_plus == value_minus {
+                warn!("input \"{}\" could not be perturbed", registered.name);
+                continue;
+            }
+            for i in 0..n {
+                b[(i, j)] = (plus[i] - minus[i]) / (value_plus - value_minus);
+            }
+        }
+
+        // leave the world at the operating point
+        physics.evaluate(&state, time, &mut dstate);
+
+        Ok(Linearization {
+            a,
+            b,
+            states,
+            inputs: inputs.names(),
+            state: DVector::from_vec(state),
+            input: DVector::from_vec(input),
+            dstate: DVector::from_vec(dstate),
+        })
+    })
+}
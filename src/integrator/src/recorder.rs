@@ -0,0 +1,358 @@
+use bevy::{app::AppExit, prelude::*};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{checkpoint::CheckpointAppExt, SimTime};
+
+// Binary recording format (all numbers little endian):
+// - the magic bytes "BVYREC01"
+// - the number of channels (u32, not counting time) and the number of samples (u64)
+// - the channel names, each as a length (u32) followed by UTF-8 bytes
+// - the columns as f64 values: time first, then every channel in the order of the names
+const MAGIC: &[u8; 8] = b"BVYREC01";
+
+// A named series of samples
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+// Channels that are all sampled at the same times. A channel that was not written in a sample
+// (e.g. it was added later) holds NaN for that sample.
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub time: Vec<f64>,
+    pub channels: Vec<Channel>,
+}
+
+impl Recording {
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|channel| channel.name == name)
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        write!(file, "time")?;
+        for channel in self.channels.iter() {
+            write!(file, ",{}", csv_field(&channel.name))?;
+        }
+        writeln!(file)?;
+
+        for (sample, time) in self.time.iter().enumerate() {
+            write!(file, "{time}")?;
+            for channel in self.channels.iter() {
+                write!(file, ",{}", channel.values[sample])?;
+            }
+            writeln!(file)?;
+        }
+        file.flush()
+    }
+
+    pub fn write_binary(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(self.channels.len() as u32).to_le_bytes())?;
+        file.write_all(&(self.time.len() as u64).to_le_bytes())?;
+        for channel in self.channels.iter() {
+            file.write_all(&(channel.name.len() as u32).to_le_bytes())?;
+            file.write_all(channel.name.as_bytes())?;
+        }
+
+        let columns = std::iter::once(&self.time).chain(self.channels.iter().map(|c| &c.values));
+        for column in columns {
+            for value in column.iter() {
+                file.write_all(&value.to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+
+    pub fn read_binary(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a recording file",
+            ));
+        }
+
+        let channel_count = read_u32(&mut file)? as usize;
+        let sample_count = read_u64(&mut file)? as usize;
+        let mut names = Vec::with_capacity(channel_count);
+        for _ in 0..channel_count {
+            let mut name = vec![0; read_u32(&mut file)? as usize];
+            file.read_exact(&mut name)?;
+            names.push(
+                String::from_utf8(name)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?,
+            );
+        }
+
+        let time = read_column(&mut file, sample_count)?;
+        let mut channels = Vec::with_capacity(channel_count);
+        for name in names {
+            let values = read_column(&mut file, sample_count)?;
+            channels.push(Channel { name, values });
+        }
+        Ok(Recording { time, channels })
+    }
+}
+
+// a CSV field, quoted if it contains a separator, quote or line break (with quotes doubled)
+pub fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", text.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_column(reader: &mut impl Read, len: usize) -> io::Result<Vec<f64>> {
+    let mut column = Vec::with_capacity(len);
+    let mut bytes = [0; 8];
+    for _ in 0..len {
+        reader.read_exact(&mut bytes)?;
+        column.push(f64::from_le_bytes(bytes));
+    }
+    Ok(column)
+}
+
+// a channel seen by the recorder, and where it is stored (if it is selected)
+#[derive(Clone, Debug)]
+struct KnownChannel {
+    name: String,
+    column: Option<usize>,
+}
+
+// Records the registered channels after every `decimation` integration steps. Recording starts when
+// this resource is inserted, and the files (if any) are written when the app exits.
+#[derive(Resource, Clone, Debug)]
+pub struct Recorder {
+    pub decimation: usize,
+    // channels to record: exact names, or prefixes ending with '*' (e.g. "wheel_fl.*"). All channels
+    // are recorded if this is empty.
+    pub selection: Vec<String>,
+    pub csv_path: Option<PathBuf>,
+    pub binary_path: Option<PathBuf>,
+    skip: usize, // steps until the next sample
+    known: Vec<KnownChannel>,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(decimation: usize) -> Self {
+        Recorder {
+            decimation: decimation.max(1),
+            selection: Vec::new(),
+            csv_path: None,
+            binary_path: None,
+            skip: 0,
+            known: Vec::new(),
+            recording: Recording::default(),
+        }
+    }
+
+    pub fn with_selection(mut self, selection: &[&str]) -> Self {
+        self.selection = selection
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        self
+    }
+
+    pub fn with_csv(mut self, path: impl Into<PathBuf>) -> Self {
+        self.csv_path = Some(path.into());
+        self
+    }
+
+    pub fn with_binary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.binary_path = Some(path.into());
+        self
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    fn is_selected(&self, name: &str) -> bool {
+        self.selection.is_empty()
+            || self
+                .selection
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == pattern,
+                })
+    }
+
+    pub fn write_files(&self) -> io::Result<()> {
+        if let Some(path) = &self.csv_path {
+            self.recording.write_csv(path)?;
+        }
+        if let Some(path) = &self.binary_path {
+            self.recording.write_binary(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new(1)
+    }
+}
+
+// Passed to the recorder sources to write the values of one sample. Channels are named
+// "{name}.{field}", and are expected to be written in the same order for every sample, so that
+// nothing needs to be allocated once the channels are known.
+pub struct ChannelWriter<'a> {
+    recorder: &'a mut Recorder,
+    cursor: usize,
+}
+
+impl ChannelWriter<'_> {
+    pub fn write(&mut self, name: &str, field: &str, value: f64) {
+        let matches = |known: &KnownChannel| {
+            known.name.len() == name.len() + field.len() + 1
+                && known.name.starts_with(name)
+                && known.name[name.len()..].starts_with('.')
+                && known.name.ends_with(field)
+        };
+
+        let index = if self.recorder.known.get(self.cursor).is_some_and(matches) {
+            self.cursor
+        } else if let Some(index) = self.recorder.known.iter().position(matches) {
+            index
+        } else {
+            self.add_channel(format!("{name}.{field}"))
+        };
+        self.cursor = index + 1;
+
+        if let Some(column) = self.recorder.known[index].column {
+            self.recorder.recording.channels[column].values.push(value);
+        }
+    }
+
+    fn add_channel(&mut self, name: String) -> usize {
+        let column = if self.recorder.is_selected(&name) {
+            // earlier samples didn't have this channel
+            let samples = self.recorder.recording.time.len() - 1;
+            self.recorder.recording.channels.push(Channel {
+                name: name.clone(),
+                values: vec![f64::NAN; samples],
+            });
+            Some(self.recorder.recording.channels.len() - 1)
+        } else {
+            None
+        };
+        self.recorder.known.push(KnownChannel { name, column });
+        self.recorder.known.len() - 1
+    }
+}
+
+// Functions that write channels to the recorder
+#[derive(Resource, Clone, Default)]
+pub struct RecorderSources(Vec<fn(&mut World, &mut ChannelWriter)>);
+
+pub trait RecorderAppExt {
+    fn add_recorder_source(&mut self, source: fn(&mut World, &mut ChannelWriter)) -> &mut Self;
+}
+
+impl RecorderAppExt for App {
+    fn add_recorder_source(&mut self, source: fn(&mut World, &mut ChannelWriter)) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(RecorderSources::default)
+            .0
+            .push(source);
+        self.add_checkpoint("recorder", save_recorder, restore_recorder)
+    }
+}
+
+// Checkpoints only hold the position in the decimation, not the recording. When a checkpoint is
+// restored, the samples after its time are dropped, so a rewound simulation records them again.
+fn save_recorder(world: &mut World) -> Value {
+    match world.get_resource::<Recorder>() {
+        Some(recorder) => Value::from(recorder.skip),
+        None => Value::Null,
+    }
+}
+
+fn restore_recorder(world: &mut World, value: &Value) {
+    let time = world.resource::<SimTime>().clone();
+    let Some(mut recorder) = world.get_resource_mut::<Recorder>() else {
+        return;
+    };
+    let tolerance = 1e-6 * time.dt;
+    let samples = recorder
+        .recording
+        .time
+        .partition_point(|sample| *sample <= time.time() + tolerance);
+    recorder.recording.time.truncate(samples);
+    for channel in recorder.recording.channels.iter_mut() {
+        channel.values.truncate(samples);
+    }
+    recorder.skip = value.as_u64().unwrap_or(0) as usize;
+}
+
+// Sample all sources. Runs in the StepSchedule (when a Recorder resource exists), so systems that
+// update recorded values once per step should run before it, and once before the first step (after
+// `initial_evaluation_system`) for the initial state.
+pub fn record_system(world: &mut World) {
+    world.resource_scope(|world: &mut World, mut recorder: Mut<Recorder>| {
+        // the initial state is sampled, then every `decimation` steps
+        if recorder.skip > 0 {
+            recorder.skip -= 1;
+            return;
+        }
+        recorder.skip = recorder.decimation.max(1) - 1;
+
+        let time = world.resource::<SimTime>().time();
+        recorder.recording.time.push(time);
+
+        let mut writer = ChannelWriter {
+            recorder: recorder.as_mut(),
+            cursor: 0,
+        };
+        if let Some(sources) = world.remove_resource::<RecorderSources>() {
+            for source in sources.0.iter() {
+                source(world, &mut writer);
+            }
+            world.insert_resource(sources);
+        }
+
+        // channels that were not written in this sample
+        let samples = recorder.recording.time.len();
+        for channel in recorder.recording.channels.iter_mut() {
+            channel.values.resize(samples, f64::NAN);
+        }
+    });
+}
+
+pub fn write_recording_system(recorder: Res<Recorder>, exit: EventReader<AppExit>) {
+    if exit.is_empty() {
+        return;
+    }
+    if let Err(error) = recorder.write_files() {
+        error!("could not write the recording: {error}");
+    }
+}
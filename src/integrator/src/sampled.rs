@@ -0,0 +1,217 @@
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    checkpoint::CheckpointAppExt,
+    input::{InputRegistry, InputSignal},
+    SimTime,
+};
+
+// tolerance of the sample and output times, relative to the time step
+const TIME_TOLERANCE: f64 = 1e-6;
+
+// The systems of a sampled controller, run at its samples (see `add_sampled_controller`)
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+pub struct SampledSchedule(pub String);
+
+// The sample being run, available to the systems of a sampled controller (e.g. the period is the
+// time step of a discrete filter)
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SampleTime {
+    pub time: f64,
+    pub period: f64,
+    pub index: usize, // samples since the start time
+}
+
+// A discrete-time controller, run every `period` seconds of simulation time (from SimTime's start
+// time). Samples are taken at the end of a step, so the period and latency should be multiples of
+// SimTime::dt, otherwise they are rounded up to the next step (a period shorter than a step is run
+// once per step).
+//
+// The outputs are inputs of the InputRegistry (e.g. "control.throttle"). The values written by the
+// controller are applied `latency` seconds after the sample, and held until the next output is
+// applied, so they are constant over all solver stages in between (zero-order hold).
+#[derive(Clone, Debug)]
+pub struct SampledController {
+    pub name: String,
+    pub period: f64,
+    pub latency: f64,
+    pub outputs: Vec<String>,
+    pending: VecDeque<(f64, Vec<f64>)>, // outputs waiting for their latency, by application time
+}
+
+impl SampledController {
+    pub fn new(name: &str, period: f64) -> Self {
+        SampledController {
+            name: name.to_string(),
+            period,
+            latency: 0.,
+            outputs: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn with_latency(mut self, latency: f64) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_outputs(mut self, outputs: &[&str]) -> Self {
+        self.outputs = outputs.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    fn sample_index(&self, elapsed: f64, dt: f64) -> usize {
+        (elapsed / self.period + TIME_TOLERANCE * dt / self.period)
+            .floor()
+            .max(0.) as usize
+    }
+}
+
+#[derive(Resource, Clone, Default)]
+pub struct SampledControllers {
+    controllers: Vec<SampledController>,
+}
+
+impl SampledControllers {
+    pub fn controllers(&self) -> &[SampledController] {
+        &self.controllers
+    }
+}
+
+pub trait SampledAppExt {
+    // Run the systems (in their own schedule) as a sampled controller. The outputs that are waiting
+    // for their latency are saved in checkpoints; any state of the controller itself (e.g. an
+    // integrator) should be registered with `add_checkpoint_resource`.
+    fn add_sampled_controller<M>(
+        &mut self,
+        controller: SampledController,
+        systems: impl IntoSystemConfigs<M>,
+    ) -> &mut Self;
+}
+
+impl SampledAppExt for App {
+    fn add_sampled_controller<M>(
+        &mut self,
+        controller: SampledController,
+        systems: impl IntoSystemConfigs<M>,
+    ) -> &mut Self {
+        let schedule = SampledSchedule(controller.name.clone());
+        let mut controllers = self
+            .world
+            .get_resource_or_insert_with(SampledControllers::default);
+        controllers
+            .controllers
+            .retain(|existing| existing.name != controller.name);
+        controllers.controllers.push(controller);
+
+        self.add_systems(schedule, systems).add_checkpoint(
+            "sampled_controllers",
+            save_sampled_outputs,
+            restore_sampled_outputs,
+        )
+    }
+}
+
+// Run the controllers that have a sample at the current time, and apply the outputs whose latency
+// has passed. This runs once after every step (in the StepSchedule), and once before the first step
+// for the samples at the start time.
+pub fn sampled_controller_system(world: &mut World) {
+    let sim_time = world.resource::<SimTime>().clone();
+    let time = sim_time.time();
+    let elapsed = time - sim_time.start_time;
+    let inputs = world
+        .get_resource::<InputRegistry>()
+        .cloned()
+        .unwrap_or_default();
+
+    world.resource_scope(
+        |world: &mut World, mut controllers: Mut<SampledControllers>| {
+            for controller in controllers.controllers.iter_mut() {
+                let outputs = controller_outputs(controller, &inputs, sim_time.index == 0);
+
+                let sample = controller.sample_index(elapsed, sim_time.dt);
+                let previous = controller.sample_index(elapsed - sim_time.dt, sim_time.dt);
+                if sim_time.index == 0 || sample > previous {
+                    // the outputs written by the controller are held at their previous values until
+                    // they are applied
+                    let held: Vec<f64> = outputs.iter().map(|input| (input.get)(world)).collect();
+                    world.insert_resource(SampleTime {
+                        time,
+                        period: controller.period,
+                        index: sample,
+                    });
+                    world.run_schedule(SampledSchedule(controller.name.clone()));
+                    let values = outputs.iter().map(|input| (input.get)(world)).collect();
+                    for (input, value) in outputs.iter().zip(held) {
+                        (input.set)(world, value);
+                    }
+                    controller
+                        .pending
+                        .push_back((time + controller.latency, values));
+                }
+
+                while let Some((apply_time, _)) = controller.pending.front() {
+                    if *apply_time > time + TIME_TOLERANCE * sim_time.dt {
+                        break;
+                    }
+                    let (_, values) = controller.pending.pop_front().unwrap();
+                    for (input, value) in outputs.iter().zip(values) {
+                        (input.set)(world, value);
+                    }
+                }
+            }
+        },
+    );
+}
+
+fn controller_outputs<'a>(
+    controller: &SampledController,
+    inputs: &'a InputRegistry,
+    warn_missing: bool,
+) -> Vec<&'a InputSignal> {
+    controller
+        .outputs
+        .iter()
+        .filter_map(|name| {
+            let input = inputs.get(name);
+            if input.is_none() && warn_missing {
+                warn!(
+                    "{name} (an output of the sampled controller {}) is not a registered input",
+                    controller.name
+                );
+            }
+            input
+        })
+        .collect()
+}
+
+// checkpoint of the outputs waiting for their latency, by controller name
+fn save_sampled_outputs(world: &mut World) -> Value {
+    let pending: HashMap<String, Vec<(f64, Vec<f64>)>> = world
+        .resource::<SampledControllers>()
+        .controllers
+        .iter()
+        .map(|controller| {
+            let pending = controller.pending.iter().cloned().collect();
+            (controller.name.clone(), pending)
+        })
+        .collect();
+    serde_json::to_value(pending).unwrap()
+}
+
+fn restore_sampled_outputs(world: &mut World, value: &Value) {
+    let Ok(mut pending) = HashMap::<String, VecDeque<(f64, Vec<f64>)>>::deserialize(value) else {
+        warn!("could not restore the sampled controller outputs");
+        return;
+    };
+    for controller in world
+        .resource_mut::<SampledControllers>()
+        .controllers
+        .iter_mut()
+    {
+        controller.pending = pending.remove(&controller.name).unwrap_or_default();
+    }
+}
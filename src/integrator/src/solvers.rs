@@ -0,0 +1,781 @@
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVectorViewMut};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{input::InputRegistry, StateEvaluator, StateKind, StatefulRegistry};
+
+// The solvers work on the combined state of all registered Stateful types, stored in one contiguous
+// buffer. Each solver advances `SolverWorkspace::state` in place, always by an increment of a state
+// derivative (see `StateVector::increment`), so states on a manifold stay on it. The explicit solvers
+// only use the preallocated workspace buffers, so a step does not allocate.
+
+#[derive(Resource, Clone, Copy)]
+pub enum Solver {
+    Euler,
+    Heun,
+    Midpoint,
+    RK4,
+    RK45,            // adaptive Dormand-Prince, configured by the AdaptiveStep resource
+    BackwardEuler,   // implicit, configured by the ImplicitSettings resource
+    Rosenbrock,      // linearly implicit (ROS2), configured by the ImplicitSettings resource
+    SymplecticEuler, // semi-implicit Euler, for position/velocity states (see StateKind)
+    VelocityVerlet,  // second order symplectic, for position/velocity states (see StateKind)
+}
+
+// A step that can't be completed. The state is left where the solver stopped (at the start of the
+// step for the implicit solvers).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolverError {
+    // the state or its derivative is NaN or infinite after a step (for RK45, the error estimate is
+    // even at the minimum step size)
+    NonFinite { time: f64 },
+    // the iteration matrix of an implicit solver is singular (I - dt * J for backward Euler)
+    Singular { time: f64 },
+    // the newton iteration of backward Euler didn't converge in `ImplicitSettings::max_iterations`
+    NotConverged { time: f64, iterations: usize },
+}
+
+impl std::fmt::Display for SolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SolverError::NonFinite { time } => write!(
+                f,
+                "the state or its derivative is not finite at t = {time:.6} s"
+            ),
+            SolverError::Singular { time } => write!(
+                f,
+                "the iteration matrix of the implicit solver is singular at t = {time:.6} s"
+            ),
+            SolverError::NotConverged { time, iterations } => write!(
+                f,
+                "the newton iteration didn't converge in {iterations} iterations at t = {time:.6} s"
+            ),
+        }
+    }
+}
+
+// Settings and statistics for the adaptive (RK45) solver. The fixed time step is split into as many
+// sub-steps as are needed to keep the estimated local error within the tolerances.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct AdaptiveStep {
+    pub abs_tol: f64,
+    pub rel_tol: f64,
+    pub min_dt: f64,
+    pub max_dt: f64,
+    pub dt: f64, // current step size, carried over between fixed time steps
+    pub accepted: usize,
+    pub rejected: usize,
+    pub error: f64, // normalized error of the last attempted step (<= 1 is accepted)
+}
+
+impl AdaptiveStep {
+    pub fn new(abs_tol: f64, rel_tol: f64, min_dt: f64, max_dt: f64) -> Self {
+        AdaptiveStep {
+            abs_tol,
+            rel_tol,
+            min_dt,
+            max_dt,
+            dt: max_dt,
+            accepted: 0,
+            rejected: 0,
+            error: 0.,
+        }
+    }
+
+    // normalized RMS error over all state values
+    fn error_norm(&self, state: &[f64], new_state: &[f64], error: &[f64]) -> f64 {
+        if error.is_empty() {
+            return 0.;
+        }
+        let mut sum = 0.;
+        for ((e, y0), y1) in error.iter().zip(state.iter()).zip(new_state.iter()) {
+            let scale = self.abs_tol + self.rel_tol * y0.abs().max(y1.abs());
+            sum += (e / scale).powi(2);
+        }
+        (sum / error.len() as f64).sqrt()
+    }
+}
+
+impl Default for AdaptiveStep {
+    fn default() -> Self {
+        AdaptiveStep::new(1e-6, 1e-4, 1e-6, 0.01)
+    }
+}
+
+// Settings and statistics for the implicit solvers. The Jacobian of the state derivative is built by
+// finite differences, perturbing one state value at a time and re-evaluating the physics.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct ImplicitSettings {
+    pub tolerance: f64, // newton iterations stop when the update norm is below this value
+    pub max_iterations: usize, // maximum newton iterations per step (backward Euler only)
+    pub perturbation: f64, // relative size of the finite difference perturbation
+    pub iterations: usize, // newton iterations used in the last step
+    pub converged: bool, // did the last step converge
+    pub evaluations: usize, // total number of physics evaluations
+}
+
+impl ImplicitSettings {
+    pub fn new(tolerance: f64, max_iterations: usize) -> Self {
+        ImplicitSettings {
+            tolerance,
+            max_iterations,
+            perturbation: 1e-7,
+            iterations: 0,
+            converged: true,
+            evaluations: 0,
+        }
+    }
+}
+
+impl Default for ImplicitSettings {
+    fn default() -> Self {
+        ImplicitSettings::new(1e-8, 10)
+    }
+}
+
+// The solver resources: the adaptive step size carries over between steps, so it is part of the
+// simulation state (saved in checkpoints, and restored when a step is taken again), and so are the
+// statistics.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub(crate) struct SolverResources {
+    adaptive: Option<AdaptiveStep>,
+    implicit: Option<ImplicitSettings>,
+}
+
+impl SolverResources {
+    pub(crate) fn save(world: &World) -> Self {
+        SolverResources {
+            adaptive: world.get_resource::<AdaptiveStep>().cloned(),
+            implicit: world.get_resource::<ImplicitSettings>().cloned(),
+        }
+    }
+
+    // resources that didn't exist when they were saved are removed (they are created with the
+    // default settings when they are first needed)
+    pub(crate) fn restore(&self, world: &mut World) {
+        match &self.adaptive {
+            Some(adaptive) => world.insert_resource(adaptive.clone()),
+            None => {
+                world.remove_resource::<AdaptiveStep>();
+            }
+        }
+        match &self.implicit {
+            Some(implicit) => world.insert_resource(implicit.clone()),
+            None => {
+                world.remove_resource::<ImplicitSettings>();
+            }
+        }
+    }
+}
+
+pub(crate) fn save_solver_state(world: &mut World) -> Value {
+    serde_json::to_value(SolverResources::save(world)).unwrap()
+}
+
+pub(crate) fn restore_solver_state(world: &mut World, value: &Value) {
+    match SolverResources::deserialize(value) {
+        Ok(resources) => resources.restore(world),
+        Err(error) => warn!("could not restore the solver state: {error}"),
+    }
+}
+
+// The state derivative at the end of the last step, which is evaluated anyway to leave the world
+// consistent with the new state. It is reused as the first stage of the next step (first same as
+// last), as long as the state, the time and the registered inputs are unchanged. Systems that change
+// anything else the physics depends on between steps (e.g. a filter in the StepSchedule) must call
+// `invalidate`.
+#[derive(Resource, Default)]
+pub struct StepDerivative {
+    valid: bool,
+    time: f64,
+    state: Vec<f64>,
+    inputs: Vec<f64>,
+    dstate: Vec<f64>,
+    pub reused: usize, // steps that started with the derivative of the last step
+}
+
+impl StepDerivative {
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    // keep the derivative in ws.k[0], at ws.state and `time`
+    pub(crate) fn store(&mut self, world: &World, ws: &SolverWorkspace, time: f64) {
+        self.valid = true;
+        self.time = time;
+        self.state.clone_from(&ws.state);
+        self.dstate.clone_from(&ws.k[0]);
+        self.inputs.clear();
+        if let Some(inputs) = world.get_resource::<InputRegistry>() {
+            self.inputs
+                .extend(inputs.inputs().iter().map(|input| (input.get)(world)));
+        }
+    }
+
+    // load the derivative into ws.k[0] if it is still the derivative at ws.state and `time`
+    pub(crate) fn load(&mut self, world: &World, ws: &mut SolverWorkspace, time: f64) {
+        let inputs = world
+            .get_resource::<InputRegistry>()
+            .map_or(&[][..], |inputs| inputs.inputs());
+        ws.start_derivative = self.valid
+            && self.time == time
+            && self.state == ws.state
+            && self.inputs.len() == inputs.len()
+            && inputs
+                .iter()
+                .zip(self.inputs.iter())
+                .all(|(input, value)| (input.get)(world) == *value);
+        if ws.start_derivative {
+            ws.k[0].copy_from_slice(&self.dstate);
+            self.reused += 1;
+        }
+    }
+}
+
+// Buffers reused by the solvers between steps. They are only reallocated when the number of state
+// values changes.
+#[derive(Resource)]
+pub(crate) struct SolverWorkspace {
+    pub(crate) state: Vec<f64>, // state being advanced
+    next: Vec<f64>,             // candidate new state
+    stage: Vec<f64>,            // state passed to a stage evaluation
+    k: [Vec<f64>; 7],           // stage derivatives
+    error: Vec<f64>,            // error estimate (RK45)
+    jacobian: DMatrix<f64>,     // Jacobian, then iteration matrix (implicit solvers)
+    pub(crate) layout: StateLayout,
+    // ws.k[0] already holds the derivative at the start of the step (see `first_stage`)
+    pub(crate) start_derivative: bool,
+}
+
+impl Default for SolverWorkspace {
+    fn default() -> Self {
+        SolverWorkspace {
+            state: Vec::new(),
+            next: Vec::new(),
+            stage: Vec::new(),
+            k: Default::default(),
+            error: Vec::new(),
+            jacobian: DMatrix::zeros(0, 0),
+            layout: StateLayout::default(),
+            start_derivative: false,
+        }
+    }
+}
+
+impl SolverWorkspace {
+    // copy in the current state of all registered Stateful types
+    pub(crate) fn read_states(&mut self, world: &World, registry: &StatefulRegistry) {
+        if self.resize(registry.len(world)) {
+            self.layout = registry.layout(world);
+        }
+        registry.read_states(world, &mut self.state);
+    }
+
+    // returns true if the number of state values changed
+    pub(crate) fn resize(&mut self, len: usize) -> bool {
+        if self.state.len() == len {
+            return false;
+        }
+        for buffer in [
+            &mut self.state,
+            &mut self.next,
+            &mut self.stage,
+            &mut self.error,
+        ]
+        .into_iter()
+        .chain(self.k.iter_mut())
+        {
+            buffer.resize(len, 0.);
+        }
+        self.jacobian = DMatrix::zeros(len, len);
+        true
+    }
+}
+
+// state values += scale * derivative values (StateVector::increment)
+type Increment = fn(&mut [f64], f64, &[f64]);
+
+// Indices of the position, velocity and other values in the combined state. Each position is paired
+// with the velocity that is its derivative.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StateLayout {
+    positions: Vec<(usize, usize)>, // (position, velocity)
+    velocities: Vec<usize>,
+    others: Vec<usize>,
+    // the increment (StateVector::increment) of each range of the state, by start and length
+    increments: Vec<(usize, usize, Increment)>,
+}
+
+impl StateLayout {
+    // (position, velocity) index pairs
+    pub(crate) fn positions(&self) -> &[(usize, usize)] {
+        &self.positions
+    }
+
+    pub(crate) fn add_increment(&mut self, start: usize, len: usize, increment: Increment) {
+        self.increments.push((start, len, increment));
+    }
+
+    // state += scale * dstate, with the increment of each Stateful type
+    pub(crate) fn increment(&self, state: &mut [f64], scale: f64, dstate: &[f64]) {
+        for &(start, len, increment) in self.increments.iter() {
+            let range = start..start + len;
+            increment(&mut state[range.clone()], scale, &dstate[range]);
+        }
+    }
+
+    // add the values of one state, starting at `start`
+    pub(crate) fn add(&mut self, start: usize, size: usize, kinds: &[StateKind]) {
+        if kinds.len() != size {
+            self.others.extend(start..start + size);
+            return;
+        }
+
+        let velocities: Vec<usize> = (0..size)
+            .filter(|i| kinds[*i] == StateKind::Velocity)
+            .collect();
+        let mut paired = velocities.iter();
+        for (i, kind) in kinds.iter().enumerate() {
+            match kind {
+                StateKind::Position => match paired.next() {
+                    Some(velocity) => self.positions.push((start + i, start + velocity)),
+                    None => self.others.push(start + i),
+                },
+                StateKind::Velocity => self.velocities.push(start + i),
+                StateKind::Other => self.others.push(start + i),
+            }
+        }
+    }
+}
+
+// Take one step from ws.state with the selected solver, and leave the components at the new state
+// (evaluated once more, so everything in the world is consistent with it, with the derivative in
+// ws.k[0] for the next step). A state or derivative that isn't finite fails the step.
+pub(crate) fn step(
+    solver: Solver,
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) -> Result<(), SolverError> {
+    match solver {
+        Solver::Euler => euler(physics, ws, t, dt),
+        Solver::Heun => heun(physics, ws, t, dt),
+        Solver::Midpoint => midpoint(physics, ws, t, dt),
+        Solver::RK4 => rk4(physics, ws, t, dt),
+        Solver::RK45 => rk45(physics, ws, t, dt)?,
+        Solver::BackwardEuler => backward_euler(physics, ws, t, dt)?,
+        Solver::Rosenbrock => rosenbrock(physics, ws, t, dt)?,
+        Solver::SymplecticEuler => symplectic_euler(physics, ws, t, dt),
+        Solver::VelocityVerlet => velocity_verlet(physics, ws, t, dt),
+    };
+
+    // the last evaluation of an RK45 step is already at the final state
+    if !matches!(solver, Solver::RK45) {
+        evaluate_state(physics, ws, t + dt);
+    }
+    let finite = ws
+        .state
+        .iter()
+        .chain(ws.k[0].iter())
+        .all(|value| value.is_finite());
+    if !finite {
+        return Err(SolverError::NonFinite { time: t + dt });
+    }
+    Ok(())
+}
+
+// Evaluate ws.state, which also assigns it to the components. The derivative is left in ws.k[0], so
+// it can be reused by the next step (see `StepDerivative`).
+pub(crate) fn evaluate_state(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64) {
+    physics.evaluate(&ws.state, t, &mut ws.k[0]);
+}
+
+// The derivative at the start of the step in ws.k[0]: evaluated, unless it is already there (reused
+// from the end of the last step). Returns true if it was evaluated.
+fn first_stage(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64) -> bool {
+    if std::mem::take(&mut ws.start_derivative) {
+        return false;
+    }
+    physics.evaluate(&ws.state, t, &mut ws.k[0]);
+    true
+}
+
+// y += a * x, for state derivatives (states are advanced with `StateLayout::increment`)
+fn axpy(y: &mut [f64], a: f64, x: &[f64]) {
+    for (y, x) in y.iter_mut().zip(x.iter()) {
+        *y += a * x;
+    }
+}
+
+// out = state + a * x, where x is a state derivative
+fn add_scaled(layout: &StateLayout, out: &mut [f64], state: &[f64], a: f64, x: &[f64]) {
+    out.copy_from_slice(state);
+    layout.increment(out, a, x);
+}
+
+fn norm(values: &[f64]) -> f64 {
+    values.iter().map(|value| value * value).sum::<f64>().sqrt()
+}
+
+pub(crate) fn euler(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64, dt: f64) {
+    first_stage(physics, ws, t);
+    ws.layout.increment(&mut ws.state, dt, &ws.k[0]);
+}
+
+pub(crate) fn heun(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64, dt: f64) {
+    first_stage(physics, ws, t);
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt, &ws.k[0]);
+    physics.evaluate(&ws.stage, t + dt, &mut ws.k[1]);
+
+    // the stage is reused for the averaged derivative
+    ws.stage.fill(0.);
+    axpy(&mut ws.stage, 0.5, &ws.k[0]);
+    axpy(&mut ws.stage, 0.5, &ws.k[1]);
+    ws.layout.increment(&mut ws.state, dt, &ws.stage);
+}
+
+pub(crate) fn midpoint(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64, dt: f64) {
+    first_stage(physics, ws, t);
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt * 0.5, &ws.k[0]);
+    physics.evaluate(&ws.stage, t + dt * 0.5, &mut ws.k[1]);
+    ws.layout.increment(&mut ws.state, dt, &ws.k[1]);
+}
+
+pub(crate) fn rk4(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64, dt: f64) {
+    first_stage(physics, ws, t);
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt * 0.5, &ws.k[0]);
+    physics.evaluate(&ws.stage, t + dt * 0.5, &mut ws.k[1]);
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt * 0.5, &ws.k[1]);
+    physics.evaluate(&ws.stage, t + dt * 0.5, &mut ws.k[2]);
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt, &ws.k[2]);
+    physics.evaluate(&ws.stage, t + dt, &mut ws.k[3]);
+
+    // the stage is reused for the weighted derivative
+    ws.stage.fill(0.);
+    axpy(&mut ws.stage, 1. / 6., &ws.k[0]);
+    axpy(&mut ws.stage, 1. / 3., &ws.k[1]);
+    axpy(&mut ws.stage, 1. / 3., &ws.k[2]);
+    axpy(&mut ws.stage, 1. / 6., &ws.k[3]);
+    ws.layout.increment(&mut ws.state, dt, &ws.stage);
+}
+
+// Semi-implicit (symplectic) Euler: the velocities are stepped with the current accelerations, then
+// the positions with the new velocities. Other values use explicit Euler.
+pub(crate) fn symplectic_euler(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) {
+    first_stage(physics, ws, t);
+
+    // the positions are advanced with the new velocities
+    let layout = &ws.layout;
+    ws.stage.copy_from_slice(&ws.k[0]);
+    for &(position, velocity) in layout.positions.iter() {
+        ws.stage[position] = ws.state[velocity] + dt * ws.k[0][velocity];
+    }
+    layout.increment(&mut ws.state, dt, &ws.stage);
+}
+
+// Velocity Verlet (kick-drift-kick). The accelerations at the end of the step are evaluated with the
+// half step velocities. Other values use Heun's method.
+pub(crate) fn velocity_verlet(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) {
+    first_stage(physics, ws, t);
+
+    // kick and drift: the positions move with the half step velocities, and the other values with
+    // their derivative at the start of the step
+    let layout = &ws.layout;
+    ws.next.copy_from_slice(&ws.k[0]);
+    for &i in layout.velocities.iter() {
+        ws.next[i] = 0.5 * ws.k[0][i];
+    }
+    for &(position, velocity) in layout.positions.iter() {
+        ws.next[position] = ws.state[velocity] + 0.5 * dt * ws.k[0][velocity];
+    }
+    add_scaled(layout, &mut ws.stage, &ws.state, dt, &ws.next);
+
+    // kick: the velocities (and other values) use the average of both derivatives
+    physics.evaluate(&ws.stage, t + dt, &mut ws.k[1]);
+    for &i in layout.velocities.iter().chain(layout.others.iter()) {
+        ws.next[i] = 0.5 * (ws.k[0][i] + ws.k[1][i]);
+    }
+    layout.increment(&mut ws.state, dt, &ws.next);
+}
+
+// Dormand-Prince 5(4) coefficients
+const DP_C: [f64; 7] = [0., 1. / 5., 3. / 10., 4. / 5., 8. / 9., 1., 1.];
+const DP_A: [[f64; 6]; 7] = [
+    [0., 0., 0., 0., 0., 0.],
+    [1. / 5., 0., 0., 0., 0., 0.],
+    [3. / 40., 9. / 40., 0., 0., 0., 0.],
+    [44. / 45., -56. / 15., 32. / 9., 0., 0., 0.],
+    [
+        19372. / 6561.,
+        -25360. / 2187.,
+        64448. / 6561.,
+        -212. / 729.,
+        0.,
+        0.,
+    ],
+    [
+        9017. / 3168.,
+        -355. / 33.,
+        46732. / 5247.,
+        49. / 176.,
+        -5103. / 18656.,
+        0.,
+    ],
+    [
+        35. / 384.,
+        0.,
+        500. / 1113.,
+        125. / 192.,
+        -2187. / 6784.,
+        11. / 84.,
+    ],
+];
+// difference between the 5th and 4th order weights, used for the error estimate
+const DP_E: [f64; 7] = [
+    71. / 57600.,
+    0.,
+    -71. / 16695.,
+    71. / 1920.,
+    -17253. / 339200.,
+    22. / 525.,
+    -1. / 40.,
+];
+
+// one Dormand-Prince step from `ws.state`, using the derivative in `ws.k[0]`. The 5th order solution
+// is left in `ws.next`, the error estimate in `ws.error`, and the derivative at the new state (first
+// same as last) in `ws.k[6]`, which can be reused as the first stage of the next step.
+fn dormand_prince_step(physics: &mut StateEvaluator, ws: &mut SolverWorkspace, t: f64, dt: f64) {
+    for stage in 1..7 {
+        // the error buffer holds the weighted derivative of the stage
+        ws.error.fill(0.);
+        for (a, k) in DP_A[stage][..stage].iter().zip(ws.k.iter()) {
+            if *a != 0. {
+                axpy(&mut ws.error, *a, k);
+            }
+        }
+        add_scaled(&ws.layout, &mut ws.next, &ws.state, dt, &ws.error);
+        // the 7th stage is evaluated at the 5th order solution
+        physics.evaluate(&ws.next, t + DP_C[stage] * dt, &mut ws.k[stage]);
+    }
+
+    ws.error.fill(0.);
+    for (e, k) in DP_E.iter().zip(ws.k.iter()) {
+        if *e != 0. {
+            axpy(&mut ws.error, e * dt, k);
+        }
+    }
+}
+
+pub(crate) fn rk45(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) -> Result<(), SolverError> {
+    let mut adaptive = physics
+        .world
+        .get_resource_or_insert_with(AdaptiveStep::default)
+        .clone();
+
+    const SAFETY: f64 = 0.9;
+    const MIN_FACTOR: f64 = 0.2;
+    const MAX_FACTOR: f64 = 5.0;
+
+    first_stage(physics, ws, t);
+    let mut elapsed = 0.;
+    while dt - elapsed > 1e-12 * dt {
+        // don't step past the end of the fixed time step
+        let remaining = dt - elapsed;
+        let step = adaptive.dt.clamp(adaptive.min_dt, adaptive.max_dt);
+        let truncated = step >= remaining;
+        let step = step.min(remaining);
+
+        dormand_prince_step(physics, ws, t + elapsed, step);
+        let error_norm = adaptive.error_norm(&ws.state, &ws.next, &ws.error);
+        adaptive.error = error_norm;
+
+        // a NaN or infinite derivative gives an error that can't be reduced by any step size
+        if !error_norm.is_finite() {
+            if step <= adaptive.min_dt {
+                physics.world.insert_resource(adaptive);
+                return Err(SolverError::NonFinite { time: t + elapsed });
+            }
+            adaptive.rejected += 1;
+            adaptive.dt = (step * MIN_FACTOR).max(adaptive.min_dt);
+            continue;
+        }
+
+        let factor = if error_norm == 0. {
+            MAX_FACTOR
+        } else {
+            (SAFETY * error_norm.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR)
+        };
+        let proposed = (step * factor).clamp(adaptive.min_dt, adaptive.max_dt);
+
+        if error_norm <= 1. || step <= adaptive.min_dt {
+            // accept the step (steps at the minimum size are always accepted)
+            std::mem::swap(&mut ws.state, &mut ws.next);
+            ws.k.swap(0, 6);
+            elapsed += step;
+            adaptive.accepted += 1;
+
+            // a step shortened to hit the end of the interval says little about the best step size
+            if !truncated || proposed < adaptive.dt {
+                adaptive.dt = proposed;
+            }
+        } else {
+            adaptive.rejected += 1;
+            adaptive.dt = proposed.min(step);
+        }
+    }
+
+    physics.world.insert_resource(adaptive);
+    Ok(())
+}
+
+// Finite difference Jacobian of the state derivative at `ws.state` (with derivative `ws.k[0]`), one
+// physics evaluation per state value. It is then turned into the iteration matrix I - scale * J.
+fn iteration_matrix(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    perturbation: f64,
+    scale: f64,
+) {
+    let n = ws.state.len();
+    ws.stage.copy_from_slice(&ws.state);
+    for j in 0..n {
+        let delta = perturbation * ws.state[j].abs().max(1.);
+        ws.stage[j] = ws.state[j] + delta;
+        physics.evaluate(&ws.stage, t, &mut ws.k[1]);
+        for i in 0..n {
+            ws.jacobian[(i, j)] = -scale * (ws.k[1][i] - ws.k[0][i]) / delta;
+        }
+        ws.stage[j] = ws.state[j];
+    }
+    for i in 0..n {
+        ws.jacobian[(i, i)] += 1.;
+    }
+}
+
+pub(crate) fn backward_euler(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) -> Result<(), SolverError> {
+    let mut settings = physics
+        .world
+        .get_resource_or_insert_with(ImplicitSettings::default)
+        .clone();
+    let n = ws.state.len();
+
+    // simplified newton iteration - the Jacobian is only evaluated once, at the start of the step
+    let evaluated = first_stage(physics, ws, t);
+    iteration_matrix(physics, ws, t, settings.perturbation, dt);
+    settings.evaluations += n + evaluated as usize;
+    let lu = ws.jacobian.clone().lu();
+    if !lu.is_invertible() {
+        settings.converged = false;
+        physics.world.insert_resource(settings);
+        return Err(SolverError::Singular { time: t });
+    }
+
+    // The unknown is the derivative d of the step, with the new state y + dt * d (so the state is
+    // only changed by increments). It starts from the explicit Euler prediction.
+    let (k0, k12) = ws.k.split_at_mut(1);
+    k12[1].copy_from_slice(&k0[0]);
+    settings.converged = false;
+    settings.iterations = 0;
+    for iteration in 0..settings.max_iterations {
+        add_scaled(&ws.layout, &mut ws.next, &ws.state, dt, &ws.k[2]);
+        physics.evaluate(&ws.next, t + dt, &mut ws.k[1]);
+        settings.evaluations += 1;
+
+        // residual, solved in place for the (negative) update
+        for i in 0..n {
+            ws.stage[i] = ws.k[2][i] - ws.k[1][i];
+        }
+        lu.solve_mut(&mut DVectorViewMut::from_slice(&mut ws.stage, n));
+        for (d, update) in ws.k[2].iter_mut().zip(ws.stage.iter()) {
+            *d -= update;
+        }
+
+        settings.iterations = iteration + 1;
+        if dt * norm(&ws.stage) < settings.tolerance * (1. + norm(&ws.next)) {
+            settings.converged = true;
+            break;
+        }
+    }
+
+    let (converged, iterations) = (settings.converged, settings.iterations);
+    physics.world.insert_resource(settings);
+    if !converged {
+        return Err(SolverError::NotConverged {
+            time: t,
+            iterations,
+        });
+    }
+    ws.layout.increment(&mut ws.state, dt, &ws.k[2]);
+    Ok(())
+}
+
+// two stage Rosenbrock method (ROS2). It is L-stable like backward Euler, but needs only linear solves.
+pub(crate) fn rosenbrock(
+    physics: &mut StateEvaluator,
+    ws: &mut SolverWorkspace,
+    t: f64,
+    dt: f64,
+) -> Result<(), SolverError> {
+    let mut settings = physics
+        .world
+        .get_resource_or_insert_with(ImplicitSettings::default)
+        .clone();
+    let gamma = 1. + 1. / 2_f64.sqrt();
+    let n = ws.state.len();
+
+    let evaluated = first_stage(physics, ws, t);
+    iteration_matrix(physics, ws, t, settings.perturbation, gamma * dt);
+    settings.evaluations += n + evaluated as usize;
+    let lu = ws.jacobian.clone().lu();
+    if !lu.is_invertible() {
+        settings.converged = false;
+        physics.world.insert_resource(settings);
+        return Err(SolverError::Singular { time: t });
+    }
+
+    // k1 = M^-1 f(y)
+    let (k0, k1) = ws.k.split_at_mut(1);
+    k1[0].copy_from_slice(&k0[0]);
+    lu.solve_mut(&mut DVectorViewMut::from_slice(&mut ws.k[1], n));
+
+    // k2 = M^-1 (f(y + dt k1) - 2 k1)
+    add_scaled(&ws.layout, &mut ws.stage, &ws.state, dt, &ws.k[1]);
+    physics.evaluate(&ws.stage, t + dt, &mut ws.k[2]);
+    settings.evaluations += 1;
+    let (k1, k2) = ws.k.split_at_mut(2);
+    axpy(&mut k2[0], -2., &k1[1]);
+    lu.solve_mut(&mut DVectorViewMut::from_slice(&mut k2[0], n));
+
+    ws.stage.fill(0.);
+    axpy(&mut ws.stage, 1.5, &ws.k[1]);
+    axpy(&mut ws.stage, 0.5, &ws.k[2]);
+    ws.layout.increment(&mut ws.state, dt, &ws.stage);
+
+    settings.iterations = 0;
+    settings.converged = true;
+    physics.world.insert_resource(settings);
+    Ok(())
+}
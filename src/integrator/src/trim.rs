@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use nalgebra::{DMatrix, DVector};
+
+use crate::{SimTime, StateEvaluator, StatefulRegistry};
+
+// Settings of the search for a static equilibrium (see `trim`). When this resource is inserted
+// before startup, the initial state is trimmed before the first step.
+#[derive(Resource, Clone, Debug)]
+pub struct TrimSettings {
+    // positions held at their initial values: state names (e.g. "steer_fl.q"), or the names of
+    // Stateful components (e.g. "wheel_fl") to hold all of their positions
+    pub fixed: Vec<String>,
+    // additional (position, velocity) pairs to search, by state name, for states whose positions
+    // aren't paired with velocities, e.g. ("chassis.z", "chassis.vz") of a floating joint
+    pub pairs: Vec<(String, String)>,
+    pub tolerance: f64, // largest residual acceleration of a converged solve
+    pub max_iterations: usize,
+    // largest change of any position in one iteration, as a full Newton step can overshoot into a
+    // different regime (e.g. lift a tire off the ground)
+    pub max_step: f64,
+    pub perturbation: f64, // finite difference step, relative to each position (minimum of 1)
+}
+
+impl TrimSettings {
+    pub fn new(fixed: &[&str]) -> Self {
+        TrimSettings {
+            fixed: fixed.iter().map(|name| name.to_string()).collect(),
+            pairs: Vec::new(),
+            tolerance: 1e-6,
+            max_iterations: 50,
+            max_step: 0.01,
+            perturbation: 1e-6,
+        }
+    }
+
+    pub fn with_pairs(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.pairs.extend(
+            pairs
+                .iter()
+                .map(|(position, velocity)| (position.to_string(), velocity.to_string())),
+        );
+        self
+    }
+
+    fn is_fixed(&self, name: &str) -> bool {
+        self.fixed.iter().any(|fixed| {
+            name == fixed
+                || (name.starts_with(fixed.as_str()) && name[fixed.len()..].starts_with('.'))
+        })
+    }
+}
+
+impl Default for TrimSettings {
+    fn default() -> Self {
+        TrimSettings::new(&[])
+    }
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct TrimResult {
+    pub converged: bool,
+    pub iterations: usize,
+    // the acceleration of every free position, by the name of its velocity
+    pub residuals: Vec<(String, f64)>,
+}
+
+impl TrimResult {
+    pub fn max_residual(&self) -> f64 {
+        self.residuals
+            .iter()
+            .fold(0., |max, (_, residual)| residual.abs().max(max))
+    }
+}
+
+// Search for positions where all accelerations are zero, starting from the current state, using a
+// Newton iteration with finite difference derivatives. The free positions are the positions paired
+// with velocities that aren't fixed, and the `pairs`. The velocities of the free positions are
+// set to zero, while the fixed positions (and their accelerations) are left out of the search and
+// keep their velocities (e.g. a spinning wheel). The evaluations only run the PhysicsSchedule,
+// which has no side effects, so the accelerations only depend on the positions. The world is left
+// at the final state.
+pub fn trim(world: &mut World, settings: &TrimSettings) -> TrimResult {
+    let time = world.resource::<SimTime>().time();
+
+    world.resource_scope(|world: &mut World, registry: Mut<StatefulRegistry>| {
+        let names = registry.names(world);
+        let n = registry.len(world);
+        let layout = registry.layout(world);
+        let mut free: Vec<(usize, usize)> = layout
+            .positions()
+            .iter()
+            .copied()
+            .filter(|(position, _)| !settings.is_fixed(&names[*position]))
+            .collect();
+        let index = |name: &str| names.iter().position(|state| state == name);
+        for (position, velocity) in settings.pairs.iter() {
+            match (index(position), index(velocity)) {
+                (Some(position), Some(velocity)) => free.push((position, velocity)),
+                _ => warn!("trim: there is no state {position} or {velocity}"),
+            }
+        }
+
+        let mut state = vec![0.; n];
+        registry.read_states(world, &mut state);
+        for &(_, velocity) in free.iter() {
+            state[velocity] = 0.;
+        }
+
+        let mut physics = StateEvaluator {
+            world,
+            registry: &registry,
+            stage: 0,
+        };
+        let mut dstate = vec![0.; n];
+        let mut accelerations = |physics: &mut StateEvaluator, state: &[f64]| {
+            physics.evaluate(state, time, &mut dstate);
+            DVector::from_iterator(
+                free.len(),
+                free.iter().map(|(_, velocity)| dstate[*velocity]),
+            )
+        };
+
+        let mut residual = accelerations(&mut physics, &state);
+        let mut jacobian = DMatrix::zeros(free.len(), free.len());
+        let mut trial = state.clone();
+        let mut iterations = 0;
+        while residual.amax() > settings.tolerance && iterations < settings.max_iterations {
+            iterations += 1;
+
+            for (j, &(position, _)) in free.iter().enumerate() {
+                let delta = settings.perturbation * state[position].abs().max(1.);
+                trial[position] = state[position] + delta;
+                let plus = accelerations(&mut physics, &trial);
+                trial[position] = state[position] - delta;
+                let minus = accelerations(&mut physics, &trial);
+                trial[position] = state[position];
+                jacobian.set_column(j, &((plus - minus) / (2. * delta)));
+            }
+
+            // least squares step, as some positions may not change any acceleration (e.g. the
+            // rotation of a wheel)
+            let svd = jacobian.clone().svd(true, true);
+            let eps = 1e-10 * svd.singular_values.max();
+            let Ok(mut step) = svd.solve(&(-&residual), eps) else {
+                break;
+            };
+            let largest = step.amax();
+            if largest > settings.max_step {
+                step *= settings.max_step / largest;
+            }
+
+            // shorten the step until the residual decreases
+            let mut scale = 1.;
+            let mut improved = false;
+            while scale > 1e-3 {
+                for (j, &(position, _)) in free.iter().enumerate() {
+                    trial[position] = state[position] + scale * step[j];
+                }
+                let next = accelerations(&mut physics, &trial);
+                if next.norm() < residual.norm() {
+                    state.copy_from_slice(&trial);
+                    residual = next;
+                    improved = true;
+                    break;
+                }
+                scale *= 0.5;
+            }
+            trial.copy_from_slice(&state);
+            if !improved {
+                break;
+            }
+        }
+
+        // leave the world at the trimmed state. The search moves single values, so states on a
+        // manifold (e.g. a quaternion) are projected back onto it by a zero increment.
+        layout.increment(&mut state, 0., &vec![0.; n]);
+        let residual = accelerations(&mut physics, &state);
+
+        TrimResult {
+            converged: residual.amax() <= settings.tolerance,
+            iterations,
+            residuals: free
+                .iter()
+                .zip(residual.iter())
+                .map(|((_, velocity), residual)| (names[*velocity].clone(), *residual))
+                .collect(),
+        }
+    })
+}
+
+// Trim the initial state with the TrimSettings resource, and insert the TrimResult
+pub fn trim_system(world: &mut World) {
+    let settings = world.resource::<TrimSettings>().clone();
+    let result = trim(world, &settings);
+    if result.converged {
+        info!(
+            "trim converged after {} iterations (largest residual acceleration {:.3e})",
+            result.iterations,
+            result.max_residual()
+        );
+    } else {
+        warn!(
+            "trim did not converge after {} iterations (largest residual acceleration {:.3e})",
+            result.iterations,
+            result.max_residual()
+        );
+        for (name, residual) in result.residuals.iter() {
+            if residual.abs() > settings.tolerance {
+                warn!("  residual acceleration of {name}: {residual:.3e}");
+            }
+        }
+    }
+    world.insert_resource(result);
+}
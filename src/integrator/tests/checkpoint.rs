@@ -0,0 +1,106 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    checkpoint::{restore_checkpoint, save_checkpoint, Checkpoint},
+    events::{CrossingAction, CrossingDirection, ZeroCrossingAppExt, ZeroCrossings},
+    recorder::{record_system, ChannelWriter, Recorder, RecorderAppExt},
+    AdaptiveStep, Solver, StepSchedule,
+};
+use common::{oscillator, oscillator_app, run_steps, Oscillator};
+
+fn record_oscillator(world: &mut World, writer: &mut ChannelWriter) {
+    for oscillator in world.query::<&Oscillator>().iter(world) {
+        writer.write("oscillator", "x", oscillator.x);
+    }
+}
+
+fn position(world: &mut World) -> f64 {
+    world.query::<&Oscillator>().single(world).x
+}
+
+// an RK45 simulation with a recorder and a zero crossing, so all of them have to be restored
+fn recorded_app() -> App {
+    let mut app = oscillator_app(Solver::RK45, 0.01, 1.);
+    app.add_recorder_source(record_oscillator)
+        .add_zero_crossing(
+            "x",
+            position,
+            CrossingDirection::Either,
+            CrossingAction::Event,
+        )
+        .add_systems(StepSchedule, record_system)
+        .insert_resource(Recorder::new(3))
+        .insert_resource(AdaptiveStep::new(1e-8, 1e-8, 1e-6, 0.01));
+    app
+}
+
+// everything that should be the same after a rewind
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    x: f64,
+    v: f64,
+    accepted: usize,
+    rejected: usize,
+    dt: f64,
+    crossings: Vec<f64>,
+    samples: Vec<f64>,
+}
+
+fn outcome(app: &mut App) -> Outcome {
+    let oscillator = oscillator(app);
+    let adaptive = app.world.resource::<AdaptiveStep>().clone();
+    let crossings = app.world.resource::<ZeroCrossings>();
+    let recorder = app.world.resource::<Recorder>();
+    Outcome {
+        x: oscillator.x,
+        v: oscillator.v,
+        accepted: adaptive.accepted,
+        rejected: adaptive.rejected,
+        dt: adaptive.dt,
+        crossings: crossings
+            .occurred()
+            .iter()
+            .map(|event| event.time)
+            .collect(),
+        samples: recorder.recording().time.clone(),
+    }
+}
+
+#[test]
+fn rewind_repeats_the_simulation() {
+    let mut app = recorded_app();
+    run_steps(&mut app, 100);
+    let checkpoint = save_checkpoint(&mut app.world);
+    run_steps(&mut app, 150);
+    let original = outcome(&mut app);
+    assert_eq!(original.crossings.len(), 1); // at pi/2
+
+    restore_checkpoint(&mut app.world, &checkpoint);
+    run_steps(&mut app, 150);
+    assert_eq!(outcome(&mut app), original);
+}
+
+#[test]
+fn checkpoint_file_restores_into_a_new_simulation() {
+    let mut app = recorded_app();
+    run_steps(&mut app, 100);
+    let path = std::env::temp_dir().join("bevy_integrator_checkpoint_test.json");
+    save_checkpoint(&mut app.world).save(&path).unwrap();
+    run_steps(&mut app, 150);
+    let original = outcome(&mut app);
+
+    let checkpoint = Checkpoint::load(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+    // the keys are stable names, not type names
+    for key in ["oscillators", "solver", "zero_crossings", "recorder"] {
+        assert!(checkpoint.entries.contains_key(key), "no {key} entry");
+    }
+
+    let mut restored = recorded_app();
+    restore_checkpoint(&mut restored.world, &checkpoint);
+    run_steps(&mut restored, 150);
+    let outcome = outcome(&mut restored);
+    assert_eq!((outcome.x, outcome.v), (original.x, original.v));
+    assert_eq!(outcome.crossings, original.crossings);
+}
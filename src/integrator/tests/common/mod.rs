@@ -0,0 +1,144 @@
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    integrator_schedule, ExitEvent, PhysicsSchedule, PhysicsScheduleExt, SimTime, Solver,
+    StageTime, StateKind, StateVector, Stateful, StatefulAppExt,
+};
+
+// A unit mass on a spring, x'' = -stiffness * x, whose exact solution is known
+#[derive(Component, Debug, Clone)]
+pub struct Oscillator {
+    pub x: f64,
+    pub v: f64,
+    pub a: f64,
+    pub stiffness: f64,
+}
+
+#[derive(Clone)]
+pub struct OscillatorState {
+    pub x: f64,
+    pub v: f64,
+}
+
+impl StateVector for OscillatorState {
+    const SIZE: usize = 2;
+    const KINDS: &'static [StateKind] = &[StateKind::Position, StateKind::Velocity];
+    const NAMES: &'static [&'static str] = &["x", "v"];
+    fn to_values(&self, values: &mut [f64]) {
+        values[0] = self.x;
+        values[1] = self.v;
+    }
+    fn from_values(values: &[f64]) -> Self {
+        OscillatorState {
+            x: values[0],
+            v: values[1],
+        }
+    }
+}
+
+impl Stateful for Oscillator {
+    type State = OscillatorState;
+    const CHECKPOINT_KEY: &'static str = "oscillators";
+    fn get_state(&self) -> Self::State {
+        OscillatorState {
+            x: self.x,
+            v: self.v,
+        }
+    }
+    fn set_state(&mut self, state: &Self::State) {
+        self.x = state.x;
+        self.v = state.v;
+    }
+    fn get_dstate(&self) -> Self::State {
+        OscillatorState {
+            x: self.v,
+            v: self.a,
+        }
+    }
+    fn set_dstate(&mut self, dstate: Self::State) {
+        self.v = dstate.x;
+        self.a = dstate.v;
+    }
+    fn reset(&mut self) {
+        self.a = 0.;
+    }
+    fn get_name(&self) -> String {
+        "oscillator".to_string()
+    }
+}
+
+// the derivative becomes NaN from this time on
+#[derive(Resource)]
+pub struct NanAfter(pub f64);
+
+// an external force on the oscillators (an input), applied when the resource exists
+#[derive(Resource, Default)]
+pub struct ExternalForce(pub f64);
+
+// the number of physics evaluations, counted when the resource exists
+#[derive(Resource, Default)]
+pub struct Evaluations(pub usize);
+
+fn spring_system(
+    mut oscillators: Query<&mut Oscillator>,
+    evaluations: Option<ResMut<Evaluations>>,
+    force: Option<Res<ExternalForce>>,
+) {
+    if let Some(mut evaluations) = evaluations {
+        evaluations.0 += 1;
+    }
+    let force = force.map_or(0., |force| force.0);
+    for mut oscillator in oscillators.iter_mut() {
+        oscillator.a = -oscillator.stiffness * oscillator.x + force;
+    }
+}
+
+fn nan_system(
+    mut oscillators: Query<&mut Oscillator>,
+    time: Res<StageTime>,
+    nan: Option<Res<NanAfter>>,
+) {
+    if nan.is_some_and(|nan| time.time >= nan.0) {
+        for mut oscillator in oscillators.iter_mut() {
+            oscillator.a = f64::NAN;
+        }
+    }
+}
+
+// an app with one oscillator (x = 1, v = 0), ready to step
+pub fn oscillator_app(solver: Solver, dt: f64, stiffness: f64) -> App {
+    let mut schedule = Schedule::new(PhysicsSchedule);
+    schedule.add_physics_systems((spring_system,), (nan_system,));
+
+    let mut app = App::new();
+    app.add_schedule(schedule)
+        .add_stateful::<Oscillator>()
+        .add_event::<ExitEvent>()
+        .insert_resource(SimTime::new(dt, 0., None))
+        .insert_resource(solver);
+    app.world.spawn(Oscillator {
+        x: 1.,
+        v: 0.,
+        a: 0.,
+        stiffness,
+    });
+    app.update(); // startup, which sets up the state storage
+    app
+}
+
+pub fn run_steps(app: &mut App, steps: usize) {
+    for _ in 0..steps {
+        integrator_schedule(&mut app.world);
+    }
+}
+
+pub fn oscillator(app: &mut App) -> Oscillator {
+    app.world.query::<&Oscillator>().single(&app.world).clone()
+}
+
+// the exact position and velocity at time t
+pub fn exact(stiffness: f64, t: f64) -> (f64, f64) {
+    let w = stiffness.sqrt();
+    ((w * t).cos(), -w * (w * t).sin())
+}
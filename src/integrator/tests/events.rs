@@ -0,0 +1,86 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    events::{CrossingAction, CrossingDirection, ZeroCrossingAppExt, ZeroCrossings},
+    AdaptiveStep, Solver,
+};
+use common::{oscillator, oscillator_app, run_steps, Oscillator};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_3};
+
+fn position(world: &mut World) -> f64 {
+    world.query::<&Oscillator>().single(world).x
+}
+
+// RK45 steps of 10 ms, until just after x = cos(t) crosses zero at pi / 2
+fn run(action: Option<CrossingAction>) -> App {
+    let mut app = oscillator_app(Solver::RK45, 0.01, 1.);
+    app.insert_resource(AdaptiveStep::new(1e-10, 1e-10, 1e-6, 0.01));
+    if let Some(action) = action {
+        app.add_zero_crossing("x", position, CrossingDirection::Falling, action);
+    }
+    run_steps(&mut app, 160);
+    app
+}
+
+#[test]
+fn crossing_is_located_within_the_tolerance() {
+    let mut app = run(Some(CrossingAction::Event));
+    let crossings = app.world.resource::<ZeroCrossings>();
+    assert_eq!(crossings.occurred().len(), 1);
+    let error = crossings.occurred()[0].time - FRAC_PI_2;
+    assert!(error.abs() < crossings.tolerance * 0.01, "error {error}");
+
+    // the bisection doesn't change the result of the step, or the solver statistics
+    let mut plain = run(None);
+    let (x, plain_x) = (oscillator(&mut app).x, oscillator(&mut plain).x);
+    assert_eq!(x, plain_x);
+    let adaptive = app.world.resource::<AdaptiveStep>();
+    let plain_adaptive = plain.world.resource::<AdaptiveStep>();
+    assert_eq!(adaptive.accepted, plain_adaptive.accepted);
+    assert_eq!(adaptive.rejected, plain_adaptive.rejected);
+    assert_eq!(adaptive.dt, plain_adaptive.dt);
+}
+
+#[test]
+fn stop_crossing_leaves_the_state_at_the_crossing() {
+    let mut app = run(Some(CrossingAction::Stop));
+    let stop_time = app.world.resource::<ZeroCrossings>().stop_time().unwrap();
+    assert!(
+        (stop_time - FRAC_PI_2).abs() < 1e-6,
+        "stopped at {stop_time}"
+    );
+    let x = oscillator(&mut app).x;
+    assert!(x.abs() < 1e-6, "x = {x}");
+}
+
+#[test]
+fn event_functions_capture_their_thresholds() {
+    let mut app = oscillator_app(Solver::RK45, 0.01, 1.);
+    app.insert_resource(AdaptiveStep::new(1e-10, 1e-10, 1e-6, 0.01));
+    for threshold in [0.5, -0.5] {
+        app.add_zero_crossing(
+            &format!("x_{threshold}"),
+            move |world| position(world) - threshold,
+            CrossingDirection::Falling,
+            CrossingAction::Event,
+        );
+    }
+    run_steps(&mut app, 250);
+
+    // x = cos(t) crosses 0.5 at pi / 3, and -0.5 at 2 pi / 3
+    let crossings = app.world.resource::<ZeroCrossings>();
+    let occurred: Vec<_> = crossings
+        .occurred()
+        .iter()
+        .map(|event| (event.name.as_str(), event.time))
+        .collect();
+    assert_eq!(occurred.len(), 2, "{occurred:?}");
+    assert_eq!(occurred[0].0, "x_0.5");
+    assert!((occurred[0].1 - FRAC_PI_3).abs() < 1e-6, "{occurred:?}");
+    assert_eq!(occurred[1].0, "x_-0.5");
+    assert!(
+        (occurred[1].1 - 2. * FRAC_PI_3).abs() < 1e-6,
+        "{occurred:?}"
+    );
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use bevy_integrator::{ImplicitSettings, Solver, SolverError, SolverFailure};
+use common::{exact, oscillator, oscillator_app, run_steps};
+
+// an oscillator of 100 rad/s (like the tire contact), after 2 s with steps of 0.05 s, far beyond
+// the stability limit of the explicit solvers
+fn stiff_amplitude(solver: Solver) -> f64 {
+    let stiffness = 1e4;
+    let mut app = oscillator_app(solver, 0.05, stiffness);
+    run_steps(&mut app, 40);
+    assert!(app.world.get_resource::<SolverFailure>().is_none());
+    let oscillator = oscillator(&mut app);
+    oscillator.x.hypot(oscillator.v / stiffness.sqrt())
+}
+
+#[test]
+fn stiff_oscillator_stays_bounded() {
+    let explicit = stiff_amplitude(Solver::RK4);
+    assert!(explicit > 1e6, "RK4 amplitude {explicit}");
+    // the implicit solvers damp the unresolved oscillation
+    let amplitude = stiff_amplitude(Solver::BackwardEuler);
+    assert!(amplitude <= 1., "backward Euler amplitude {amplitude}");
+    let amplitude = stiff_amplitude(Solver::Rosenbrock);
+    assert!(amplitude <= 1., "Rosenbrock amplitude {amplitude}");
+}
+
+// global error at t = 2 s with steps of `dt`
+fn error(solver: Solver, dt: f64) -> f64 {
+    let mut app = oscillator_app(solver, dt, 1.);
+    run_steps(&mut app, (2. / dt).round() as usize);
+    let oscillator = oscillator(&mut app);
+    let (x, v) = exact(1., 2.);
+    (oscillator.x - x).hypot(oscillator.v - v)
+}
+
+#[test]
+fn convergence_order() {
+    // halving the step reduces the error by 2 (backward Euler) and 4 (Rosenbrock)
+    let ratio = error(Solver::BackwardEuler, 0.01) / error(Solver::BackwardEuler, 0.005);
+    assert!(
+        (1.8..2.2).contains(&ratio),
+        "backward Euler error ratio {ratio}"
+    );
+    let ratio = error(Solver::Rosenbrock, 0.01) / error(Solver::Rosenbrock, 0.005);
+    assert!(
+        (3.6..4.4).contains(&ratio),
+        "Rosenbrock error ratio {ratio}"
+    );
+}
+
+#[test]
+fn unconverged_step_stops_the_simulation() {
+    let mut app = oscillator_app(Solver::BackwardEuler, 0.01, 1.);
+    app.world.insert_resource(ImplicitSettings::new(1e-8, 1));
+    run_steps(&mut app, 10);
+
+    let failure = app
+        .world
+        .get_resource::<SolverFailure>()
+        .expect("the step failed");
+    assert_eq!(
+        failure.0,
+        SolverError::NotConverged {
+            time: 0.,
+            iterations: 1
+        }
+    );
+    // the state is left at the start of the step
+    assert_eq!(oscillator(&mut app).x, 1.);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use bevy_integrator::{input::InputAppExt, linearize::linearize, Solver};
+use common::{oscillator_app, ExternalForce};
+use nalgebra::DMatrix;
+
+#[test]
+fn spring_mass() {
+    let stiffness = 4.;
+    let mut app = oscillator_app(Solver::RK4, 0.01, stiffness);
+    app.insert_resource(ExternalForce(0.5)).add_input(
+        "force",
+        |world| world.resource::<ExternalForce>().0,
+        |world, force| world.resource_mut::<ExternalForce>().0 = force,
+    );
+
+    let linear = linearize(&mut app.world, 1e-6).unwrap();
+    assert_eq!(linear.states, ["oscillator.x", "oscillator.v"]);
+    assert_eq!(linear.inputs, ["force"]);
+    // x' = v, v' = -stiffness * x + force
+    let a = DMatrix::from_row_slice(2, 2, &[0., 1., -stiffness, 0.]);
+    let b = DMatrix::from_row_slice(2, 1, &[0., 1.]);
+    assert!((&linear.a - a).amax() < 1e-6, "{}", linear.a);
+    assert!((&linear.b - b).amax() < 1e-6, "{}", linear.b);
+    // at x = 1, v = 0
+    let dstate = [0., -stiffness + 0.5];
+    assert!((linear.dstate[0] - dstate[0]).abs() < 1e-9);
+    assert!((linear.dstate[1] - dstate[1]).abs() < 1e-9);
+    // the world is left at the operating point
+    assert_eq!(app.world.resource::<ExternalForce>().0, 0.5);
+}
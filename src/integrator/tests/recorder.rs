@@ -0,0 +1,64 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    initial_evaluation_system,
+    recorder::{record_system, Channel, ChannelWriter, Recorder, RecorderAppExt, Recording},
+    Solver, StepSchedule,
+};
+use common::{oscillator_app, run_steps, Oscillator};
+
+fn record_oscillator(world: &mut World, writer: &mut ChannelWriter) {
+    for oscillator in world.query::<&Oscillator>().iter(world) {
+        writer.write("oscillator", "x", oscillator.x);
+        writer.write("oscillator", "a", oscillator.a);
+    }
+}
+
+#[test]
+fn csv_header_is_escaped() {
+    let channel = |name: &str| Channel {
+        name: name.to_string(),
+        values: vec![1.],
+    };
+    let recording = Recording {
+        time: vec![0.],
+        channels: vec![
+            channel("plain.x"),
+            channel("a,b.x"),
+            channel("say \"hi\".x"),
+        ],
+    };
+    let path = std::env::temp_dir().join(format!("recorder_test_{}.csv", std::process::id()));
+    recording.write_csv(&path).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("time,plain.x,\"a,b.x\",\"say \"\"hi\"\".x\"")
+    );
+    assert_eq!(lines.next().map(|line| line.split(',').count()), Some(4));
+}
+
+#[test]
+fn initial_state_is_recorded() {
+    let dt = 0.01;
+    let mut app = oscillator_app(Solver::RK4, dt, 4.);
+    app.add_recorder_source(record_oscillator)
+        .add_systems(StepSchedule, record_system)
+        .insert_resource(Recorder::new(2));
+
+    // like the PostStartup systems of the rigid body plugin
+    initial_evaluation_system(&mut app.world);
+    record_system(&mut app.world);
+    run_steps(&mut app, 4);
+
+    let recording = app.world.resource::<Recorder>().recording();
+    let times: Vec<f64> = recording.time.iter().map(|t| (t / dt).round()).collect();
+    assert_eq!(times, [0., 2., 4.]);
+    // the derived values are consistent with the initial state
+    assert_eq!(recording.channel("oscillator.x").unwrap().values[0], 1.);
+    assert_eq!(recording.channel("oscillator.a").unwrap().values[0], -4.);
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use bevy_integrator::{AdaptiveStep, Solver, SolverError, SolverFailure};
+use common::{exact, oscillator, oscillator_app, run_steps, NanAfter};
+
+#[test]
+fn non_finite_derivative_stops_the_simulation() {
+    let mut app = oscillator_app(Solver::RK45, 0.01, 1.);
+    app.world.insert_resource(NanAfter(0.05));
+    run_steps(&mut app, 10);
+
+    let failure = app
+        .world
+        .get_resource::<SolverFailure>()
+        .expect("the step failed");
+    let SolverError::NonFinite { time } = failure.0 else {
+        panic!("{:?}", failure.0);
+    };
+    assert!((0.05..0.06).contains(&time), "failed at t = {time}");
+
+    // the step size was reduced down to the minimum before giving up
+    let adaptive = app.world.resource::<AdaptiveStep>();
+    assert!(adaptive.rejected > 0);
+    assert_eq!(adaptive.dt, adaptive.min_dt);
+}
+
+// global error at t = 2 s with sub-steps of a fixed size (min_dt = max_dt)
+fn fixed_step_error(step: f64) -> f64 {
+    let mut app = oscillator_app(Solver::RK45, 0.1, 1.);
+    app.world
+        .insert_resource(AdaptiveStep::new(1., 1., step, step));
+    run_steps(&mut app, 20);
+    let oscillator = oscillator(&mut app);
+    let (x, v) = exact(1., 2.);
+    (oscillator.x - x).hypot(oscillator.v - v)
+}
+
+#[test]
+fn fifth_order_convergence() {
+    let coarse = fixed_step_error(0.05);
+    let fine = fixed_step_error(0.025);
+    // halving the step reduces the error by 2^5
+    let ratio = coarse / fine;
+    assert!((24. ..40.).contains(&ratio), "error ratio {ratio}");
+}
+
+// global error at t = 2 s, and the number of accepted steps, with the given tolerances (for an
+// oscillator of 10 rad/s, so the largest step is not accurate enough)
+fn adaptive_error(tolerance: f64) -> (f64, usize) {
+    let mut app = oscillator_app(Solver::RK45, 0.1, 100.);
+    app.world
+        .insert_resource(AdaptiveStep::new(tolerance, tolerance, 1e-6, 0.1));
+    run_steps(&mut app, 20);
+    let oscillator = oscillator(&mut app);
+    let (x, v) = exact(100., 2.);
+    let accepted = app.world.resource::<AdaptiveStep>().accepted;
+    ((oscillator.x - x).hypot(oscillator.v - v), accepted)
+}
+
+#[test]
+fn error_control() {
+    let (loose_error, loose_steps) = adaptive_error(1e-4);
+    let (tight_error, tight_steps) = adaptive_error(1e-8);
+    assert!(
+        loose_error < 1e-2,
+        "error {loose_error} with a tolerance of 1e-4"
+    );
+    assert!(
+        tight_error < 1e-6,
+        "error {tight_error} with a tolerance of 1e-8"
+    );
+    assert!(tight_error < loose_error / 100.);
+    assert!(tight_steps > loose_steps);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_integrator::{
+    input::InputAppExt,
+    sampled::{sampled_controller_system, SampleTime, SampledAppExt, SampledController},
+    PhysicsSchedule, SimTime, Solver, StepSchedule,
+};
+use common::{oscillator_app, run_steps, ExternalForce};
+
+const DT: f64 = 0.01;
+const PERIOD: f64 = 0.05;
+const LATENCY: f64 = 0.02;
+
+// the times of the samples, the force applied after every step, and the forces seen by the physics
+// during the current step
+#[derive(Resource, Default)]
+struct Log {
+    samples: Vec<f64>,
+    applied: Vec<(f64, f64)>,
+    stages: Vec<f64>,
+    held: bool,
+}
+
+// outputs the number of the sample (from 1)
+fn controller(sample: Res<SampleTime>, mut force: ResMut<ExternalForce>, mut log: ResMut<Log>) {
+    log.samples.push(sample.time);
+    force.0 = (sample.index + 1) as f64;
+}
+
+fn stage_system(force: Res<ExternalForce>, mut log: ResMut<Log>) {
+    log.stages.push(force.0);
+}
+
+fn step_system(time: Res<SimTime>, force: Res<ExternalForce>, mut log: ResMut<Log>) {
+    let stages = std::mem::take(&mut log.stages);
+    if stages.iter().any(|stage| *stage != stages[0]) {
+        log.held = false;
+    }
+    log.applied.push((time.time(), force.0));
+}
+
+#[test]
+fn sample_hold_and_latency() {
+    let mut app = oscillator_app(Solver::RK4, DT, 1.);
+    app.insert_resource(ExternalForce(0.))
+        .insert_resource(Log {
+            held: true,
+            ..default()
+        })
+        .add_input(
+            "force",
+            |world| world.resource::<ExternalForce>().0,
+            |world, force| world.resource_mut::<ExternalForce>().0 = force,
+        )
+        .add_sampled_controller(
+            SampledController::new("controller", PERIOD)
+                .with_latency(LATENCY)
+                .with_outputs(&["force"]),
+            controller,
+        )
+        .add_systems(PhysicsSchedule, stage_system)
+        .add_systems(
+            StepSchedule,
+            (sampled_controller_system, step_system).chain(),
+        );
+    // the samples at the start time
+    sampled_controller_system(&mut app.world);
+    run_steps(&mut app, 20);
+
+    let log = app.world.resource::<Log>();
+    // at multiples of the period
+    assert_eq!(log.samples.len(), 5, "{:?}", log.samples);
+    for (index, time) in log.samples.iter().enumerate() {
+        assert!(
+            (time - index as f64 * PERIOD).abs() < 1e-9,
+            "{:?}",
+            log.samples
+        );
+    }
+    // the output of the last sample that is at least `latency` old, held in between
+    for (time, force) in log.applied.iter() {
+        let expected = ((time - LATENCY) / PERIOD + 1e-6).floor() + 1.;
+        assert_eq!(*force, expected.max(0.), "at t = {time}");
+    }
+    // constant over the solver stages of every step
+    assert!(log.held);
+}
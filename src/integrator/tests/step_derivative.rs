@@ -0,0 +1,61 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_integrator::{PhysicsState, Solver, StepDerivative, StepSchedule};
+use common::{oscillator, oscillator_app, run_steps, Evaluations, Oscillator};
+
+fn invalidate(mut derivative: ResMut<StepDerivative>) {
+    derivative.invalidate();
+}
+
+// the state after 10 steps, and the number of evaluations
+fn run(solver: Solver, reuse: bool) -> (Oscillator, usize) {
+    let mut app = oscillator_app(solver, 0.01, 1.);
+    app.init_resource::<Evaluations>();
+    if !reuse {
+        app.add_systems(StepSchedule, invalidate);
+    }
+    run_steps(&mut app, 10);
+    (oscillator(&mut app), app.world.resource::<Evaluations>().0)
+}
+
+#[test]
+fn derivative_is_reused_by_the_next_step() {
+    for (solver, stages) in [
+        (Solver::Euler, 1),
+        (Solver::RK4, 4),
+        (Solver::VelocityVerlet, 2),
+    ] {
+        let (reused, evaluations) = run(solver, true);
+        // every step evaluates its stages and the new state, except for the first stage after the
+        // first step
+        assert_eq!(evaluations, 10 * stages + 1);
+
+        // the same result as evaluating every first stage
+        let (evaluated, evaluations) = run(solver, false);
+        assert_eq!(evaluations, 10 * (stages + 1));
+        assert_eq!((reused.x, reused.v), (evaluated.x, evaluated.v));
+    }
+}
+
+// 2 steps, a change of the position, then another step
+fn run_moved(reuse: bool) -> (Oscillator, usize) {
+    let mut app = oscillator_app(Solver::RK4, 0.01, 1.);
+    if !reuse {
+        app.add_systems(StepSchedule, invalidate);
+    }
+    run_steps(&mut app, 2);
+    // the state is held by its PhysicsState, the position first
+    app.world.resource_mut::<PhysicsState<Oscillator>>().states[0] = 2.;
+    run_steps(&mut app, 1);
+    let reused = app.world.resource::<StepDerivative>().reused;
+    (oscillator(&mut app), reused)
+}
+
+#[test]
+fn changed_state_is_evaluated() {
+    let (moved, reused) = run_moved(true);
+    assert_eq!(reused, 1); // only by the second step
+    let (evaluated, _) = run_moved(false);
+    assert_eq!((moved.x, moved.v), (evaluated.x, evaluated.v));
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use bevy_integrator::Solver;
+use common::{oscillator, oscillator_app, run_steps};
+
+// the relative energy error of an oscillator after every step, over about 80 periods
+fn energy_errors(solver: Solver) -> Vec<f64> {
+    let dt = 0.05;
+    let mut app = oscillator_app(solver, dt, 1.);
+    let energy = |x: f64, v: f64| 0.5 * (x * x + v * v);
+    let initial = energy(1., 0.);
+    (0..10_000)
+        .map(|_| {
+            run_steps(&mut app, 1);
+            let oscillator = oscillator(&mut app);
+            (energy(oscillator.x, oscillator.v) - initial) / initial
+        })
+        .collect()
+}
+
+// the largest error in the first and the last 1000 steps (about 8 periods each)
+fn first_and_last(errors: &[f64]) -> (f64, f64) {
+    let max = |errors: &[f64]| {
+        errors
+            .iter()
+            .fold(0., |max: f64, error| max.max(error.abs()))
+    };
+    (max(&errors[..1000]), max(&errors[errors.len() - 1000..]))
+}
+
+#[test]
+fn symplectic_solvers_have_bounded_energy_error() {
+    for (solver, bound) in [
+        (Solver::SymplecticEuler, 0.03), // first order: the energy oscillates by about dt / 2
+        (Solver::VelocityVerlet, 1e-3),
+    ] {
+        let (first, last) = first_and_last(&energy_errors(solver));
+        assert!(last < bound, "energy error {last}");
+        // no drift: the oscillation doesn't grow over the run
+        assert!(
+            last < first * 1.1,
+            "energy error {first} at the start, {last} at the end"
+        );
+    }
+}
+
+#[test]
+fn explicit_euler_energy_drifts() {
+    let (first, last) = first_and_last(&energy_errors(Solver::Euler));
+    assert!(
+        last > 10. * first,
+        "energy error {first} at the start, {last} at the end"
+    );
+}
@@ -6,6 +6,7 @@ use bevy_integrator::{PhysicsSchedule, PhysicsSet, SimTime, Solver};
 use cameras::camera_az_el::{self, camera_builder};
 use rigid_body::{
     definitions::{MeshDef, MeshTypeDef, TransformDef},
+    headless::{joint_states, run_headless},
     joint::{Base, Joint},
     plugin::RigidBodyPlugin,
     sva::{Inertia, Matrix, Motion, Vector, Xform},
@@ -13,22 +14,33 @@ use rigid_body::{
 
 // Main function
 fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
     // Create App
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, Some(10.)),
-            solver: Solver::RK4,
-            simulation_setup: vec![],
-            environment_setup: vec![camera_setup],
-            name: "example 00_1dof".to_string(),
-        })
-        .add_systems(
-            PhysicsSchedule,
-            (spring_damper_system,).in_set(PhysicsSet::Evaluate),
-        )
-        .add_systems(Startup, startup_system)
-        .add_systems(Startup, environment_startup_system)
-        .run();
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(10.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 00_1dof".to_string(),
+        headless,
+    })
+    .add_systems(
+        PhysicsSchedule,
+        (spring_damper_system,).in_set(PhysicsSet::Evaluate),
+    )
+    .add_systems(Startup, startup_system);
+
+    if headless {
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -7,6 +7,7 @@ use bevy_integrator::{SimTime, Solver};
 use cameras::camera_az_el::{self, camera_builder};
 use rigid_body::{
     definitions::{MeshDef, MeshTypeDef, TransformDef},
+    headless::{joint_states, run_headless},
     // forces::spring_damper_system,
     joint::{Base, Joint},
     plugin::RigidBodyPlugin,
@@ -14,17 +15,28 @@ use rigid_body::{
 };
 
 fn main() {
-    App::new()
-        .add_plugins(RigidBodyPlugin {
-            time: SimTime::new(0.002, 0.0, Some(60.)),
-            solver: Solver::RK4,
-            simulation_setup: vec![],
-            environment_setup: vec![camera_setup],
-            name: "example 01_pendulum".to_string(),
-        })
-        .add_systems(Startup, startup_system)
-        .add_systems(Startup, environment_startup_system)
-        .run();
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(60.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 01_pendulum".to_string(),
+        headless,
+    })
+    .add_systems(Startup, startup_system);
+
+    if headless {
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
 }
 
 pub fn camera_setup(app: &mut App) {
@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::camera_az_el::{self, camera_builder};
+use rigid_body::{
+    definitions::{MeshDef, MeshTypeDef, TransformDef},
+    headless::run_headless,
+    joint::{Base, FloatingJoint, Joint},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const MASS: f64 = 1.;
+const DIMENSIONS: [f64; 3] = [0.6, 0.3, 0.1];
+
+// A free floating box (without gravity), spinning about its intermediate axis. The rotation is
+// unstable, so the box keeps flipping over (the Dzhanibekov effect), passing through all
+// orientations without gimbal lock.
+fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(20.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 03_floating_body".to_string(),
+        headless,
+    })
+    .add_systems(Startup, startup_system);
+
+    if headless {
+        // the kinetic energy and the angular momentum (in the parent frame) are conserved
+        app.add_systems(PostStartup, print_initial_state);
+        run_headless(&mut app);
+        print_state(app.world.query::<&FloatingJoint>().single(&app.world));
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
+}
+
+// moment of inertia about the center of the box
+fn moment_of_inertia() -> Matrix {
+    let [x, y, z] = DIMENSIONS;
+    let moi = Vector::new(y * y + z * z, x * x + z * z, x * x + y * y) * MASS / 12.;
+    Matrix::from_diagonal(&moi)
+}
+
+fn print_initial_state(body_query: Query<&FloatingJoint>) {
+    for body in body_query.iter() {
+        print_state(body);
+    }
+}
+
+fn print_state(body: &FloatingJoint) {
+    let moi = moment_of_inertia();
+    let momentum = body.rotation * (moi * body.v.w);
+    let energy = 0.5 * body.v.w.dot(&(moi * body.v.w)) + 0.5 * MASS * body.v.v.norm_squared();
+    println!(
+        "{}: position = {:.4?}, energy = {energy:.9}, angular momentum = {:.9?}",
+        body.name,
+        body.position.as_slice(),
+        momentum.as_slice(),
+    );
+}
+
+pub fn camera_setup(app: &mut App) {
+    app.add_systems(
+        Startup,
+        camera_builder(
+            Vec3 {
+                x: 0.,
+                y: 0.,
+                z: 1.,
+            },
+            -90.0_f32.to_radians(),
+            10.0_f32.to_radians(),
+            3.,
+            camera_az_el::UpDirection::Z,
+        ),
+    )
+    .add_systems(Update, (camera_az_el::az_el_camera,)); // setup the camera
+}
+
+fn startup_system(mut commands: Commands) {
+    // no gravity
+    let base = Joint::base(Motion::zero());
+    let base_id = commands.spawn((base, Base)).id();
+
+    let inertia = Inertia::new(MASS, Vector::zeros(), moment_of_inertia());
+    let (joint, mut body) = Joint::floating("body".to_string(), inertia, Xform::identity());
+    body.position = Vector::new(0., 0., 1.);
+    // spinning about the y axis (with a small disturbance), and moving along x
+    body.v = Motion::new([0.1, 0., 0.], [0.01, 5., 0.]);
+    let [x, y, z] = DIMENSIONS;
+    let mut body_e = commands.spawn((
+        joint,
+        body,
+        MeshDef {
+            mesh_type: MeshTypeDef::Box {
+                dimensions: [x as f32, y as f32, z as f32],
+            },
+            transform: TransformDef::Identity,
+            color: Color::rgb(0.0, 0.0, 1.0),
+        },
+    ));
+    body_e.set_parent(base_id);
+}
+
+fn environment_startup_system(mut commands: Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 10000.0, // lux
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 1.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 10.0),
+            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+            ..default()
+        },
+
+        ..default()
+    });
+}
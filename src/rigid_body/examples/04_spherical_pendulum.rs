@@ -0,0 +1,145 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::camera_az_el::{self, camera_builder};
+use nalgebra::UnitQuaternion;
+use rigid_body::{
+    definitions::{MeshDef, MeshTypeDef, TransformDef},
+    headless::run_headless,
+    joint::{Base, Joint, SphericalJoint},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+const MASS: f64 = 1.;
+const WIDTH: f64 = 0.05;
+const LENGTH: f64 = 1.;
+
+// A rod hanging from a ball joint (a spherical joint), released at 60 degrees from vertical with a
+// sideways velocity, so it swings around the vertical axis in a precessing ellipse
+fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(60.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 04_spherical_pendulum".to_string(),
+        headless,
+    })
+    .add_systems(Startup, startup_system);
+
+    if headless {
+        // the energy of the pendulum is conserved
+        app.add_systems(PostStartup, print_initial_state);
+        run_headless(&mut app);
+        print_state(app.world.query::<&SphericalJoint>().single(&app.world));
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
+}
+
+// center of mass, in body coordinates
+fn center() -> Vector {
+    Vector::new(0., 0., -LENGTH / 2.)
+}
+
+// moment of inertia about the center of mass
+fn moment_of_inertia() -> Matrix {
+    let moi_xy = MASS / 12. * (WIDTH.powi(2) + LENGTH.powi(2));
+    let moi_z = MASS / 12. * 2. * WIDTH.powi(2);
+    Matrix::from_diagonal(&Vector::new(moi_xy, moi_xy, moi_z))
+}
+
+fn print_initial_state(ball_query: Query<&SphericalJoint>) {
+    for ball in ball_query.iter() {
+        print_state(ball);
+    }
+}
+
+fn print_state(ball: &SphericalJoint) {
+    // moment of inertia about the ball joint
+    let c = center();
+    let moi =
+        moment_of_inertia() + MASS * (c.norm_squared() * Matrix::identity() - c * c.transpose());
+    let center = ball.rotation * c;
+    let energy = 0.5 * ball.w.dot(&(moi * ball.w)) + MASS * GRAVITY * center.z;
+    println!(
+        "{}: center of mass = {:.4?}, energy = {energy:.9}",
+        ball.name,
+        center.as_slice(),
+    );
+}
+
+pub fn camera_setup(app: &mut App) {
+    app.add_systems(
+        Startup,
+        camera_builder(
+            Vec3 {
+                x: 0.,
+                y: 0.,
+                z: -0.5,
+            },
+            180.0_f32.to_radians(),
+            20.0_f32.to_radians(),
+            3.,
+            camera_az_el::UpDirection::Z,
+        ),
+    )
+    .add_systems(Update, (camera_az_el::az_el_camera,)); // setup the camera
+}
+
+fn startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let inertia = Inertia::new(MASS, center(), moment_of_inertia());
+    let (joint, mut ball) = Joint::spherical("ball".to_string(), inertia, Xform::identity());
+    ball.rotation = UnitQuaternion::from_euler_angles(60_f64.to_radians(), 0., 0.);
+    // rotating about the vertical, in body coordinates
+    ball.w = ball.rotation.inverse() * Vector::new(0., 0., 3.);
+    let mesh_def = MeshDef {
+        mesh_type: MeshTypeDef::Box {
+            dimensions: [WIDTH as f32, WIDTH as f32, LENGTH as f32],
+        },
+        transform: TransformDef::Position {
+            x: 0.,
+            y: 0.,
+            z: -LENGTH / 2.,
+        },
+        color: Color::rgb(1.0, 0.0, 0.0),
+    };
+    let mut ball_e = commands.spawn((joint, ball, mesh_def));
+    ball_e.set_parent(base_id);
+}
+
+fn environment_startup_system(mut commands: Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 10000.0, // lux
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 1.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 10.0),
+            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+            ..default()
+        },
+
+        ..default()
+    });
+}
@@ -0,0 +1,240 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver, StepSchedule};
+use cameras::camera_az_el::{self, camera_builder};
+use rigid_body::{
+    constraints::LoopConstraint,
+    definitions::{MeshDef, MeshTypeDef, TransformDef},
+    headless::{joint_states, run_headless},
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, InertiaAB, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+const MASS: f64 = 1.;
+const WIDTH: f64 = 0.05;
+const GROUND: f64 = 1.0; // distance between the crank and rocker pivots
+const CRANK: f64 = 0.4;
+const COUPLER: f64 = 1.0;
+const ROCKER: f64 = 0.6;
+const CRANK_ANGLE: f64 = 60.; // initial, degrees
+
+// A four-bar linkage (a crank-rocker), swinging under gravity. The crank, coupler and rocker form a
+// tree of revolute joints, and the loop is closed by a point constraint between the end of the
+// coupler and the end of the rocker.
+fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(20.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 05_four_bar".to_string(),
+        headless,
+    })
+    .init_resource::<Summary>()
+    .add_systems(Startup, startup_system)
+    .add_systems(StepSchedule, summary_system);
+
+    if headless {
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+        // the constraint forces do no work, so the energy is (nearly) conserved
+        let summary = app.world.resource::<Summary>();
+        println!(
+            "energy: first step = {:.6}, last step = {:.6}",
+            summary.initial_energy.unwrap_or_default(),
+            summary.energy
+        );
+        println!(
+            "max constraint violation: position = {:.3e} m, velocity = {:.3e} m/s",
+            summary.position_error, summary.velocity_error
+        );
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
+}
+
+// the energy after the first and last steps, and the largest constraint violation
+#[derive(Resource, Default)]
+struct Summary {
+    initial_energy: Option<f64>,
+    energy: f64,
+    position_error: f64,
+    velocity_error: f64,
+}
+
+fn summary_system(
+    joint_query: Query<&Joint>,
+    constraint_query: Query<&LoopConstraint>,
+    mut summary: ResMut<Summary>,
+) {
+    let mut energy = 0.;
+    for joint in joint_query.iter() {
+        if let Some(length) = link_length(&joint.name) {
+            let center = joint.x.position + joint.x.rotation.transpose() * link_center(length);
+            let inertia: InertiaAB = joint.i.into();
+            energy += 0.5 * (&joint.v * &(inertia * joint.v)) + MASS * GRAVITY * center.z;
+        }
+    }
+    summary.initial_energy.get_or_insert(energy);
+    summary.energy = energy;
+
+    for constraint in constraint_query.iter() {
+        summary.position_error = summary.position_error.max(constraint.position_error);
+        summary.velocity_error = summary.velocity_error.max(constraint.velocity_error);
+    }
+}
+
+fn link_length(name: &str) -> Option<f64> {
+    match name {
+        "crank" => Some(CRANK),
+        "coupler" => Some(COUPLER),
+        "rocker" => Some(ROCKER),
+        _ => None,
+    }
+}
+
+fn link_center(length: f64) -> Vector {
+    Vector::new(length / 2., 0., 0.)
+}
+
+// a slender link along the x axis of the joint
+fn link(name: &str, length: f64, xt: Xform, angle: f64, color: Color) -> (Joint, MeshDef) {
+    let moi_x = 1. / 12. * MASS * 2. * WIDTH.powi(2);
+    let moi_yz = 1. / 12. * MASS * (WIDTH.powi(2) + length.powi(2));
+    let inertia = Inertia::new(
+        MASS,
+        link_center(length),
+        Matrix::from_diagonal(&Vector::new(moi_x, moi_yz, moi_yz)),
+    );
+    let mut joint = Joint::ry(name.to_string(), inertia, xt);
+    // a rotation about y moves the x axis down, the angles of the linkage are measured upwards
+    joint.q = -angle;
+    let mesh_def = MeshDef {
+        mesh_type: MeshTypeDef::Box {
+            dimensions: [length as f32, WIDTH as f32, WIDTH as f32],
+        },
+        transform: TransformDef::Position {
+            x: length / 2.,
+            y: 0.,
+            z: 0.,
+        },
+        color,
+    };
+    (joint, mesh_def)
+}
+
+// The angles (from the x axis, in the x-z plane) of the coupler and the rocker, with the crank at
+// `crank_angle`. The coupler end is on the upper intersection of the circles about the crank end
+// and the rocker pivot.
+fn assembly_angles(crank_angle: f64) -> (f64, f64) {
+    let crank_end = Vector::new(CRANK * crank_angle.cos(), 0., CRANK * crank_angle.sin());
+    let pivot = Vector::new(GROUND, 0., 0.);
+    let diagonal = pivot - crank_end;
+    let d = diagonal.norm();
+    let along = (COUPLER.powi(2) - ROCKER.powi(2) + d.powi(2)) / (2. * d);
+    let across = (COUPLER.powi(2) - along.powi(2)).sqrt();
+    let normal = Vector::new(-diagonal.z, 0., diagonal.x) / d;
+    let joint_point = crank_end + diagonal / d * along + normal * across;
+
+    let coupler = joint_point - crank_end;
+    let rocker = joint_point - pivot;
+    (coupler.z.atan2(coupler.x), rocker.z.atan2(rocker.x))
+}
+
+pub fn camera_setup(app: &mut App) {
+    app.add_systems(
+        Startup,
+        camera_builder(
+            Vec3 {
+                x: 0.5,
+                y: 0.,
+                z: 0.,
+            },
+            -90.0_f32.to_radians(),
+            0.0_f32.to_radians(),
+            3.5,
+            camera_az_el::UpDirection::Z,
+        ),
+    )
+    .add_systems(Update, (camera_az_el::az_el_camera,)); // setup the camera
+}
+
+fn startup_system(mut commands: Commands) {
+    let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+    let base_id = commands.spawn((base, Base)).id();
+
+    let crank_angle = CRANK_ANGLE.to_radians();
+    let (coupler_angle, rocker_angle) = assembly_angles(crank_angle);
+
+    let crank = link(
+        "crank",
+        CRANK,
+        Xform::identity(),
+        crank_angle,
+        Color::rgb(1.0, 0.0, 0.0),
+    );
+    let crank_id = commands.spawn(crank).set_parent(base_id).id();
+
+    // the coupler angle is relative to the crank
+    let coupler = link(
+        "coupler",
+        COUPLER,
+        Xform::posx(CRANK),
+        coupler_angle - crank_angle,
+        Color::rgb(0.0, 1.0, 0.0),
+    );
+    let coupler_id = commands.spawn(coupler).set_parent(crank_id).id();
+
+    let rocker = link(
+        "rocker",
+        ROCKER,
+        Xform::posx(GROUND),
+        rocker_angle,
+        Color::rgb(0.0, 0.0, 1.0),
+    );
+    let rocker_id = commands.spawn(rocker).set_parent(base_id).id();
+
+    // close the loop: the end of the coupler is pinned to the end of the rocker
+    commands.spawn(LoopConstraint::point(
+        "coupler_rocker",
+        coupler_id,
+        Vector::new(COUPLER, 0., 0.),
+        rocker_id,
+        Vector::new(ROCKER, 0., 0.),
+    ));
+}
+
+fn environment_startup_system(mut commands: Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 10000.0, // lux
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 1.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 10.0),
+            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+            ..default()
+        },
+
+        ..default()
+    });
+}
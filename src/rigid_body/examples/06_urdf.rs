@@ -0,0 +1,207 @@
+use std::f32::consts::PI;
+
+use bevy::{ecs::system::SystemState, prelude::*};
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::camera_az_el::{self, camera_builder};
+use rigid_body::{
+    headless::{joint_states, run_headless},
+    joint::Joint,
+    kinematics::Kinematics,
+    limits::LimitType,
+    plugin::RigidBodyPlugin,
+    sva::{Motion, Vector},
+    urdf::{write_urdf, Urdf},
+};
+
+// A two link arm, swinging under gravity. The shoulder stops at its joint limit, and the hand is
+// attached to the forearm by a fixed joint (so it is merged into the forearm body).
+const ARM_URDF: &str = r#"<?xml version="1.0"?>
+<robot name="arm">
+  <material name="red">
+    <color rgba="1 0 0 1"/>
+  </material>
+
+  <link name="world">
+    <visual>
+      <geometry>
+        <box size="0.1 0.1 0.1"/>
+      </geometry>
+    </visual>
+  </link>
+
+  <joint name="shoulder" type="revolute">
+    <parent link="world"/>
+    <child link="upper_arm"/>
+    <axis xyz="0 1 0"/>
+    <limit lower="-1.2" upper="1.2" effort="0" velocity="0"/>
+  </joint>
+
+  <link name="upper_arm">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="1"/>
+      <inertia ixx="0.000417" ixy="0" ixz="0" iyy="0.083542" iyz="0" izz="0.083542"/>
+    </inertial>
+    <visual>
+      <origin xyz="0.5 0 0"/>
+      <geometry>
+        <box size="1 0.05 0.05"/>
+      </geometry>
+      <material name="red"/>
+    </visual>
+  </link>
+
+  <joint name="elbow" type="continuous">
+    <parent link="upper_arm"/>
+    <child link="forearm"/>
+    <origin xyz="1 0 0"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+
+  <link name="forearm">
+    <inertial>
+      <origin xyz="0.4 0 0"/>
+      <mass value="0.8"/>
+      <inertia ixx="0.0001" ixy="0" ixz="0" iyy="0.0427" iyz="0" izz="0.0427"/>
+    </inertial>
+    <visual>
+      <origin xyz="0.4 0 0" rpy="0 1.5707963 0"/>
+      <geometry>
+        <cylinder radius="0.02" length="0.8"/>
+      </geometry>
+      <material name="green">
+        <color rgba="0 1 0 1"/>
+      </material>
+    </visual>
+  </link>
+
+  <joint name="wrist" type="fixed">
+    <parent link="forearm"/>
+    <child link="hand"/>
+    <origin xyz="0.8 0 0"/>
+  </joint>
+
+  <link name="hand">
+    <inertial>
+      <mass value="0.5"/>
+      <inertia ixx="0.0005" ixy="0" ixz="0" iyy="0.0005" iyz="0" izz="0.0005"/>
+    </inertial>
+    <visual>
+      <geometry>
+        <sphere radius="0.05"/>
+      </geometry>
+    </visual>
+  </link>
+</robot>
+"#;
+
+fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state, the hand
+    // kinematics and the exported URDF
+    let headless = std::env::args().any(|arg| arg == "--headless");
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(10.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: "example 06_urdf".to_string(),
+        headless,
+    })
+    .add_systems(Startup, startup_system);
+
+    if headless {
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+        print_hand_kinematics(&mut app.world);
+        // the hand is part of the forearm body, and is written back as a link fixed to the forearm
+        match write_urdf(&app.world, "arm") {
+            Ok(urdf) => print!("{urdf}"),
+            Err(error) => println!("could not write the URDF: {error}"),
+        }
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
+}
+
+// the motion of the hand (at the end of the forearm) and its Jacobian
+fn print_hand_kinematics(world: &mut World) {
+    let Some(forearm) = world
+        .query::<(Entity, &Joint)>()
+        .iter(world)
+        .find(|(_, joint)| joint.name == "elbow")
+        .map(|(entity, _)| entity)
+    else {
+        return;
+    };
+    let mut state = SystemState::<Kinematics>::new(world);
+    let kinematics = state.get(world);
+    let hand = Vector::new(0.8, 0., 0.);
+    if let (Some(motion), Some(jacobian)) = (
+        kinematics.point_motion(forearm, hand),
+        kinematics.jacobian(forearm, hand),
+    ) {
+        println!("hand position: {:.6?}", motion.position.as_slice());
+        println!("hand velocity: {:.6?}", motion.velocity.as_slice());
+        println!("hand Jacobian ({}):", jacobian.names.join(", "));
+        for row in jacobian.matrix.row_iter() {
+            println!("  {:.6?}", row.iter().collect::<Vec<_>>());
+        }
+    }
+}
+
+pub fn camera_setup(app: &mut App) {
+    app.add_systems(
+        Startup,
+        camera_builder(
+            Vec3 {
+                x: 0.5,
+                y: 0.,
+                z: -0.5,
+            },
+            -90.0_f32.to_radians(),
+            0.0_f32.to_radians(),
+            5.,
+            camera_az_el::UpDirection::Z,
+        ),
+    )
+    .add_systems(Update, (camera_az_el::az_el_camera,)); // setup the camera
+}
+
+fn startup_system(mut commands: Commands) {
+    // the shoulder limit is a rigid end stop
+    let urdf = Urdf::parse(ARM_URDF)
+        .expect("the arm URDF is valid")
+        .with_limits(LimitType::Hard { restitution: 0. });
+    urdf.spawn(&mut commands, Motion::new([0., 0., 9.81], [0., 0., 0.]))
+        .expect("the arm URDF is a tree");
+}
+
+fn environment_startup_system(mut commands: Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 10000.0, // lux
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 1.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 10.0),
+            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+            ..default()
+        },
+
+        ..default()
+    });
+}
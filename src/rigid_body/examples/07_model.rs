@@ -0,0 +1,98 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use bevy_integrator::{SimTime, Solver};
+use cameras::camera_az_el::{self, camera_builder};
+use rigid_body::{
+    headless::{joint_states, run_headless},
+    model::{model_startup_system, ModelDef},
+    plugin::RigidBodyPlugin,
+};
+
+const DEFAULT_MODEL: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/examples/models/double_pendulum.ron"
+);
+
+// A mechanism loaded from a model file (RON, JSON or TOML), e.g.
+// `cargo run --example 07_model -- rigid_body/examples/models/four_bar.json`
+fn main() {
+    // pass `--headless` to run the simulation without a window, and print the final state
+    let headless = std::env::args().any(|arg| arg == "--headless");
+    let path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or(DEFAULT_MODEL.to_string());
+
+    let model = match ModelDef::read(&path) {
+        Ok(model) => model,
+        Err(error) => {
+            eprintln!("could not load the model: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0.0, Some(20.)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![camera_setup],
+        name: format!("example 07_model ({})", model.name),
+        headless,
+    })
+    .add_systems(Startup, model_startup_system(model));
+
+    if headless {
+        run_headless(&mut app);
+        for (name, state) in joint_states(&mut app.world) {
+            println!("{name}: q = {:.6}, qd = {:.6}", state.q, state.qd);
+        }
+    } else {
+        app.add_systems(Startup, environment_startup_system).run();
+    }
+}
+
+pub fn camera_setup(app: &mut App) {
+    app.add_systems(
+        Startup,
+        camera_builder(
+            Vec3 {
+                x: 0.,
+                y: 0.,
+                z: -0.5,
+            },
+            -90.0_f32.to_radians(),
+            0.0_f32.to_radians(),
+            5.,
+            camera_az_el::UpDirection::Z,
+        ),
+    )
+    .add_systems(Update, (camera_az_el::az_el_camera,)); // setup the camera
+}
+
+fn environment_startup_system(mut commands: Commands) {
+    commands.insert_resource(AmbientLight {
+        color: Color::rgb(0.9, 0.9, 1.0),
+        brightness: 0.4,
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            shadows_enabled: true,
+            illuminance: 10000.0, // lux
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 1.0,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 10.0),
+            rotation: Quat::from_rotation_x(-PI / 4.) * Quat::from_rotation_y(-PI / 4.),
+
+            ..default()
+        },
+
+        ..default()
+    });
+}
@@ -1,6 +1,7 @@
 use crate::joint::{Joint, JointType};
-use crate::sva::{Force, Motion, Xform};
+use crate::sva::{Force, ForceArray, InertiaAB, Motion, MotionArray, Xform};
 use bevy::prelude::*;
+use nalgebra::{SMatrix, Vector6};
 
 pub fn loop_1_update(joint: &mut Joint, parent: &Joint) {
     // reset joint
@@ -8,6 +9,7 @@ pub fn loop_1_update(joint: &mut Joint, parent: &Joint) {
     joint.f_ext = Force::zero();
     joint.qdd = 0.;
     joint.a = Motion::zero();
+    joint.aj = Motion::zero();
 
     // joint transform
     joint.xj = match joint.joint_type {
@@ -18,9 +20,17 @@ pub fn loop_1_update(joint: &mut Joint, parent: &Joint) {
         JointType::Px => Xform::posx(joint.q),
         JointType::Py => Xform::posy(joint.q),
         JointType::Pz => Xform::posz(joint.q),
+        JointType::Revolute(axis) => Xform::rot_axis(&axis, joint.q),
+        JointType::Prismatic(axis) => Xform::pos_axis(&axis, joint.q),
+        JointType::Helical { axis, pitch } => Xform::screw(&axis, joint.q, pitch),
+        // the transforms and velocities of multi degree of freedom joints are set from their state
+        // (see `loop_1`)
+        JointType::Floating | JointType::Spherical => joint.xj,
     };
 
-    joint.vj = joint.qd * joint.s;
+    if !joint.is_multi_dof() {
+        joint.vj = joint.qd * joint.s;
+    }
     joint.xl = joint.xj * joint.xt;
 
     joint.x = joint.xl * parent.x;
@@ -35,7 +45,23 @@ pub fn apply_external_update(joint: &mut Joint, _parent: &Joint) {
     joint.paa -= joint.x * joint.f_ext;
 }
 
+// Loop 1 without the kinematics (and with the external forces): resets the articulated inertias and
+// bias forces, so loops 2 and 3 can be run again with different external forces
+pub fn articulated_reset_update(joint: &mut Joint, _parent: &Joint) {
+    joint.iaa = joint.i.into();
+    joint.paa = joint.v.cross_f(joint.i * joint.v);
+    joint.paa -= joint.x * joint.f_ext;
+}
+
 pub fn loop_2_update(joint: &mut Joint, parent_option: Option<&mut Joint>) {
+    match joint.joint_type {
+        JointType::Floating => loop_2_multi_dof(joint, parent_option, &floating_subspace()),
+        JointType::Spherical => loop_2_multi_dof(joint, parent_option, &spherical_subspace()),
+        _ => loop_2_single_dof(joint, parent_option),
+    }
+}
+
+fn loop_2_single_dof(joint: &mut Joint, parent_option: Option<&mut Joint>) {
     joint.uu = joint.iaa * joint.s;
     joint.dd = joint.s.w.dot(&joint.uu.m) + joint.s.v.dot(&joint.uu.f);
     joint.u = joint.tau - (joint.s.w.dot(&joint.paa.m) + joint.s.v.dot(&joint.paa.f));
@@ -56,10 +82,148 @@ pub fn loop_2_update(joint: &mut Joint, parent_option: Option<&mut Joint>) {
 pub fn loop_3_update(joint: &mut Joint, parent: &Joint) {
     let ap = joint.xl * parent.a + joint.c;
 
-    let dd_inv = 1. / joint.dd;
-    let te = joint.u - (joint.uu.m.dot(&ap.w) + joint.uu.f.dot(&ap.v));
-    joint.qdd = dd_inv * te;
-    joint.a = ap + (joint.qdd * joint.s);
+    joint.aj = match joint.joint_type {
+        JointType::Floating => multi_dof_acceleration(joint, &ap, &joint.u_n, &floating_subspace()),
+        JointType::Spherical => {
+            multi_dof_acceleration(joint, &ap, &joint.u_n, &spherical_subspace())
+        }
+        _ => {
+            let dd_inv = 1. / joint.dd;
+            let te = joint.u - (joint.uu.m.dot(&ap.w) + joint.uu.f.dot(&ap.v));
+            joint.qdd = dd_inv * te;
+            joint.qdd * joint.s
+        }
+    };
+    joint.a = ap + joint.aj;
+}
+
+// The motion subspace of a floating joint: rotation and translation about all axes of the body. The
+// joint velocity is the body velocity (wx, wy, wz, vx, vy, vz).
+pub fn floating_subspace() -> MotionArray<6> {
+    MotionArray::new([
+        Motion::new([0., 0., 0.], [1., 0., 0.]),
+        Motion::new([0., 0., 0.], [0., 1., 0.]),
+        Motion::new([0., 0., 0.], [0., 0., 1.]),
+        Motion::new([1., 0., 0.], [0., 0., 0.]),
+        Motion::new([0., 1., 0.], [0., 0., 0.]),
+        Motion::new([0., 0., 1.], [0., 0., 0.]),
+    ])
+}
+
+// The motion subspace of a spherical joint: rotation about all axes of the body. The joint velocity
+// is the angular velocity (wx, wy, wz).
+pub fn spherical_subspace() -> MotionArray<3> {
+    MotionArray::new([
+        Motion::new([0., 0., 0.], [1., 0., 0.]),
+        Motion::new([0., 0., 0.], [0., 1., 0.]),
+        Motion::new([0., 0., 0.], [0., 0., 1.]),
+    ])
+}
+
+// Loop 2 of a joint with the motion subspace s. U = IA S, D^-1 = (S^T U)^-1 and u = -S^T pA (without
+// joint forces) are kept for loop 3. A singular D makes the accelerations of the joint and its
+// ancestors NaN, rather than dropping its inertia, which stops the simulation (a SolverFailure).
+fn loop_2_multi_dof<const N: usize>(
+    joint: &mut Joint,
+    parent_option: Option<&mut Joint>,
+    s: &MotionArray<N>,
+) {
+    let uu: ForceArray<N> = joint.iaa * s;
+    let dd_inv = (s * &uu).try_inverse().unwrap_or_else(|| {
+        if !joint.singular {
+            error!(
+                "singular articulated inertia at joint {}, its accelerations are not finite",
+                joint.name
+            );
+            joint.singular = true;
+        }
+        SMatrix::repeat(f64::NAN)
+    });
+    let uu = uu.to_mat();
+    let u = -(s * joint.paa);
+    joint.uu_n.fixed_view_mut::<6, N>(0, 0).copy_from(&uu);
+    joint
+        .dd_inv_n
+        .fixed_view_mut::<N, N>(0, 0)
+        .copy_from(&dd_inv);
+    joint.u_n.fixed_rows_mut::<N>(0).copy_from(&u);
+
+    let Some(parent) = parent_option else {
+        return;
+    };
+    let ia = joint.iaa - InertiaAB::from_mat(&(uu * dd_inv * uu.transpose()));
+    let pa = joint.paa + (ia * joint.c) + Force::from_mat(&(uu * dd_inv * u));
+    let xli = joint.xl.inverse();
+    parent.iaa += xli * ia;
+    parent.paa += xli * pa;
+}
+
+// Loop 2 for a change of the external and joint forces (e.g. a unit constraint force), with the
+// articulated inertias, U and D of the last loop 2, which don't depend on the forces. `pa` is the
+// change of the bias force of the joint (its own and that of its children), and is replaced by the
+// change to be added to the parent (in joint coordinates). Returns the change of u.
+pub fn loop_2_change(joint: &Joint, pa: &mut Force, tau: f64) -> Vector6<f64> {
+    let u = match joint.joint_type {
+        JointType::Floating => multi_dof_bias_change(joint, pa, &floating_subspace()),
+        JointType::Spherical => multi_dof_bias_change(joint, pa, &spherical_subspace()),
+        _ => {
+            let u = tau - (joint.s.w.dot(&pa.m) + joint.s.v.dot(&pa.f));
+            *pa += (u / joint.dd) * joint.uu;
+            Vector6::new(u, 0., 0., 0., 0., 0.)
+        }
+    };
+    let xli = joint.xl.inverse();
+    *pa = xli * *pa;
+    u
+}
+
+fn multi_dof_bias_change<const N: usize>(
+    joint: &Joint,
+    pa: &mut Force,
+    s: &MotionArray<N>,
+) -> Vector6<f64> {
+    let uu = joint.uu_n.fixed_view::<6, N>(0, 0);
+    let dd_inv = joint.dd_inv_n.fixed_view::<N, N>(0, 0);
+    let u = -(s * *pa);
+    *pa += Force::from_mat(&(uu * dd_inv * u));
+    let mut u_n = Vector6::zeros();
+    u_n.fixed_rows_mut::<N>(0).copy_from(&u);
+    u_n
+}
+
+// Loop 3 for the change of the forces: from the change of the parent acceleration and of u (from
+// `loop_2_change`), returns the changes of a, aj and qdd
+pub fn loop_3_change(joint: &Joint, parent_a: &Motion, u: &Vector6<f64>) -> (Motion, Motion, f64) {
+    let ap = joint.xl * *parent_a;
+    let (aj, qdd) = match joint.joint_type {
+        JointType::Floating => (
+            multi_dof_acceleration(joint, &ap, u, &floating_subspace()),
+            0.,
+        ),
+        JointType::Spherical => (
+            multi_dof_acceleration(joint, &ap, u, &spherical_subspace()),
+            0.,
+        ),
+        _ => {
+            let qdd = (u[0] - (joint.uu.m.dot(&ap.w) + joint.uu.f.dot(&ap.v))) / joint.dd;
+            (qdd * joint.s, qdd)
+        }
+    };
+    (ap + aj, aj, qdd)
+}
+
+// loop 3 of a joint with the motion subspace s (with u of loop 2, or its change), returns the joint
+// acceleration (s * qdd)
+fn multi_dof_acceleration<const N: usize>(
+    joint: &Joint,
+    ap: &Motion,
+    u: &Vector6<f64>,
+    s: &MotionArray<N>,
+) -> Motion {
+    let uu = joint.uu_n.fixed_view::<6, N>(0, 0);
+    let dd_inv = joint.dd_inv_n.fixed_view::<N, N>(0, 0);
+    let ap = Vector6::new(ap.w.x, ap.w.y, ap.w.z, ap.v.x, ap.v.y, ap.v.z);
+    s * &(dd_inv * (u.fixed_rows::<N>(0) - uu.transpose() * ap))
 }
 
 pub fn integrate_joint_state(fixed_time: Res<Time<Fixed>>, mut joint_query: Query<&mut Joint>) {
@@ -0,0 +1,400 @@
+use bevy::prelude::*;
+use bevy_integrator::{recorder::csv_field, SimTime, SolverFailure, StateVector, Stateful};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    headless::run_headless,
+    joint::{FloatingJoint, Joint, SphericalJoint},
+};
+
+// The values of a batch parameter
+#[derive(Clone, Debug)]
+pub enum ParameterValues {
+    // every value is run, combined with every value of the other listed parameters
+    List(Vec<f64>),
+    // drawn for every sample (see `Batch::with_samples`)
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, std_dev: f64 },
+}
+
+// Evaluated on the world at the end of a run
+pub type Metric = fn(&mut World) -> f64;
+
+// A parameter that is varied between the runs of a batch, and how it is applied to the parameters
+// of a run (e.g. a field of a CarDefinition)
+#[derive(Clone)]
+pub struct BatchParameter<P> {
+    pub name: String,
+    pub set: fn(&mut P, f64),
+    pub values: ParameterValues,
+}
+
+// A set of headless simulations that differ in their parameters. Each run starts from a copy of
+// `base` with the parameters applied, is built into its own app by `build` (with a headless
+// RigidBodyPlugin), and is simulated until `end_time`. The metrics are evaluated on the world at the
+// end of each run.
+pub struct Batch<P> {
+    pub base: P,
+    pub build: fn(P) -> App,
+    pub end_time: f64,
+    pub parameters: Vec<BatchParameter<P>>,
+    pub metrics: Vec<(String, Metric)>,
+    pub samples: usize, // random draws for every combination of the listed values
+    pub seed: u64,
+    pub threads: usize,
+}
+
+impl<P: Clone + Send + Sync> Batch<P> {
+    pub fn new(base: P, build: fn(P) -> App, end_time: f64) -> Self {
+        Batch {
+            base,
+            build,
+            end_time,
+            parameters: Vec::new(),
+            metrics: Vec::new(),
+            samples: 1,
+            seed: 0,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+
+    pub fn with_values(mut self, name: &str, set: fn(&mut P, f64), values: &[f64]) -> Self {
+        self.parameters.push(BatchParameter {
+            name: name.to_string(),
+            set,
+            values: ParameterValues::List(values.to_vec()),
+        });
+        self
+    }
+
+    pub fn with_uniform(mut self, name: &str, set: fn(&mut P, f64), min: f64, max: f64) -> Self {
+        self.parameters.push(BatchParameter {
+            name: name.to_string(),
+            set,
+            values: ParameterValues::Uniform { min, max },
+        });
+        self
+    }
+
+    pub fn with_normal(
+        mut self,
+        name: &str,
+        set: fn(&mut P, f64),
+        mean: f64,
+        std_dev: f64,
+    ) -> Self {
+        self.parameters.push(BatchParameter {
+            name: name.to_string(),
+            set,
+            values: ParameterValues::Normal { mean, std_dev },
+        });
+        self
+    }
+
+    pub fn with_metric(mut self, name: &str, metric: Metric) -> Self {
+        self.metrics.push((name.to_string(), metric));
+        self
+    }
+
+    pub fn with_samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    // The parameter values of every run (in the order of `parameters`). The listed values are
+    // combined with the first parameter varying slowest. The random values are drawn from `seed`,
+    // so the same batch always has the same runs. A distribution with invalid parameters (e.g. a
+    // negative or infinite standard deviation) is an error.
+    pub fn variants(&self) -> io::Result<Vec<Vec<f64>>> {
+        let mut combinations = vec![Vec::new()];
+        for parameter in self.parameters.iter() {
+            combinations = match &parameter.values {
+                ParameterValues::List(values) => combinations
+                    .iter()
+                    .flat_map(|combination| {
+                        values.iter().map(move |value| {
+                            let mut combination = combination.clone();
+                            combination.push(Some(*value));
+                            combination
+                        })
+                    })
+                    .collect(),
+                _ => combinations
+                    .into_iter()
+                    .map(|mut combination| {
+                        combination.push(None);
+                        combination
+                    })
+                    .collect(),
+            };
+        }
+
+        let random = self
+            .parameters
+            .iter()
+            .any(|parameter| !matches!(parameter.values, ParameterValues::List(_)));
+        let samples = if random { self.samples } else { 1 };
+
+        for parameter in self.parameters.iter() {
+            let valid = match parameter.values {
+                ParameterValues::List(_) => true,
+                ParameterValues::Uniform { min, max } => {
+                    min.is_finite() && max.is_finite() && min <= max
+                }
+                ParameterValues::Normal { mean, std_dev } => {
+                    mean.is_finite() && std_dev.is_finite() && std_dev >= 0.
+                }
+            };
+            if !valid {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "parameter {}: invalid distribution {:?}",
+                        parameter.name, parameter.values
+                    ),
+                ));
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut variants = Vec::with_capacity(combinations.len() * samples);
+        for combination in combinations.iter() {
+            for _ in 0..samples {
+                let variant = self
+                    .parameters
+                    .iter()
+                    .zip(combination.iter())
+                    .map(|(parameter, value)| match (&parameter.values, value) {
+                        (_, Some(value)) => Ok(*value),
+                        (ParameterValues::Uniform { min, max }, None) => {
+                            Ok(min + (max - min) * rng.gen::<f64>())
+                        }
+                        (ParameterValues::Normal { mean, std_dev }, None) => {
+                            let normal = Normal::new(*mean, *std_dev).map_err(|error| {
+                                io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    format!("parameter {}: {error}", parameter.name),
+                                )
+                            })?;
+                            Ok(normal.sample(&mut rng))
+                        }
+                        (ParameterValues::List(_), None) => unreachable!(),
+                    })
+                    .collect::<io::Result<_>>()?;
+                variants.push(variant);
+            }
+        }
+        Ok(variants)
+    }
+
+    // Simulate every variant, spread over `threads` threads. A run that panics (or whose joint
+    // states are no longer finite) is reported with an error instead of metrics. Fails before any
+    // run if the variants can't be drawn (see `variants`).
+    pub fn run(&self) -> io::Result<BatchResults> {
+        let variants = self.variants()?;
+        let runs: Vec<Mutex<Option<BatchRun>>> =
+            variants.iter().map(|_| Mutex::new(None)).collect();
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.clamp(1, variants.len().max(1)) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(values) = variants.get(index) else {
+                        break;
+                    };
+                    *runs[index].lock().unwrap() = Some(self.run_variant(values));
+                });
+            }
+        });
+
+        Ok(BatchResults {
+            parameters: self
+                .parameters
+                .iter()
+                .map(|parameter| parameter.name.clone())
+                .collect(),
+            metrics: self.metrics.iter().map(|(name, _)| name.clone()).collect(),
+            runs: runs
+                .into_iter()
+                .map(|run| run.into_inner().unwrap().unwrap())
+                .collect(),
+        })
+    }
+
+    fn run_variant(&self, values: &[f64]) -> BatchRun {
+        let start = Instant::now();
+        let mut parameters = self.base.clone();
+        for (parameter, value) in self.parameters.iter().zip(values.iter()) {
+            (parameter.set)(&mut parameters, *value);
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut app = (self.build)(parameters);
+            app.world.resource_mut::<SimTime>().end_time = Some(self.end_time);
+            run_headless(&mut app);
+
+            if let Some(failure) = app.world.get_resource::<SolverFailure>() {
+                return Err(failure.0.to_string());
+            }
+            let diverged = !(is_finite::<Joint>(&mut app.world)
+                && is_finite::<FloatingJoint>(&mut app.world)
+                && is_finite::<SphericalJoint>(&mut app.world));
+            if diverged {
+                return Err("the simulation diverged".to_string());
+            }
+            Ok(self
+                .metrics
+                .iter()
+                .map(|(_, metric)| metric(&mut app.world))
+                .collect())
+        }));
+
+        let (metrics, error) = match result {
+            Ok(Ok(metrics)) => (metrics, None),
+            Ok(Err(error)) => (vec![f64::NAN; self.metrics.len()], Some(error)),
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "the simulation panicked".to_string());
+                (vec![f64::NAN; self.metrics.len()], Some(message))
+            }
+        };
+
+        BatchRun {
+            parameters: values.to_vec(),
+            metrics,
+            wall_time: start.elapsed().as_secs_f64(),
+            error,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchRun {
+    pub parameters: Vec<f64>,
+    pub metrics: Vec<f64>, // NaN if the run failed
+    pub wall_time: f64,    // seconds
+    pub error: Option<String>,
+}
+
+// Statistics of one metric over the successful runs
+#[derive(Clone, Debug)]
+pub struct MetricSummary {
+    pub name: String,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchResults {
+    pub parameters: Vec<String>,
+    pub metrics: Vec<String>,
+    pub runs: Vec<BatchRun>,
+}
+
+impl BatchResults {
+    pub fn summary(&self) -> Vec<MetricSummary> {
+        self.metrics
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let values: Vec<f64> = self
+                    .runs
+                    .iter()
+                    .filter(|run| run.error.is_none())
+                    .map(|run| run.metrics[index])
+                    .collect();
+                let count = values.len() as f64;
+                let mean = values.iter().sum::<f64>() / count;
+                let variance = values
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>()
+                    / (count - 1.).max(1.);
+                MetricSummary {
+                    name: name.clone(),
+                    mean,
+                    std_dev: variance.sqrt(),
+                    min: values.iter().copied().fold(f64::NAN, f64::min),
+                    max: values.iter().copied().fold(f64::NAN, f64::max),
+                }
+            })
+            .collect()
+    }
+
+    // One row per run (numbered from 0, with the names and errors escaped like the recorder's CSV
+    // files), followed by the mean, std_dev, min and max of every metric
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        write!(file, "run")?;
+        for name in self.parameters.iter().chain(self.metrics.iter()) {
+            write!(file, ",{}", csv_field(name))?;
+        }
+        writeln!(file, ",wall_time,error")?;
+
+        for (index, run) in self.runs.iter().enumerate() {
+            write!(file, "{index}")?;
+            for value in run.parameters.iter().chain(run.metrics.iter()) {
+                write!(file, ",{value}")?;
+            }
+            let error = csv_field(run.error.as_deref().unwrap_or_default());
+            writeln!(file, ",{},{error}", run.wall_time)?;
+        }
+
+        let summary = self.summary();
+        for label in ["mean", "std_dev", "min", "max"] {
+            write!(file, "{label}")?;
+            for _ in self.parameters.iter() {
+                write!(file, ",")?;
+            }
+            for metric in summary.iter() {
+                let value = match label {
+                    "mean" => metric.mean,
+                    "std_dev" => metric.std_dev,
+                    "min" => metric.min,
+                    _ => metric.max,
+                };
+                write!(file, ",{value}")?;
+            }
+            writeln!(file, ",,")?;
+        }
+        file.flush()
+    }
+}
+
+// the states of every T are finite
+fn is_finite<T: Stateful + Component>(world: &mut World) -> bool {
+    let mut values: Vec<f64> = vec![0.; <T::State as StateVector>::SIZE];
+    world.query::<&T>().iter(world).all(|component| {
+        component.get_state().to_values(&mut values);
+        values.iter().all(|value| value.is_finite())
+    })
+}
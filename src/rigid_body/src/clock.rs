@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use bevy_integrator::SimTime;
+
+// time scales selected with the - and = keys
+const TIME_SCALES: [f64; 6] = [0.1, 0.25, 0.5, 1., 2., 4.];
+
+// real time over which the real-time factor is measured
+const MEASURE_TIME: f64 = 1.;
+
+// Controls the clock of the viewer, which paces the fixed time step loop. Headless simulations are
+// not paced, and don't use this resource.
+#[derive(Resource, Clone, Debug)]
+pub struct SimClock {
+    pub paused: bool,
+    pub time_scale: f64, // simulation time per real time, e.g. 0.1 for slow motion
+    steps: usize,        // single steps requested while paused
+    real_time_factor: f64,
+    measure_start: Option<(f64, f64)>, // real and simulation time at the start of the measurement
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock {
+            paused: false,
+            time_scale: 1.,
+            steps: 0,
+            real_time_factor: 0.,
+            measure_start: None,
+        }
+    }
+}
+
+impl SimClock {
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // advance exactly one physics step, and stay paused
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.steps += 1;
+    }
+
+    // the simulation time per real time that is actually achieved (zero while paused)
+    pub fn real_time_factor(&self) -> f64 {
+        self.real_time_factor
+    }
+}
+
+// Space pauses and resumes, "." advances one step, "-" and "=" slow down and speed up, and "0"
+// returns to real time
+pub fn clock_input_system(input: Res<Input<KeyCode>>, mut clock: ResMut<SimClock>) {
+    if input.just_pressed(KeyCode::Space) {
+        clock.toggle_pause();
+        info!("{}", if clock.paused { "paused" } else { "resumed" });
+    }
+    if input.just_pressed(KeyCode::Period) {
+        clock.step();
+    }
+
+    let scale = clock.time_scale;
+    if input.just_pressed(KeyCode::Minus) {
+        clock.time_scale = TIME_SCALES
+            .iter()
+            .rev()
+            .find(|candidate| **candidate < scale)
+            .map_or(scale, |candidate| *candidate);
+    }
+    if input.just_pressed(KeyCode::Equals) {
+        clock.time_scale = TIME_SCALES
+            .iter()
+            .find(|candidate| **candidate > scale)
+            .map_or(scale, |candidate| *candidate);
+    }
+    if input.just_pressed(KeyCode::Key0) {
+        clock.time_scale = 1.;
+    }
+    if clock.time_scale != scale {
+        info!("time scale {}x", clock.time_scale);
+        clock.measure_start = None;
+    }
+}
+
+// The fixed time step loop is driven by the virtual time, so pausing and scaling it controls the
+// physics. Each frame advances the virtual time by at most 0.25 s of real time (times the time
+// scale), so a slow simulation falls behind real time rather than building up a backlog of steps.
+pub fn apply_clock_system(clock: Res<SimClock>, mut time: ResMut<Time<Virtual>>) {
+    if clock.paused && !time.is_paused() {
+        time.pause();
+    } else if !clock.paused && time.is_paused() {
+        time.unpause();
+    }
+    if time.relative_speed_f64() != clock.time_scale {
+        time.set_relative_speed_f64(clock.time_scale);
+    }
+}
+
+// run the requested single steps (these don't depend on the virtual time)
+pub fn single_step_system(world: &mut World) {
+    let steps = std::mem::take(&mut world.resource_mut::<SimClock>().steps);
+    for _ in 0..steps {
+        world.run_schedule(FixedUpdate);
+    }
+}
+
+// Measure the real-time factor, and warn if the physics steps can't keep up with the time scale
+pub fn real_time_factor_system(
+    real: Res<Time<Real>>,
+    sim_time: Res<SimTime>,
+    mut clock: ResMut<SimClock>,
+) {
+    let real_time = real.elapsed_seconds_f64();
+    let time = sim_time.time();
+    if clock.paused {
+        clock.real_time_factor = 0.;
+        clock.measure_start = None;
+        return;
+    }
+
+    // a rewind moves the simulation time back, then the measurement restarts
+    if let Some((start_real, start_time)) = clock.measure_start.filter(|start| time >= start.1) {
+        if real_time - start_real < MEASURE_TIME {
+            return;
+        }
+        clock.real_time_factor = (time - start_time) / (real_time - start_real);
+        if clock.real_time_factor < 0.9 * clock.time_scale {
+            warn!(
+                "the simulation runs at {:.2}x real time instead of {}x: the physics steps take \
+                 longer than the time step",
+                clock.real_time_factor, clock.time_scale
+            );
+        }
+    }
+    clock.measure_start = Some((real_time, time));
+}
@@ -0,0 +1,721 @@
+use bevy::prelude::*;
+use bevy_integrator::{recorder::ChannelWriter, PhysicsState, StateVector};
+use nalgebra::{DMatrix, DVector, Rotation3, Vector6};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::algorithms::{loop_2_change, loop_3_change};
+use crate::joint::{
+    Base, FloatingJoint, FloatingState, Joint, JointState, SphericalJoint, SphericalState,
+};
+use crate::kinematics::PointMotion;
+use crate::limits::{JointLimit, LimitType};
+use crate::structure::{forward_dynamics, tree_order};
+use crate::sva::{Force, Matrix, Motion, Vector, Xform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConstraintType {
+    Point, // the origins of the two frames coincide (three constraints, like a ball joint)
+    Frame, // the two frames coincide (six constraints, a rigid connection)
+}
+
+// A loop closure constraint between frames on two joints of the tree, e.g. the outer end of a
+// suspension link that is also attached to the upright. The constraint forces are solved after the
+// joint accelerations (in the PhysicsSchedule), so that the constraint is met at the acceleration
+// level. The drift of the position and velocity is corrected by Baumgarte stabilization: the
+// constraint error decays like a critically damped system with the time constant
+// `time_constant` (which should be several time steps long).
+#[derive(Component, Clone, Debug)]
+pub struct LoopConstraint {
+    pub name: String,
+    pub constraint_type: ConstraintType,
+    pub joint_a: Entity,
+    pub frame_a: Xform, // from the body coordinates of joint_a to the constraint frame
+    pub joint_b: Entity,
+    pub frame_b: Xform,
+    pub time_constant: f64,
+
+    // solution
+    pub force: Force, // constraint force on joint_a (in base coordinates), joint_b has the opposite
+    pub position_error: f64, // distance between the frame origins
+    pub rotation_error: f64, // angle between the frames (Frame constraints only)
+    pub velocity_error: f64, // of all constrained directions
+}
+
+impl LoopConstraint {
+    // the points (in the body coordinates of each joint) coincide
+    pub fn point(
+        name: &str,
+        joint_a: Entity,
+        point_a: Vector,
+        joint_b: Entity,
+        point_b: Vector,
+    ) -> Self {
+        let frame_a = Xform::new(point_a, Matrix::identity());
+        let frame_b = Xform::new(point_b, Matrix::identity());
+        Self::new(
+            name,
+            ConstraintType::Point,
+            joint_a,
+            frame_a,
+            joint_b,
+            frame_b,
+        )
+    }
+
+    // the frames (from the body coordinates of each joint) coincide
+    pub fn frame(
+        name: &str,
+        joint_a: Entity,
+        frame_a: Xform,
+        joint_b: Entity,
+        frame_b: Xform,
+    ) -> Self {
+        Self::new(
+            name,
+            ConstraintType::Frame,
+            joint_a,
+            frame_a,
+            joint_b,
+            frame_b,
+        )
+    }
+
+    fn new(
+        name: &str,
+        constraint_type: ConstraintType,
+        joint_a: Entity,
+        frame_a: Xform,
+        joint_b: Entity,
+        frame_b: Xform,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            constraint_type,
+            joint_a,
+            frame_a,
+            joint_b,
+            frame_b,
+            time_constant: 0.02,
+            force: Force::zero(),
+            position_error: 0.,
+            rotation_error: 0.,
+            velocity_error: 0.,
+        }
+    }
+
+    pub fn with_time_constant(mut self, time_constant: f64) -> Self {
+        self.time_constant = time_constant;
+        self
+    }
+
+    // Errors of the constrained directions (rotation first, for Frame constraints), in base
+    // coordinates. The rotation error is the rotation vector of the relative rotation, and its rate
+    // the relative angular velocity w_a - w_b, which is only its derivative for small errors (the
+    // derivative of the rotation vector differs by a term of the order of the error times the angular
+    // velocity). The stabilization keeps the error small, so the error still decays as designed.
+    fn errors(&self, a: &FrameMotion, b: &FrameMotion) -> ConstraintErrors {
+        let position = ConstraintErrors {
+            error: dvector(a.position - b.position),
+            rate: dvector(a.v - b.v),
+            acceleration: dvector(a.a - b.a),
+        };
+        match self.constraint_type {
+            ConstraintType::Point => position,
+            ConstraintType::Frame => {
+                let rotation =
+                    Rotation3::from_matrix_unchecked(a.rotation * b.rotation.transpose());
+                ConstraintErrors {
+                    error: join(rotation.scaled_axis(), position.error),
+                    rate: join(a.w - b.w, position.rate),
+                    acceleration: join(a.dw - b.dw, position.acceleration),
+                }
+            }
+        }
+    }
+
+    // The forces on joint_a and joint_b of a unit constraint force in the direction of one
+    // constraint (a moment for the rotation constraints)
+    fn unit_forces(&self, row: usize, a: &FrameMotion, b: &FrameMotion) -> (Force, Force) {
+        let (rotation_rows, point) = match self.constraint_type {
+            ConstraintType::Point => (0, row),
+            ConstraintType::Frame => (3, row.wrapping_sub(3)),
+        };
+        if row < rotation_rows {
+            let moment = Vector::ith(row, 1.);
+            let force = Force {
+                f: Vector::zeros(),
+                m: moment,
+            };
+            (force, Force::zero() - force)
+        } else {
+            let direction = Vector::ith(point, 1.);
+            (
+                Force::force_point(direction, a.position),
+                Force::force_point(-direction, b.position),
+            )
+        }
+    }
+
+    fn rows(&self) -> usize {
+        match self.constraint_type {
+            ConstraintType::Point => 3,
+            ConstraintType::Frame => 6,
+        }
+    }
+}
+
+struct ConstraintErrors {
+    error: DVector<f64>,
+    rate: DVector<f64>,
+    acceleration: DVector<f64>,
+}
+
+fn dvector(vector: Vector) -> DVector<f64> {
+    DVector::from_column_slice(vector.as_slice())
+}
+
+fn join(rotation: Vector, position: DVector<f64>) -> DVector<f64> {
+    DVector::from_iterator(6, rotation.iter().chain(position.iter()).copied())
+}
+
+// The motion of a frame on a body, in base coordinates: the orientation (from the frame to the
+// base), position and the velocity and (classical) acceleration of the frame origin. The
+// accelerations include the acceleration of the base (the gravity), which is the same for all
+// frames.
+struct FrameMotion {
+    rotation: Matrix,
+    position: Vector,
+    w: Vector,
+    v: Vector,
+    dw: Vector,
+    a: Vector,
+}
+
+fn frame_motion(joint: &Joint, frame: &Xform) -> FrameMotion {
+    // the joint accelerations include the base acceleration, the same for both frames, so it
+    // isn't removed
+    let motion = PointMotion::new(joint, frame.position, &Motion::zero());
+    FrameMotion {
+        rotation: motion.rotation * frame.rotation.transpose(),
+        position: motion.position,
+        w: motion.angular_velocity,
+        v: motion.velocity,
+        dw: motion.angular_acceleration,
+        a: motion.acceleration,
+    }
+}
+
+// A unit constraint force in one constrained direction: opposite forces on the bodies of two joints
+// (in base coordinates), or a generalized force on a joint (of a joint limit)
+#[derive(Clone, Copy)]
+enum UnitForce {
+    Bodies(Entity, Force, Entity, Force),
+    Joint(Entity, f64),
+}
+
+impl UnitForce {
+    // the external and generalized forces on each joint, scaled
+    fn joint_forces(&self, scale: f64) -> Vec<(Entity, Force, f64)> {
+        match *self {
+            UnitForce::Bodies(joint_a, force_a, joint_b, force_b) => vec![
+                (joint_a, scale * force_a, 0.),
+                (joint_b, scale * force_b, 0.),
+            ],
+            UnitForce::Joint(joint, tau) => vec![(joint, Force::zero(), scale * tau)],
+        }
+    }
+}
+
+// The constrained directions: their unit forces, the targets of their accelerations (or velocity
+// changes), and whether their forces can only push (the joint limits)
+#[derive(Default)]
+struct ConstraintRows {
+    units: Vec<UnitForce>,
+    targets: Vec<f64>,
+    unilateral: Vec<bool>,
+}
+
+impl ConstraintRows {
+    fn push(&mut self, unit: UnitForce, target: f64, unilateral: bool) {
+        self.units.push(unit);
+        self.targets.push(target);
+        self.unilateral.push(unilateral);
+    }
+
+    // The rows of the loop constraints (in the order of `constraints`), with the targets from
+    // `target(constraint, errors)`
+    fn push_loop_constraints<'a>(
+        &mut self,
+        joint_query: &Query<&mut Joint>,
+        constraints: impl Iterator<Item = &'a LoopConstraint>,
+        target: impl Fn(&LoopConstraint, &ConstraintErrors) -> DVector<f64>,
+    ) {
+        for constraint in constraints {
+            let Some((a, b)) = motions(joint_query, constraint) else {
+                warn!(
+                    "the joints of loop constraint {} were not found",
+                    constraint.name
+                );
+                continue;
+            };
+            let targets = target(constraint, &constraint.errors(&a, &b));
+            for (row, target) in targets.iter().enumerate() {
+                let (force_a, force_b) = constraint.unit_forces(row, &a, &b);
+                let unit =
+                    UnitForce::Bodies(constraint.joint_a, force_a, constraint.joint_b, force_b);
+                self.push(unit, *target, false);
+            }
+        }
+    }
+}
+
+// the motion of the frames of a loop constraint
+fn motions(
+    joint_query: &Query<&mut Joint>,
+    constraint: &LoopConstraint,
+) -> Option<(FrameMotion, FrameMotion)> {
+    let a = joint_query.get(constraint.joint_a).ok()?;
+    let b = joint_query.get(constraint.joint_b).ok()?;
+    Some((
+        frame_motion(a, &constraint.frame_a),
+        frame_motion(b, &constraint.frame_b),
+    ))
+}
+
+// The accelerations of the constrained directions: of the loop constraints, then of the limited
+// joints (in the direction of their limit forces)
+fn row_accelerations<'a>(
+    joint_query: &Query<&mut Joint>,
+    constraints: impl Iterator<Item = &'a LoopConstraint>,
+    limits: &[(Entity, f64)],
+) -> Vec<f64> {
+    let mut accelerations = Vec::new();
+    for constraint in constraints {
+        if let Some((a, b)) = motions(joint_query, constraint) {
+            accelerations.extend(constraint.errors(&a, &b).acceleration.iter());
+        }
+    }
+    for (entity, direction) in limits {
+        if let Ok(joint) = joint_query.get(*entity) {
+            accelerations.push(direction * joint.qdd);
+        }
+    }
+    accelerations
+}
+
+// The change of `measure` (e.g. the constraint accelerations) for each unit force. The
+// accelerations are linear in the forces, and the articulated inertias don't depend on them, so the
+// changes of the joint accelerations of all unit forces are found in a single pass over `tree` (see
+// `acceleration_changes`), and `measure` is evaluated with each of them added. The same changes are
+// the velocity changes of a unit impulse. Returns `measure` without the unit forces, and the changes
+// (one column per unit force).
+fn unit_responses(
+    joint_query: &mut Query<&mut Joint>,
+    tree: &[(Entity, Option<usize>)],
+    units: &[UnitForce],
+    measure: impl Fn(&Query<&mut Joint>) -> Vec<f64>,
+) -> (DVector<f64>, DMatrix<f64>) {
+    let free = DVector::from_vec(measure(joint_query));
+    let changes = acceleration_changes(joint_query, tree, units);
+    let accelerations: Vec<_> = tree
+        .iter()
+        .map(|(entity, _)| {
+            let joint = joint_query.get(*entity).unwrap();
+            (joint.a, joint.aj, joint.qdd)
+        })
+        .collect();
+
+    let mut response = DMatrix::zeros(free.len(), units.len());
+    for (j, changes) in changes.iter().enumerate() {
+        for (((entity, _), (a, aj, qdd)), (da, daj, dqdd)) in
+            tree.iter().zip(accelerations.iter()).zip(changes.iter())
+        {
+            let mut joint = joint_query.get_mut(*entity).unwrap();
+            joint.a = *a + *da;
+            joint.aj = *aj + *daj;
+            joint.qdd = qdd + dqdd;
+        }
+        response.set_column(j, &(DVector::from_vec(measure(joint_query)) - &free));
+    }
+    for ((entity, _), (a, aj, qdd)) in tree.iter().zip(accelerations) {
+        let mut joint = joint_query.get_mut(*entity).unwrap();
+        (joint.a, joint.aj, joint.qdd) = (a, aj, qdd);
+    }
+    (free, response)
+}
+
+// The changes of the accelerations (a, aj and qdd) of the joints of `tree` for each unit force: loops
+// 2 and 3 for the changes of the forces only (`loop_2_change` and `loop_3_change`), run for all unit
+// forces at once
+fn acceleration_changes(
+    joint_query: &Query<&mut Joint>,
+    tree: &[(Entity, Option<usize>)],
+    units: &[UnitForce],
+) -> Vec<Vec<(Motion, Motion, f64)>> {
+    let index: HashMap<Entity, usize> = tree
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, _))| (*entity, index))
+        .collect();
+    let joints: Vec<&Joint> = tree
+        .iter()
+        .map(|(entity, _)| joint_query.get(*entity).unwrap())
+        .collect();
+
+    // the changes of the bias forces and joint forces (by joint, then unit force)
+    let mut pa = vec![vec![Force::zero(); units.len()]; tree.len()];
+    let mut tau = vec![vec![0.; units.len()]; tree.len()];
+    for (k, unit) in units.iter().enumerate() {
+        for (entity, force, unit_tau) in unit.joint_forces(1.) {
+            if let Some(&i) = index.get(&entity) {
+                pa[i][k] -= joints[i].x * force;
+                tau[i][k] += unit_tau;
+            }
+        }
+    }
+
+    // inward, children before their parents
+    let mut u = vec![vec![Vector6::zeros(); units.len()]; tree.len()];
+    for i in (0..tree.len()).rev() {
+        for k in 0..units.len() {
+            u[i][k] = loop_2_change(joints[i], &mut pa[i][k], tau[i][k]);
+            if let Some(parent) = tree[i].1 {
+                let change = pa[i][k];
+                pa[parent][k] += change;
+            }
+        }
+    }
+
+    // outward, the base acceleration doesn't change
+    let mut changes: Vec<Vec<(Motion, Motion, f64)>> =
+        vec![Vec::with_capacity(tree.len()); units.len()];
+    for (i, (_, parent)) in tree.iter().enumerate() {
+        for (k, changes) in changes.iter_mut().enumerate() {
+            let parent_a = parent.map_or(Motion::zero(), |parent| changes[parent].0);
+            changes.push(loop_3_change(joints[i], &parent_a, &u[i][k]));
+        }
+    }
+    changes
+}
+
+// Least squares solution of `response * forces = rhs`, as some constraints may be redundant (e.g. a
+// planar linkage closed by a point constraint). The unilateral forces can only push: while any of
+// them is negative, the most negative is dropped and the rest are solved again. None if the
+// equations are not finite (the SVD would not converge).
+fn solve_rows(
+    response: &DMatrix<f64>,
+    rhs: &DVector<f64>,
+    unilateral: &[bool],
+) -> Option<DVector<f64>> {
+    if response
+        .iter()
+        .chain(rhs.iter())
+        .any(|value| !value.is_finite())
+    {
+        return None;
+    }
+
+    let mut active: Vec<usize> = (0..rhs.len()).collect();
+    let mut forces = DVector::zeros(rhs.len());
+    while !active.is_empty() {
+        let svd = response
+            .select_rows(&active)
+            .select_columns(&active)
+            .svd(true, true);
+        let eps = 1e-10 * svd.singular_values.max();
+        let solution = svd.solve(&rhs.select_rows(&active), eps).ok()?;
+
+        let pulling = active
+            .iter()
+            .zip(solution.iter())
+            .filter(|(row, force)| unilateral[**row] && **force < 0.)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(row, _)| *row);
+        match pulling {
+            Some(row) => active.retain(|active_row| *active_row != row),
+            None => {
+                for (row, force) in active.iter().zip(solution.iter()) {
+                    forces[*row] = *force;
+                }
+                break;
+            }
+        }
+    }
+    Some(forces)
+}
+
+// Solve the forces of the loop constraints and the hard joint limits, and update the joint
+// accelerations. The accelerations depend linearly on the constraint forces, so the response to a
+// unit force in each constrained direction is found by running loops 2 and 3 again with that force,
+// and the forces that give the stabilized constraint accelerations are the solution of the
+// resulting linear system. A hard limit is a constraint while its joint is past the limit, as long
+// as its force pushes.
+pub fn constraint_forces(
+    base_query: Query<Entity, With<Base>>,
+    joint_children_query: Query<&Children, With<Joint>>,
+    mut joint_query: Query<&mut Joint>,
+    mut constraint_query: Query<&mut LoopConstraint>,
+    mut limit_query: Query<(Entity, &mut JointLimit)>,
+) {
+    let mut rows = ConstraintRows::default();
+    rows.push_loop_constraints(
+        &joint_query,
+        constraint_query.iter(),
+        |constraint, errors| {
+            let tau = constraint.time_constant;
+            -2. / tau * &errors.rate - 1. / tau.powi(2) * &errors.error
+        },
+    );
+
+    // the hard limits in contact, and the direction of their forces
+    let mut limits = Vec::new();
+    for (entity, mut limit) in limit_query.iter_mut() {
+        if !limit.is_hard() {
+            continue;
+        }
+        limit.force = 0.;
+        let Ok(joint) = joint_query.get(entity) else {
+            continue;
+        };
+        if let Some(direction) = limit.contact(joint.q) {
+            // a joint moving into the limit is stopped by an impact after the step, only the
+            // separation is damped
+            let tau = limit.time_constant;
+            let separation = (direction * joint.qd).max(0.);
+            let penetration = direction * limit.penetration(joint.q);
+            let target = -2. / tau * separation - 1. / tau.powi(2) * penetration;
+            rows.push(UnitForce::Joint(entity, direction), target, true);
+            limits.push((entity, direction));
+        }
+    }
+
+    if rows.units.is_empty() {
+        return;
+    }
+
+    let tree = tree_order(&base_query, &joint_children_query, &joint_query);
+    let (free, response) = unit_responses(&mut joint_query, &tree, &rows.units, |joint_query| {
+        row_accelerations(joint_query, constraint_query.iter(), &limits)
+    });
+    let rhs = DVector::from_vec(rows.targets) - free;
+    let Some(forces) = solve_rows(&response, &rhs, &rows.unilateral) else {
+        warn!("the constraint equations are not finite, the constraint forces are not applied");
+        forward_dynamics(&base_query, &joint_children_query, &mut joint_query);
+        return;
+    };
+
+    let constraint_forces: Vec<_> = rows
+        .units
+        .iter()
+        .zip(forces.iter())
+        .flat_map(|(unit, force)| unit.joint_forces(*force))
+        .collect();
+    apply_forces(&mut joint_query, &constraint_forces);
+    forward_dynamics(&base_query, &joint_children_query, &mut joint_query);
+
+    // the solution, and the constraint violation
+    let mut row = 0;
+    for mut constraint in constraint_query.iter_mut() {
+        let Some((a, b)) = motions(&joint_query, &constraint) else {
+            continue;
+        };
+        let errors = constraint.errors(&a, &b);
+        let size = constraint.rows();
+        let (rotation, position) = errors.error.as_slice().split_at(size - 3);
+        constraint.position_error = DVector::from_column_slice(position).norm();
+        constraint.rotation_error = DVector::from_column_slice(rotation).norm();
+        constraint.velocity_error = errors.rate.norm();
+        constraint.force = rows.units[row..row + size]
+            .iter()
+            .zip(forces.rows(row, size).iter())
+            .fold(Force::zero(), |force, (unit, scale)| match unit {
+                UnitForce::Bodies(_, unit, _, _) => force + *scale * *unit,
+                UnitForce::Joint(..) => force,
+            });
+        row += size;
+    }
+    for ((entity, direction), force) in limits.iter().zip(forces.rows(row, limits.len()).iter()) {
+        if let Ok((_, mut limit)) = limit_query.get_mut(*entity) {
+            limit.force = direction * force;
+        }
+    }
+}
+
+// the states of all joint velocities
+type VelocityStates<'w> = (
+    ResMut<'w, PhysicsState<Joint>>,
+    Option<ResMut<'w, PhysicsState<FloatingJoint>>>,
+    Option<ResMut<'w, PhysicsState<SphericalJoint>>>,
+);
+
+// Impacts of the hard joint limits, after every step. A joint past its limit and still moving into
+// it is stopped by an impulse, and bounces back with `restitution` times its velocity. The impulses
+// act on the whole tree (the velocities of all joints change), and don't change the velocities of
+// the loop constraints. The new velocities are written to the state, so the next step starts from
+// them.
+pub fn limit_impacts(
+    base_query: Query<Entity, With<Base>>,
+    joint_children_query: Query<&Children, With<Joint>>,
+    mut joint_query: Query<&mut Joint>,
+    constraint_query: Query<&LoopConstraint>,
+    mut limit_query: Query<(Entity, &mut JointLimit)>,
+    mut multi_dof_query: Query<(Option<&mut FloatingJoint>, Option<&mut SphericalJoint>)>,
+    (mut joint_state, mut floating_state, mut spherical_state): VelocityStates,
+) {
+    // the impacting limits, the direction of their impulses, and the velocity change
+    let mut impacts = Vec::new();
+    for (entity, mut limit) in limit_query.iter_mut() {
+        let LimitType::Hard { restitution } = limit.limit_type else {
+            continue;
+        };
+        limit.impulse = 0.;
+        let Ok(joint) = joint_query.get(entity) else {
+            continue;
+        };
+        if let Some(direction) = limit.contact(joint.q) {
+            let velocity = direction * joint.qd;
+            if velocity < 0. {
+                let restitution = if -velocity > limit.rest_velocity {
+                    restitution
+                } else {
+                    0.
+                };
+                impacts.push((entity, direction, -(1. + restitution) * velocity));
+            }
+        }
+    }
+    if impacts.is_empty() {
+        return;
+    }
+
+    let mut rows = ConstraintRows::default();
+    rows.push_loop_constraints(&joint_query, constraint_query.iter(), |_, errors| {
+        DVector::zeros(errors.rate.len())
+    });
+    for (entity, direction, velocity_change) in impacts.iter() {
+        rows.push(
+            UnitForce::Joint(*entity, *direction),
+            *velocity_change,
+            true,
+        );
+    }
+    let limits: Vec<_> = impacts
+        .iter()
+        .map(|(entity, direction, _)| (*entity, *direction))
+        .collect();
+
+    // the velocity changes of the constrained directions, then of every joint (qd, and the
+    // velocity of the multi degree of freedom joints)
+    let entities = joint_state.entities().to_vec();
+    let n = rows.units.len();
+    let tree = tree_order(&base_query, &joint_children_query, &joint_query);
+    let (_, response) = unit_responses(&mut joint_query, &tree, &rows.units, |joint_query| {
+        let mut changes = row_accelerations(joint_query, constraint_query.iter(), &limits);
+        for entity in entities.iter() {
+            let joint = joint_query.get(*entity).unwrap();
+            changes.push(joint.qdd);
+            changes.extend(joint.aj.w.iter().chain(joint.aj.v.iter()));
+        }
+        changes
+    });
+    forward_dynamics(&base_query, &joint_children_query, &mut joint_query);
+
+    let rhs = DVector::from_vec(rows.targets);
+    let Some(impulses) = solve_rows(&response.rows(0, n).into(), &rhs, &rows.unilateral) else {
+        warn!("the joint limit impact equations are not finite, the impulses are not applied");
+        return;
+    };
+    let changes = response.rows(n, response.nrows() - n) * &impulses;
+
+    for ((entity, direction, _), impulse) in impacts
+        .iter()
+        .zip(impulses.rows(n - impacts.len(), impacts.len()).iter())
+    {
+        if let Ok((_, mut limit)) = limit_query.get_mut(*entity) {
+            limit.impulse = direction * impulse;
+        }
+    }
+
+    // the new velocities
+    for (entity, change) in entities.iter().zip(changes.as_slice().chunks(7)) {
+        let (qdd, aj) = (
+            change[0],
+            Motion::new(
+                [change[4], change[5], change[6]],
+                [change[1], change[2], change[3]],
+            ),
+        );
+        if let Ok(mut joint) = joint_query.get_mut(*entity) {
+            joint.qd += qdd;
+        }
+        if let Some(values) = joint_state.values_mut(entity) {
+            values[JointState::index("qd").unwrap()] += qdd;
+        }
+        let Ok((floating, spherical)) = multi_dof_query.get_mut(*entity) else {
+            continue;
+        };
+        if let Some(mut floating) = floating {
+            floating.v = floating.v + aj;
+            if let Some(values) = floating_state
+                .as_mut()
+                .and_then(|state| state.values_mut(entity))
+            {
+                add_values(
+                    values,
+                    FloatingState::index("wx").unwrap(),
+                    aj.w.iter().chain(aj.v.iter()),
+                );
+            }
+        }
+        if let Some(mut spherical) = spherical {
+            spherical.w += aj.w;
+            if let Some(values) = spherical_state
+                .as_mut()
+                .and_then(|state| state.values_mut(entity))
+            {
+                add_values(values, SphericalState::index("wx").unwrap(), aj.w.iter());
+            }
+        }
+    }
+}
+
+// add changes to consecutive values, from `start`
+fn add_values<'a>(values: &mut [f64], start: usize, changes: impl Iterator<Item = &'a f64>) {
+    for (value, change) in values[start..].iter_mut().zip(changes) {
+        *value += change;
+    }
+}
+
+// add external and generalized forces to joints
+fn apply_forces(joint_query: &mut Query<&mut Joint>, forces: &[(Entity, Force, f64)]) {
+    for (entity, force, tau) in forces {
+        if let Ok(mut joint) = joint_query.get_mut(*entity) {
+            joint.f_ext += *force;
+            joint.tau += tau;
+        }
+    }
+}
+
+// Recorder source for the loop constraints: "{name}.position_error", "{name}.rotation_error" and
+// "{name}.velocity_error"
+pub fn record_loop_constraints(world: &mut World, writer: &mut ChannelWriter) {
+    let mut constraints = world.query::<&LoopConstraint>();
+    for constraint in constraints.iter(world) {
+        writer.write(
+            &constraint.name,
+            "position_error",
+            constraint.position_error,
+        );
+        writer.write(
+            &constraint.name,
+            "rotation_error",
+            constraint.rotation_error,
+        );
+        writer.write(
+            &constraint.name,
+            "velocity_error",
+            constraint.velocity_error,
+        );
+    }
+}
@@ -1,31 +1,62 @@
-use crate::sva::Xform;
+use crate::sva::{Vector, Xform};
 use bevy::prelude::{Color, Component, Transform};
+use nalgebra::{Quaternion, UnitQuaternion};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct MeshDef {
     pub mesh_type: MeshTypeDef,
+    #[serde(default)]
     pub transform: TransformDef,
+    #[serde(with = "rgba")]
     pub color: Color,
 }
 
-#[derive(Debug, Clone)]
+// colors are written as [r, g, b, a]
+mod rgba {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        color.as_rgba_f32().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let [r, g, b, a] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(Color::rgba(r, g, b, a))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MeshTypeDef {
     Box { dimensions: [f32; 3] },
     Cylinder { height: f32, radius: f32 },
     Wheel { radius: f32, width: f32 },
     File { file_name: String },
-    // Sphere { radius: f64 },
-    // Mesh { filename: String },
+    Sphere { radius: f32 },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TransformDef {
     Identity,
-    Position { x: f64, y: f64, z: f64 },
-    Quaternion { x: f64, y: f64, z: f64, w: f64 },
+    Position {
+        x: f64,
+        y: f64,
+        z: f64,
+    },
+    Quaternion {
+        x: f64,
+        y: f64,
+        z: f64,
+        w: f64,
+    },
     RotationX(f64),
     RotationY(f64),
     RotationZ(f64),
+    // a position and an orientation (quaternion [x, y, z, w]) in the joint frame
+    Pose {
+        position: [f64; 3],
+        quaternion: [f64; 4],
+    },
 }
 
 impl Default for TransformDef {
@@ -43,6 +74,13 @@ impl From<&TransformDef> for Xform {
             TransformDef::RotationX(angle) => Xform::rotx(*angle),
             TransformDef::RotationY(angle) => Xform::roty(*angle),
             TransformDef::RotationZ(angle) => Xform::rotz(*angle),
+            TransformDef::Pose {
+                position,
+                quaternion: [x, y, z, w],
+            } => Xform::from_unit_quaternion(
+                Vector::from_column_slice(position),
+                &UnitQuaternion::from_quaternion(Quaternion::new(*w, *x, *y, *z)),
+            ),
             // TransformDef::RotationVector(vector) => Xform::from_rotation_vector(vector),
         }
     }
@@ -73,7 +111,15 @@ impl From<&TransformDef> for Transform {
                 let mut transform = Transform::IDENTITY;
                 transform.rotate_local_z(*angle as f32);
                 transform
-            } // TransformDef::RotationVector(vector) => Xform::from_rotation_vector(vector),
+            }
+            TransformDef::Pose {
+                position: [x, y, z],
+                quaternion: [qx, qy, qz, qw],
+            } => Transform::from_xyz(*x as f32, *y as f32, *z as f32).with_rotation(
+                bevy::math::Quat::from_xyzw(*qx as f32, *qy as f32, *qz as f32, *qw as f32)
+                    .normalize(),
+            ),
+            // TransformDef::RotationVector(vector) => Xform::from_rotation_vector(vector),
         }
     }
 }
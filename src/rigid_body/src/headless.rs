@@ -0,0 +1,62 @@
+use bevy::{
+    app::{AppExit, PluginsState},
+    prelude::*,
+};
+use bevy_integrator::{SimTime, Stateful};
+
+use crate::joint::{FloatingJoint, Joint, JointState};
+
+// Run an app built with a headless RigidBodyPlugin until the simulation is complete. Unlike
+// `App::run`, the app is handed back to the caller, so the final state can be read from the world.
+pub fn run_headless(app: &mut App) {
+    let end_time = app.world.resource::<SimTime>().end_time;
+    assert!(
+        end_time.is_some(),
+        "headless simulations need an end time, otherwise they never finish"
+    );
+
+    // same start up sequence as the default runners
+    while app.plugins_state() == PluginsState::Adding {
+        bevy::tasks::tick_global_task_pools_on_main_thread();
+    }
+    app.finish();
+    app.cleanup();
+
+    loop {
+        app.update();
+
+        let complete = app.world.resource::<SimTime>().is_complete();
+        let exit = app
+            .world
+            .get_resource::<Events<AppExit>>()
+            .is_some_and(|events| !events.is_empty());
+        if complete || exit {
+            break;
+        }
+    }
+}
+
+// name and state of every joint, sorted by name
+pub fn joint_states(world: &mut World) -> Vec<(String, JointState)> {
+    let mut states: Vec<(String, JointState)> = world
+        .query::<&Joint>()
+        .iter(world)
+        // skip the base, and the multi degree of freedom joints (their state is in their own
+        // component)
+        .filter(|joint| !joint.name.is_empty() && !joint.is_multi_dof())
+        .map(|joint| (joint.get_name(), joint.get_state()))
+        .collect();
+    states.sort_by(|a, b| a.0.cmp(&b.0));
+    states
+}
+
+// the floating joints, sorted by name
+pub fn floating_joints(world: &mut World) -> Vec<FloatingJoint> {
+    let mut joints: Vec<FloatingJoint> = world
+        .query::<&FloatingJoint>()
+        .iter(world)
+        .cloned()
+        .collect();
+    joints.sort_by(|a, b| a.name.cmp(&b.name));
+    joints
+}
@@ -1,9 +1,10 @@
 use bevy::prelude::*;
-use bevy_integrator::Stateful;
+use bevy_integrator::{recorder::ChannelWriter, PhysicsState, StateKind, StateVector, Stateful};
+use nalgebra::{Matrix6, Quaternion, UnitQuaternion, Vector6};
 use std::ops::{Add, Mul};
 
 use crate::mesh::Mesh as RBDA_Mesh;
-use crate::sva::{Force, Inertia, InertiaAB, Motion, Xform};
+use crate::sva::{Force, Inertia, InertiaAB, Motion, Vector, Xform};
 
 #[derive(Default, Debug)]
 pub enum JointType {
@@ -15,6 +16,19 @@ pub enum JointType {
     Px,
     Py,
     Pz,
+    // about or along a unit axis of the joint frame
+    Revolute(Vector),
+    Prismatic(Vector),
+    // rotation about a unit axis, and translation along it by pitch (m/rad) times the rotation
+    Helical {
+        axis: Vector,
+        pitch: f64,
+    },
+    // six degrees of freedom, with the state in a FloatingJoint component (see `Joint::floating`)
+    Floating,
+    // three rotational degrees of freedom (a ball joint), with the state in a SphericalJoint
+    // component (see `Joint::spherical`)
+    Spherical,
 }
 
 #[derive(Component, Default, Debug)]
@@ -43,6 +57,7 @@ pub struct Joint {
     pub vj: Motion,
     pub c: Motion,
     pub a: Motion,
+    pub aj: Motion, // joint acceleration (s * qdd)
 
     // algorithm specific parameters
     pub iaa: InertiaAB,
@@ -52,6 +67,12 @@ pub struct Joint {
     pub dd: f64,
     pub u: f64,
     pub uu: Force,
+    // U, D^-1 and u of a multi degree of freedom joint (the first N rows and columns, for its N
+    // degrees of freedom), from loop 2 for loop 3
+    pub uu_n: Matrix6<f64>,
+    pub dd_inv_n: Matrix6<f64>,
+    pub u_n: Vector6<f64>,
+    pub singular: bool, // D was singular (the error is only logged once)
     pub meshes: Vec<RBDA_Mesh>,
 }
 
@@ -137,6 +158,87 @@ impl Joint {
             ..Default::default()
         }
     }
+
+    // revolute joint about a unit axis (normalized here), e.g. an inclined steering axis
+    pub fn revolute(name: String, inertia: Inertia, xt: Xform, axis: Vector) -> Self {
+        let axis = axis.normalize();
+        let s = Motion::new([0., 0., 0.], axis.into());
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Revolute(axis),
+            ..Default::default()
+        }
+    }
+
+    // prismatic joint along a unit axis (normalized here)
+    pub fn prismatic(name: String, inertia: Inertia, xt: Xform, axis: Vector) -> Self {
+        let axis = axis.normalize();
+        let s = Motion::new(axis.into(), [0., 0., 0.]);
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Prismatic(axis),
+            ..Default::default()
+        }
+    }
+
+    // Helical (screw) joint about a unit axis (normalized here). The body moves `pitch` along the
+    // axis per radian of rotation (the lead of the screw is 2 pi pitch).
+    pub fn helical(name: String, inertia: Inertia, xt: Xform, axis: Vector, pitch: f64) -> Self {
+        let axis = axis.normalize();
+        let s = Motion::new((pitch * axis).into(), axis.into());
+
+        Self {
+            name,
+            i: inertia,
+            xt,
+            s,
+            joint_type: JointType::Helical { axis, pitch },
+            ..Default::default()
+        }
+    }
+
+    // A free body, e.g. a vehicle chassis. The orientation is a quaternion, so unlike a chain of
+    // single axis joints it has no gimbal lock. Its state is held by the FloatingJoint component, which
+    // has to be spawned with the joint (the joint's q and qd are unused):
+    // `commands.spawn(Joint::floating(name, inertia, xt))`
+    pub fn floating(name: String, inertia: Inertia, xt: Xform) -> (Self, FloatingJoint) {
+        let joint = Self {
+            name: name.clone(),
+            i: inertia,
+            xt,
+            joint_type: JointType::Floating,
+            ..Default::default()
+        };
+        (joint, FloatingJoint::new(name))
+    }
+
+    // A ball joint. Its state is held by the SphericalJoint component, which has to be spawned with
+    // the joint (the joint's q and qd are unused): `commands.spawn(Joint::spherical(name, inertia, xt))`
+    pub fn spherical(name: String, inertia: Inertia, xt: Xform) -> (Self, SphericalJoint) {
+        let joint = Self {
+            name: name.clone(),
+            i: inertia,
+            xt,
+            joint_type: JointType::Spherical,
+            ..Default::default()
+        };
+        (joint, SphericalJoint::new(name))
+    }
+
+    // Joints with more than one degree of freedom (floating and spherical joints). Their state is in
+    // a separate component, and their motion subspace has multiple columns (the single column `s` is
+    // unused).
+    pub fn is_multi_dof(&self) -> bool {
+        matches!(self.joint_type, JointType::Floating | JointType::Spherical)
+    }
 }
 
 pub fn bevy_joint_positions(mut joint_transform_query: Query<(&mut Joint, &mut Transform)>) {
@@ -163,14 +265,33 @@ pub fn bevy_joint_positions(mut joint_transform_query: Query<(&mut Joint, &mut T
     }
 }
 
-impl Into<f64> for JointState {
-    fn into(self) -> f64 {
-        self.q
+// Recorder source for the joint states: "{name}.q", "{name}.qd" and "{name}.qdd" for every named
+// joint. The positions and velocities are the integrated state at the end of the step, and the
+// accelerations are evaluated at that state.
+pub fn record_joints(world: &mut World, writer: &mut ChannelWriter) {
+    let mut joints = world.query::<&Joint>();
+    let physics_state = world.resource::<PhysicsState<Joint>>();
+    for entity in physics_state.entities() {
+        let Ok(joint) = joints.get(world, *entity) else {
+            continue;
+        };
+        if joint.name.is_empty() || joint.is_multi_dof() {
+            continue; // the base, and multi degree of freedom joints (see `record_state_values`)
+        }
+        if let (Some(state), Some(dstate)) = (
+            physics_state.get_state(entity),
+            physics_state.get_dstate(entity),
+        ) {
+            writer.write(&joint.name, "q", state.q);
+            writer.write(&joint.name, "qd", state.qd);
+            writer.write(&joint.name, "qdd", dstate.qd);
+        }
     }
 }
 
 impl Stateful for Joint {
     type State = JointState;
+    const CHECKPOINT_KEY: &'static str = "joints";
     fn get_state(&self) -> Self::State {
         Self::State {
             q: self.q,
@@ -224,6 +345,19 @@ impl JointState {
     }
 }
 
+impl StateVector for JointState {
+    const SIZE: usize = 2;
+    const KINDS: &'static [StateKind] = &[StateKind::Position, StateKind::Velocity];
+    const NAMES: &'static [&'static str] = &["q", "qd"];
+    fn to_values(&self, values: &mut [f64]) {
+        values[0] = self.q;
+        values[1] = self.qd;
+    }
+    fn from_values(values: &[f64]) -> Self {
+        Self::new(values[0], values[1])
+    }
+}
+
 impl Add for JointState {
     type Output = JointState;
     fn add(self, other: JointState) -> JointState {
@@ -243,3 +377,233 @@ impl Mul<f64> for JointState {
         }
     }
 }
+
+// The state of a floating joint: the orientation and position of the body in the parent frame (of
+// the joint's xt), and the spatial velocity of the body in body coordinates. The body velocity is
+// integrated directly (its derivative is the joint acceleration `a`), and the orientation and
+// position from it.
+#[derive(Component, Debug, Clone)]
+pub struct FloatingJoint {
+    pub name: String,
+    pub rotation: UnitQuaternion<f64>, // body to parent
+    pub position: Vector,              // of the body origin, in parent coordinates
+    pub v: Motion,                     // body coordinates
+    pub a: Motion,                     // derivative of v (body coordinates)
+}
+
+impl FloatingJoint {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            rotation: UnitQuaternion::identity(),
+            position: Vector::zeros(),
+            v: Motion::zero(),
+            a: Motion::zero(),
+        }
+    }
+
+    // joint transform, from the parent to the body
+    pub fn xj(&self) -> Xform {
+        Xform::from_unit_quaternion(self.position, &self.rotation)
+    }
+}
+
+impl Stateful for FloatingJoint {
+    type State = FloatingState;
+    const CHECKPOINT_KEY: &'static str = "floating_joints";
+    fn get_state(&self) -> Self::State {
+        Self::State {
+            rotation: *self.rotation.quaternion(),
+            position: self.position,
+            v: self.v,
+        }
+    }
+
+    fn set_state(&mut self, state: &Self::State) {
+        // the solvers stay on the unit sphere, but finite differences (e.g. of the implicit solvers)
+        // perturb single values
+        self.rotation = UnitQuaternion::from_quaternion(state.rotation);
+        self.position = state.position;
+        self.v = state.v;
+    }
+
+    fn get_dstate(&self) -> Self::State {
+        Self::State {
+            rotation: 0.5 * self.rotation.quaternion() * Quaternion::from_imag(self.v.w),
+            position: self.rotation * self.v.v,
+            v: self.a,
+        }
+    }
+
+    fn set_dstate(&mut self, dstate: Self::State) {
+        self.a = dstate.v;
+    }
+
+    fn reset(&mut self) {
+        self.a = Motion::zero();
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+// The state of a floating joint, or its derivative (`rotation` is then the derivative of the
+// quaternion)
+#[derive(Clone, Debug)]
+pub struct FloatingState {
+    pub rotation: Quaternion<f64>,
+    pub position: Vector,
+    pub v: Motion,
+}
+
+impl StateVector for FloatingState {
+    const SIZE: usize = 13;
+    // The positions are not paired with velocities (the body velocity is not their derivative), so
+    // the symplectic solvers integrate floating joints explicitly, and the trim only searches them
+    // when they are listed in `TrimSettings::pairs`.
+    const NAMES: &'static [&'static str] = &[
+        "qw", "qx", "qy", "qz", "x", "y", "z", "wx", "wy", "wz", "vx", "vy", "vz",
+    ];
+    fn to_values(&self, values: &mut [f64]) {
+        values[0] = self.rotation.w;
+        values[1..4].copy_from_slice(self.rotation.imag().as_slice());
+        values[4..7].copy_from_slice(self.position.as_slice());
+        values[7..10].copy_from_slice(self.v.w.as_slice());
+        values[10..13].copy_from_slice(self.v.v.as_slice());
+    }
+    fn from_values(values: &[f64]) -> Self {
+        Self {
+            rotation: Quaternion::new(values[0], values[1], values[2], values[3]),
+            position: Vector::from_column_slice(&values[4..7]),
+            v: Motion::new(
+                [values[10], values[11], values[12]],
+                [values[7], values[8], values[9]],
+            ),
+        }
+    }
+
+    fn increment(values: &mut [f64], scale: f64, dvalues: &[f64]) {
+        increment_quaternion(values, scale, dvalues);
+    }
+}
+
+// The state of a spherical joint: the orientation of the body relative to the parent frame (of the
+// joint's xt), and its angular velocity in body coordinates
+#[derive(Component, Debug, Clone)]
+pub struct SphericalJoint {
+    pub name: String,
+    pub rotation: UnitQuaternion<f64>, // body to parent
+    pub w: Vector,                     // body coordinates
+    pub dw: Vector,                    // derivative of w (body coordinates)
+}
+
+impl SphericalJoint {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            rotation: UnitQuaternion::identity(),
+            w: Vector::zeros(),
+            dw: Vector::zeros(),
+        }
+    }
+
+    // joint transform, from the parent to the body
+    pub fn xj(&self) -> Xform {
+        Xform::from_unit_quaternion(Vector::zeros(), &self.rotation)
+    }
+}
+
+impl Stateful for SphericalJoint {
+    type State = SphericalState;
+    const CHECKPOINT_KEY: &'static str = "spherical_joints";
+    fn get_state(&self) -> Self::State {
+        Self::State {
+            rotation: *self.rotation.quaternion(),
+            w: self.w,
+        }
+    }
+
+    fn set_state(&mut self, state: &Self::State) {
+        self.rotation = UnitQuaternion::from_quaternion(state.rotation);
+        self.w = state.w;
+    }
+
+    fn get_dstate(&self) -> Self::State {
+        Self::State {
+            rotation: 0.5 * self.rotation.quaternion() * Quaternion::from_imag(self.w),
+            w: self.dw,
+        }
+    }
+
+    fn set_dstate(&mut self, dstate: Self::State) {
+        self.dw = dstate.w;
+    }
+
+    fn reset(&mut self) {
+        self.dw = Vector::zeros();
+    }
+
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+// The state of a spherical joint, or its derivative (`rotation` is then the derivative of the
+// quaternion)
+#[derive(Clone, Debug)]
+pub struct SphericalState {
+    pub rotation: Quaternion<f64>,
+    pub w: Vector,
+}
+
+impl StateVector for SphericalState {
+    const SIZE: usize = 7;
+    // like FloatingState, the positions are not paired with velocities
+    const NAMES: &'static [&'static str] = &["qw", "qx", "qy", "qz", "wx", "wy", "wz"];
+    fn to_values(&self, values: &mut [f64]) {
+        values[0] = self.rotation.w;
+        values[1..4].copy_from_slice(self.rotation.imag().as_slice());
+        values[4..7].copy_from_slice(self.w.as_slice());
+    }
+    fn from_values(values: &[f64]) -> Self {
+        Self {
+            rotation: Quaternion::new(values[0], values[1], values[2], values[3]),
+            w: Vector::from_column_slice(&values[4..7]),
+        }
+    }
+
+    fn increment(values: &mut [f64], scale: f64, dvalues: &[f64]) {
+        increment_quaternion(values, scale, dvalues);
+    }
+}
+
+// StateVector::increment of a state that starts with a unit quaternion. The quaternion is
+// normalized after it is incremented, so it stays a unit quaternion (the increment is a combination
+// of the solver stages, so it is added like any other value first).
+fn increment_quaternion(values: &mut [f64], scale: f64, dvalues: &[f64]) {
+    for (value, dvalue) in values.iter_mut().zip(dvalues.iter()) {
+        *value += scale * dvalue;
+    }
+    let norm = Quaternion::new(values[0], values[1], values[2], values[3]).norm();
+    for value in values[0..4].iter_mut() {
+        *value /= norm;
+    }
+}
+
+// Recorder source for the state values of the Stateful component T, e.g. "{name}.qw" and
+// "{name}.vx" of the floating joints (see FloatingState)
+pub fn record_state_values<T: Component + Stateful>(world: &mut World, writer: &mut ChannelWriter) {
+    let mut components = world.query::<&T>();
+    let physics_state = world.resource::<PhysicsState<T>>();
+    for entity in physics_state.entities() {
+        if let (Ok(component), Some(values)) =
+            (components.get(world, *entity), physics_state.values(entity))
+        {
+            let name = component.get_name();
+            for (field, value) in <T::State as StateVector>::NAMES.iter().zip(values) {
+                writer.write(&name, field, *value);
+            }
+        }
+    }
+}
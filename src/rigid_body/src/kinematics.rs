@@ -0,0 +1,216 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use nalgebra::{DMatrix, DVector};
+
+use crate::algorithms::{floating_subspace, spherical_subspace};
+use crate::joint::{Base, Joint, JointType};
+use crate::sva::{Matrix, Motion, Vector, Xform};
+
+// Kinematics queries in world (base) coordinates. The transforms and velocities of the joints are
+// updated by loop 1 of the physics, and their accelerations by loop 3, so they are consistent with
+// the state after the physics has run (e.g. in the StepSchedule, or after `run_headless`).
+// Accelerations are relative to the world. With gravity modeled as an upward acceleration of the
+// base, the joint accelerations of the physics include it: these are proper accelerations, what an
+// accelerometer measures (a body at rest has (0, 0, 9.81)).
+
+// transform from the body coordinates of a joint to world coordinates
+pub fn world_xform(joint: &Joint) -> Xform {
+    joint.x.inverse()
+}
+
+// spatial velocity of a joint's body in world coordinates
+pub fn world_velocity(joint: &Joint) -> Motion {
+    joint.x.inverse() * joint.v
+}
+
+// spatial acceleration of a joint's body in world coordinates, relative to the world (without the
+// base acceleration, e.g. `Kinematics::base_acceleration`)
+pub fn world_acceleration(joint: &Joint, base_acceleration: &Motion) -> Motion {
+    world_proper_acceleration(joint) + -1. * *base_acceleration
+}
+
+// spatial acceleration of a joint's body in world coordinates, including the base acceleration
+pub fn world_proper_acceleration(joint: &Joint) -> Motion {
+    joint.x.inverse() * joint.a
+}
+
+// The pose and motion of a point fixed on a joint's body, in world coordinates. The accelerations
+// are relative to the world, and the proper acceleration includes the base acceleration (what an
+// accelerometer at the point measures).
+#[derive(Clone, Copy, Debug)]
+pub struct PointMotion {
+    pub rotation: Matrix, // from the body to world coordinates
+    pub position: Vector,
+    pub velocity: Vector,
+    pub angular_velocity: Vector,
+    pub acceleration: Vector,
+    pub angular_acceleration: Vector,
+    pub proper_acceleration: Vector,
+}
+
+impl PointMotion {
+    // the motion of a point (in the body coordinates of the joint), with the acceleration of the
+    // base (e.g. `Kinematics::base_acceleration`)
+    pub fn new(joint: &Joint, point: Vector, base_acceleration: &Motion) -> Self {
+        let rotation = joint.x.rotation.transpose(); // body to base
+        let v = joint.v.v + joint.v.w.cross(&point);
+        let a = joint.a.v + joint.a.w.cross(&point) + joint.v.w.cross(&v);
+        let position = joint.x.position + rotation * point;
+        let proper_acceleration = rotation * a;
+        Self {
+            rotation,
+            position,
+            velocity: rotation * v,
+            angular_velocity: rotation * joint.v.w,
+            acceleration: proper_acceleration - base_acceleration.velocity_point(position).vel,
+            angular_acceleration: rotation * joint.a.w - base_acceleration.w,
+            proper_acceleration,
+        }
+    }
+
+    // transform from world coordinates to a frame at the point, aligned with the body
+    pub fn pose(&self) -> Xform {
+        Xform::new(self.position, self.rotation.transpose())
+    }
+}
+
+// The geometric Jacobian of a point on a body: its velocity (rows 0 to 2) and the angular velocity
+// of the body (rows 3 to 5), in world coordinates, per unit velocity of each degree of freedom of
+// the ancestor joints. The columns are ordered from the base to the body, and multi degree of
+// freedom joints have a column for each component of their velocity (in body coordinates), named
+// like their recorded states (e.g. `chassis.wx`, ..., `chassis.vz`).
+#[derive(Clone, Debug)]
+pub struct Jacobian {
+    pub matrix: DMatrix<f64>,
+    pub joints: Vec<Entity>, // of each column
+    pub names: Vec<String>,  // of each column
+}
+
+impl Jacobian {
+    // the velocity and angular velocity [v, w] of the point for the joint velocities
+    pub fn velocity(&self, joint_velocities: &DVector<f64>) -> DVector<f64> {
+        &self.matrix * joint_velocities
+    }
+}
+
+// the motion subspace of a joint (in body coordinates), with the names of its degrees of freedom
+fn joint_subspace(joint: &Joint) -> Vec<(Motion, String)> {
+    match joint.joint_type {
+        JointType::Base => vec![],
+        JointType::Floating => floating_subspace()
+            .motions
+            .into_iter()
+            .zip(["wx", "wy", "wz", "vx", "vy", "vz"])
+            .map(|(s, dof)| (s, format!("{}.{dof}", joint.name)))
+            .collect(),
+        JointType::Spherical => spherical_subspace()
+            .motions
+            .into_iter()
+            .zip(["wx", "wy", "wz"])
+            .map(|(s, dof)| (s, format!("{}.{dof}", joint.name)))
+            .collect(),
+        _ => vec![(joint.s, format!("{}.qd", joint.name))],
+    }
+}
+
+// the velocities of the degrees of freedom of a joint, in the order of `joint_subspace`
+fn joint_velocities(joint: &Joint) -> Vec<f64> {
+    let w = joint.vj.w;
+    let v = joint.vj.v;
+    match joint.joint_type {
+        JointType::Base => vec![],
+        JointType::Floating => vec![w.x, w.y, w.z, v.x, v.y, v.z],
+        JointType::Spherical => vec![w.x, w.y, w.z],
+        _ => vec![joint.qd],
+    }
+}
+
+// Kinematics queries of any joint, e.g. for sensors and controllers:
+// `fn sensor_system(kinematics: Kinematics, ...)`. In exclusive systems, use
+// `SystemState::<Kinematics>::new(world)`. Systems that also modify joints can use `PointMotion`
+// and the `world_*` functions directly.
+#[derive(SystemParam)]
+pub struct Kinematics<'w, 's> {
+    joints: Query<'w, 's, (&'static Joint, Option<&'static Parent>)>,
+    base: Query<'w, 's, &'static Joint, With<Base>>,
+}
+
+impl<'w, 's> Kinematics<'w, 's> {
+    // the acceleration of the base (e.g. gravity, as an upward acceleration)
+    pub fn base_acceleration(&self) -> Motion {
+        self.base
+            .get_single()
+            .map(|base| base.a)
+            .unwrap_or_default()
+    }
+
+    // transform from world coordinates to the body coordinates of a joint
+    pub fn pose(&self, joint: Entity) -> Option<Xform> {
+        self.joints.get(joint).ok().map(|(joint, _)| joint.x)
+    }
+
+    // the motion of a point (in the body coordinates of the joint)
+    pub fn point_motion(&self, joint: Entity, point: Vector) -> Option<PointMotion> {
+        let (joint, _) = self.joints.get(joint).ok()?;
+        Some(PointMotion::new(joint, point, &self.base_acceleration()))
+    }
+
+    // the joint and its ancestors, from the base to the joint
+    fn ancestors(&self, joint: Entity) -> Option<Vec<(Entity, &Joint)>> {
+        let mut ancestors = Vec::new();
+        let mut entity = joint;
+        loop {
+            let (joint, parent) = self.joints.get(entity).ok()?;
+            ancestors.push((entity, joint));
+            match parent {
+                Some(parent) if self.joints.contains(parent.get()) => entity = parent.get(),
+                _ => break,
+            }
+        }
+        ancestors.reverse();
+        Some(ancestors)
+    }
+
+    // the Jacobian of a point (in the body coordinates of the joint)
+    pub fn jacobian(&self, joint: Entity, point: Vector) -> Option<Jacobian> {
+        let ancestors = self.ancestors(joint)?;
+        let (_, body) = ancestors.last()?;
+        let point = body.x.position + body.x.rotation.transpose() * point;
+
+        let mut columns = Vec::new();
+        let mut joints = Vec::new();
+        let mut names = Vec::new();
+        for (entity, joint) in ancestors.iter() {
+            let x0i = world_xform(joint);
+            for (s, name) in joint_subspace(joint) {
+                let s0 = x0i * s; // world coordinates
+                let v = s0.velocity_point(point).vel;
+                columns.push(DVector::from_column_slice(&[
+                    v.x, v.y, v.z, s0.w.x, s0.w.y, s0.w.z,
+                ]));
+                joints.push(*entity);
+                names.push(name);
+            }
+        }
+        let matrix = if columns.is_empty() {
+            DMatrix::zeros(6, 0)
+        } else {
+            DMatrix::from_columns(&columns)
+        };
+        Some(Jacobian {
+            matrix,
+            joints,
+            names,
+        })
+    }
+
+    // the velocities of the degrees of freedom of the joint and its ancestors, in the order of the
+    // columns of its Jacobian
+    pub fn joint_velocities(&self, joint: Entity) -> Option<DVector<f64>> {
+        let velocities: Vec<f64> = self
+            .ancestors(joint)?
+            .iter()
+            .flat_map(|(_, joint)| joint_velocities(joint))
+            .collect();
+        Some(DVector::from_vec(velocities))
+    }
+}
@@ -1,8 +1,16 @@
 pub mod algorithms;
+pub mod batch;
+pub mod clock;
+pub mod constraints;
 pub mod definitions;
+pub mod headless;
 pub mod joint;
+pub mod kinematics;
+pub mod limits;
 pub mod mesh;
+pub mod model;
 pub mod plugin;
 pub mod rendering;
 pub mod structure;
 pub mod sva;
+pub mod urdf;
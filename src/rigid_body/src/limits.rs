@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy_integrator::recorder::ChannelWriter;
+use serde::{Deserialize, Serialize};
+
+use crate::joint::Joint;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum LimitType {
+    // a spring and damper end stop (e.g. a rubber bump stop), pushing while the joint is past the
+    // limit
+    Compliant { stiffness: f64, damping: f64 },
+    // a rigid end stop: a unilateral constraint, and impacts that reverse `restitution` times the
+    // joint velocity (0 is a plastic impact, 1 an elastic one)
+    Hard { restitution: f64 },
+}
+
+// The range of a single degree of freedom joint, added to the joint's entity. The force of the end
+// stops is a generalized force (N for prismatic joints, Nm for revolute joints), positive in the
+// direction of increasing q. Compliant limits add their force to the joint (before the joint
+// accelerations are solved). Hard limits are solved with the loop constraints (see
+// `constraints::constraint_forces`), with `time_constant` for the correction of the penetration,
+// and their impacts are applied after every step (`constraints::limit_impacts`).
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
+pub struct JointLimit {
+    pub lower: f64,
+    pub upper: f64,
+    pub limit_type: LimitType,
+    pub time_constant: f64,
+    // slower impacts don't bounce (so a joint resting on a hard limit stays there)
+    pub rest_velocity: f64,
+
+    // solution
+    #[serde(skip)]
+    pub force: f64,
+    #[serde(skip)]
+    pub impulse: f64, // of an impact in the last step (hard limits)
+}
+
+impl JointLimit {
+    pub fn compliant(lower: f64, upper: f64, stiffness: f64, damping: f64) -> Self {
+        Self::new(lower, upper, LimitType::Compliant { stiffness, damping })
+    }
+
+    pub fn hard(lower: f64, upper: f64, restitution: f64) -> Self {
+        Self::new(lower, upper, LimitType::Hard { restitution })
+    }
+
+    fn new(lower: f64, upper: f64, limit_type: LimitType) -> Self {
+        Self {
+            lower,
+            upper,
+            limit_type,
+            time_constant: 0.02,
+            rest_velocity: 0.01,
+            force: 0.,
+            impulse: 0.,
+        }
+    }
+
+    pub fn with_time_constant(mut self, time_constant: f64) -> Self {
+        self.time_constant = time_constant;
+        self
+    }
+
+    pub fn with_rest_velocity(mut self, rest_velocity: f64) -> Self {
+        self.rest_velocity = rest_velocity;
+        self
+    }
+
+    pub fn is_hard(&self) -> bool {
+        matches!(self.limit_type, LimitType::Hard { .. })
+    }
+
+    // how far q is past the limits: negative below the lower limit, positive above the upper limit,
+    // and zero within the range
+    pub fn penetration(&self, q: f64) -> f64 {
+        if q < self.lower {
+            q - self.lower
+        } else if q > self.upper {
+            q - self.upper
+        } else {
+            0.
+        }
+    }
+
+    // the direction of the end stop force (1 at the lower limit, -1 at the upper limit), if q is
+    // past a limit
+    pub fn contact(&self, q: f64) -> Option<f64> {
+        if q < self.lower {
+            Some(1.)
+        } else if q > self.upper {
+            Some(-1.)
+        } else {
+            None
+        }
+    }
+}
+
+// The forces of the compliant limits, added to the joints. The damping doesn't pull the joint back
+// into the limit (the end stop only pushes).
+pub fn compliant_limits(mut joint_query: Query<(&mut Joint, &mut JointLimit)>) {
+    for (mut joint, mut limit) in joint_query.iter_mut() {
+        let LimitType::Compliant { stiffness, damping } = limit.limit_type else {
+            continue;
+        };
+        let Some(direction) = limit.contact(joint.q) else {
+            limit.force = 0.;
+            continue;
+        };
+        let force = -stiffness * limit.penetration(joint.q) - damping * joint.qd;
+        limit.force = direction * (direction * force).max(0.);
+        joint.tau += limit.force;
+    }
+}
+
+// Recorder source for the joint limits: "{joint}.limit_force" and "{joint}.limit_impulse"
+pub fn record_joint_limits(world: &mut World, writer: &mut ChannelWriter) {
+    let mut limits = world.query::<(&Joint, &JointLimit)>();
+    for (joint, limit) in limits.iter(world) {
+        writer.write(&joint.name, "limit_force", limit.force);
+        writer.write(&joint.name, "limit_impulse", limit.impulse);
+    }
+}
@@ -138,11 +138,26 @@ pub fn add_wheel_mesh(
     }
 }
 
+#[derive(Debug)]
+pub struct SphereMesh {
+    pub radius: f32,
+}
+
+impl SphereMesh {
+    pub fn to_bevy_mesh(self) -> BevyMesh {
+        BevyMesh::from(shape::UVSphere {
+            radius: self.radius,
+            ..default()
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum Mesh {
     Box(BoxMesh),
     Wheel(WheelMesh),
     Cylinder(CylinderMesh),
+    Sphere(SphereMesh),
     File(String),
 }
 
@@ -164,6 +179,7 @@ impl Mesh {
             }
             MeshTypeDef::Wheel { radius, width } => Self::Wheel(WheelMesh { radius, width }),
             MeshTypeDef::File { file_name } => Self::File(file_name),
+            MeshTypeDef::Sphere { radius } => Self::Sphere(SphereMesh { radius }),
         }
     }
 }
@@ -0,0 +1,498 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+};
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    constraints::{ConstraintType, LoopConstraint},
+    definitions::{MeshDef, TransformDef},
+    joint::{Base, Joint},
+    limits::{JointLimit, LimitType},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// the parent name of the bodies attached to the base
+pub const BASE_NAME: &str = "base";
+
+// A rigid body tree described in a model file, so that new mechanisms can be built without
+// recompiling. The bodies refer to their parent by name ("base" for the base), and the loop
+// constraints to the bodies they connect. Other components of the bodies (e.g. springs or
+// actuators of the application) are added by the names they are registered with in
+// `ModelComponents`. Model files are RON, JSON or TOML (by the file extension, see `ModelFormat`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ModelDef {
+    pub name: String,
+    #[serde(default)]
+    pub base: BaseDef,
+    pub bodies: Vec<BodyDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<ConstraintDef>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BaseDef {
+    // the linear acceleration of the base (gravity is an upward acceleration of the base)
+    pub acceleration: [f64; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshDef>,
+}
+
+impl Default for BaseDef {
+    fn default() -> Self {
+        Self {
+            acceleration: [0., 0., 9.81],
+            mesh: None,
+        }
+    }
+}
+
+// A body, and the joint that connects it to its parent
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BodyDef {
+    pub name: String,
+    #[serde(default = "base_name")]
+    pub parent: String,
+    pub joint: JointDef,
+    // from the parent body to the joint frame
+    #[serde(default)]
+    pub xt: TransformDef,
+    pub inertia: InertiaDef,
+    // the initial state of single degree of freedom joints (floating and spherical joints start at
+    // rest, in the joint frame)
+    #[serde(default)]
+    pub q: f64,
+    #[serde(default)]
+    pub qd: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshDef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<LimitDef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<ComponentDef>,
+}
+
+fn base_name() -> String {
+    BASE_NAME.to_string()
+}
+
+// The joint types (see the `Joint` constructors), with axes in the joint frame
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum JointDef {
+    Rx,
+    Ry,
+    Rz,
+    Px,
+    Py,
+    Pz,
+    Revolute { axis: [f64; 3] },
+    Prismatic { axis: [f64; 3] },
+    Helical { axis: [f64; 3], pitch: f64 },
+    Floating,
+    Spherical,
+}
+
+impl JointDef {
+    pub fn is_multi_dof(&self) -> bool {
+        matches!(self, JointDef::Floating | JointDef::Spherical)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InertiaDef {
+    pub mass: f64,
+    // center of mass, in body coordinates
+    #[serde(default)]
+    pub com: [f64; 3],
+    // moments of inertia about the center of mass [ixx, iyy, izz], and the products of inertia
+    // [ixy, ixz, iyz]
+    pub moi: [f64; 3],
+    #[serde(default)]
+    pub products: [f64; 3],
+}
+
+impl From<&InertiaDef> for Inertia {
+    fn from(inertia: &InertiaDef) -> Self {
+        let [ixx, iyy, izz] = inertia.moi;
+        let [ixy, ixz, iyz] = inertia.products;
+        Inertia::new(
+            inertia.mass,
+            Vector::from(inertia.com),
+            Matrix::new(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz),
+        )
+    }
+}
+
+// A `JointLimit` of a single degree of freedom joint
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LimitDef {
+    pub lower: f64,
+    pub upper: f64,
+    pub limit_type: LimitType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_constant: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest_velocity: Option<f64>,
+}
+
+impl From<&LimitDef> for JointLimit {
+    fn from(limit: &LimitDef) -> Self {
+        let mut joint_limit = match limit.limit_type {
+            LimitType::Compliant { stiffness, damping } => {
+                JointLimit::compliant(limit.lower, limit.upper, stiffness, damping)
+            }
+            LimitType::Hard { restitution } => {
+                JointLimit::hard(limit.lower, limit.upper, restitution)
+            }
+        };
+        if let Some(time_constant) = limit.time_constant {
+            joint_limit = joint_limit.with_time_constant(time_constant);
+        }
+        if let Some(rest_velocity) = limit.rest_velocity {
+            joint_limit = joint_limit.with_rest_velocity(rest_velocity);
+        }
+        joint_limit
+    }
+}
+
+// A `LoopConstraint` between two bodies (or a body and the base). The frames are in the body
+// coordinates of each body, and only their positions are used by point constraints.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConstraintDef {
+    pub name: String,
+    pub constraint_type: ConstraintType,
+    pub body_a: String,
+    #[serde(default)]
+    pub frame_a: TransformDef,
+    pub body_b: String,
+    #[serde(default)]
+    pub frame_b: TransformDef,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_constant: Option<f64>,
+}
+
+// A component of a body: the name of its type in `ModelComponents`, and its fields (deserialized
+// into the component)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ComponentDef {
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    pub fields: serde_json::Value,
+}
+
+type InsertComponent = Box<dyn FnOnce(&mut EntityCommands) + Send + Sync>;
+type ComponentBuilder =
+    Box<dyn Fn(serde_json::Value) -> Result<InsertComponent, String> + Send + Sync>;
+
+// The component types that model files can add to their bodies, by name, e.g.
+// `ModelComponents::default().register::<Spring>("spring")` for `{ "kind": "spring", "fields":
+// { "stiffness": 1000 } }`. Insert it as a resource for `model_startup_system`.
+#[derive(Resource, Default)]
+pub struct ModelComponents {
+    builders: HashMap<String, ComponentBuilder>,
+}
+
+impl ModelComponents {
+    pub fn register<T: Component + DeserializeOwned>(mut self, kind: &str) -> Self {
+        let builder = |fields| {
+            let component: T = serde_json::from_value(fields).map_err(|error| error.to_string())?;
+            let insert: InsertComponent = Box::new(move |entity: &mut EntityCommands| {
+                entity.insert(component);
+            });
+            Ok(insert)
+        };
+        self.builders.insert(kind.to_string(), Box::new(builder));
+        self
+    }
+
+    fn build(&self, body: &str, component: &ComponentDef) -> io::Result<InsertComponent> {
+        let Some(builder) = self.builders.get(&component.kind) else {
+            return Err(invalid_data(format!(
+                "body {body}: no component type is registered as {}",
+                component.kind
+            )));
+        };
+        builder(component.fields.clone())
+            .map_err(|error| invalid_data(format!("body {body}: {}: {error}", component.kind)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFormat {
+    Ron,
+    Json,
+    Toml,
+}
+
+impl ModelFormat {
+    // by the file extension (.ron, .json or .toml)
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => Ok(Self::Ron),
+            Some("json") => Ok(Self::Json),
+            Some("toml") => Ok(Self::Toml),
+            _ => Err(invalid_data(format!(
+                "{}: unknown model format (expected .ron, .json or .toml)",
+                path.display()
+            ))),
+        }
+    }
+}
+
+impl ModelDef {
+    // read and validate a model file
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = ModelFormat::from_path(path)?;
+        fs::read_to_string(path)
+            .and_then(|text| Self::parse(&text, format))
+            .map_err(|error| io::Error::new(error.kind(), format!("{}: {error}", path.display())))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let text = self.to_string(ModelFormat::from_path(path)?)?;
+        fs::write(path, text)
+    }
+
+    pub fn parse(text: &str, format: ModelFormat) -> io::Result<Self> {
+        let model: Self = match format {
+            ModelFormat::Ron => ron::from_str(text).map_err(invalid_data)?,
+            ModelFormat::Json => serde_json::from_str(text)?,
+            ModelFormat::Toml => toml::from_str(text).map_err(invalid_data)?,
+        };
+        model.validate()?;
+        Ok(model)
+    }
+
+    pub fn to_string(&self, format: ModelFormat) -> io::Result<String> {
+        match format {
+            ModelFormat::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                .map_err(invalid_data),
+            ModelFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+            ModelFormat::Toml => toml::to_string_pretty(self).map_err(invalid_data),
+        }
+    }
+
+    // Check the references between the bodies and constraints, and the body definitions
+    pub fn validate(&self) -> io::Result<()> {
+        let mut names = HashSet::from([BASE_NAME]);
+        for body in self.bodies.iter() {
+            if !names.insert(body.name.as_str()) {
+                return Err(invalid_data(format!(
+                    "body {}: the name is used more than once (or is \"{BASE_NAME}\")",
+                    body.name
+                )));
+            }
+        }
+
+        for body in self.bodies.iter() {
+            if !names.contains(body.parent.as_str()) {
+                return Err(invalid_data(format!(
+                    "body {}: the parent {} is not a body of the model",
+                    body.name, body.parent
+                )));
+            }
+            if body.inertia.mass < 0. {
+                return Err(invalid_data(format!(
+                    "body {}: the mass is negative",
+                    body.name
+                )));
+            }
+            if let JointDef::Revolute { axis }
+            | JointDef::Prismatic { axis }
+            | JointDef::Helical { axis, .. } = body.joint
+            {
+                let axis = Vector::from(axis);
+                if !(axis.norm() > 0. && axis.iter().all(|value| value.is_finite())) {
+                    return Err(invalid_data(format!(
+                        "body {}: the joint axis is zero (or not finite)",
+                        body.name
+                    )));
+                }
+            }
+            if body.joint.is_multi_dof() && (body.q != 0. || body.qd != 0.) {
+                return Err(invalid_data(format!(
+                    "body {}: floating and spherical joints have no q or qd (they start at rest, \
+                     in the joint frame)",
+                    body.name
+                )));
+            }
+            if let Some(limit) = &body.limit {
+                if body.joint.is_multi_dof() {
+                    return Err(invalid_data(format!(
+                        "body {}: only single degree of freedom joints can have a limit",
+                        body.name
+                    )));
+                }
+                if limit.lower > limit.upper {
+                    return Err(invalid_data(format!(
+                        "body {}: the lower limit is above the upper limit",
+                        body.name
+                    )));
+                }
+            }
+        }
+        // every body has to be reachable from the base (no loops of parents)
+        self.spawn_order()?;
+
+        for constraint in self.constraints.iter() {
+            for body in [&constraint.body_a, &constraint.body_b] {
+                if !names.contains(body.as_str()) {
+                    return Err(invalid_data(format!(
+                        "constraint {}: {body} is not a body of the model",
+                        constraint.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The indices of the bodies, parents before children
+    fn spawn_order(&self) -> io::Result<Vec<usize>> {
+        let mut order = Vec::with_capacity(self.bodies.len());
+        let mut spawned = HashSet::from([BASE_NAME]);
+        while order.len() < self.bodies.len() {
+            let count = order.len();
+            for (index, body) in self.bodies.iter().enumerate() {
+                if !spawned.contains(body.name.as_str()) && spawned.contains(body.parent.as_str()) {
+                    spawned.insert(body.name.as_str());
+                    order.push(index);
+                }
+            }
+            if order.len() == count {
+                let body = self
+                    .bodies
+                    .iter()
+                    .find(|body| !spawned.contains(body.name.as_str()))
+                    .expect("a body is left");
+                return Err(invalid_data(format!(
+                    "body {}: not connected to the base (its parents form a loop)",
+                    body.name
+                )));
+            }
+        }
+        Ok(order)
+    }
+
+    // Spawn the bodies and constraints, and return the base entity
+    pub fn spawn(&self, commands: &mut Commands) -> io::Result<Entity> {
+        self.spawn_with(commands, &ModelComponents::default())
+    }
+
+    // Spawn the bodies (with their components, of the types in `components`) and constraints, and
+    // return the base entity. Nothing is spawned if a component can't be built.
+    pub fn spawn_with(
+        &self,
+        commands: &mut Commands,
+        components: &ModelComponents,
+    ) -> io::Result<Entity> {
+        self.validate()?;
+        let mut inserts = HashMap::new();
+        for body in self.bodies.iter() {
+            let body_inserts = body
+                .components
+                .iter()
+                .map(|component| components.build(&body.name, component))
+                .collect::<io::Result<Vec<_>>>()?;
+            inserts.insert(body.name.as_str(), body_inserts);
+        }
+
+        let base = Joint::base(Motion::new(self.base.acceleration, [0., 0., 0.]));
+        let mut base_commands = commands.spawn((base, Base));
+        if let Some(mesh) = &self.base.mesh {
+            base_commands.insert(mesh.clone());
+        }
+        let base_id = base_commands.id();
+
+        let mut entities = HashMap::from([(BASE_NAME, base_id)]);
+        for index in self.spawn_order()? {
+            let body = &self.bodies[index];
+            let mut entity_commands = spawn_joint(commands, body);
+            if let Some(mesh) = &body.mesh {
+                entity_commands.insert(mesh.clone());
+            }
+            if let Some(limit) = &body.limit {
+                entity_commands.insert(JointLimit::from(limit));
+            }
+            for insert in inserts.remove(body.name.as_str()).unwrap_or_default() {
+                insert(&mut entity_commands);
+            }
+            entity_commands.set_parent(entities[body.parent.as_str()]);
+            entities.insert(body.name.as_str(), entity_commands.id());
+        }
+
+        for constraint in self.constraints.iter() {
+            let joint_a = entities[constraint.body_a.as_str()];
+            let joint_b = entities[constraint.body_b.as_str()];
+            let frame_a = Xform::from(&constraint.frame_a);
+            let frame_b = Xform::from(&constraint.frame_b);
+            let mut loop_constraint = match constraint.constraint_type {
+                ConstraintType::Point => LoopConstraint::point(
+                    &constraint.name,
+                    joint_a,
+                    frame_a.position,
+                    joint_b,
+                    frame_b.position,
+                ),
+                ConstraintType::Frame => {
+                    LoopConstraint::frame(&constraint.name, joint_a, frame_a, joint_b, frame_b)
+                }
+            };
+            if let Some(time_constant) = constraint.time_constant {
+                loop_constraint = loop_constraint.with_time_constant(time_constant);
+            }
+            commands.spawn(loop_constraint);
+        }
+        Ok(base_id)
+    }
+}
+
+fn spawn_joint<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    body: &BodyDef,
+) -> bevy::ecs::system::EntityCommands<'w, 's, 'a> {
+    let name = body.name.clone();
+    let inertia = Inertia::from(&body.inertia);
+    let xt = Xform::from(&body.xt);
+    let mut joint = match body.joint {
+        JointDef::Rx => Joint::rx(name, inertia, xt),
+        JointDef::Ry => Joint::ry(name, inertia, xt),
+        JointDef::Rz => Joint::rz(name, inertia, xt),
+        JointDef::Px => Joint::px(name, inertia, xt),
+        JointDef::Py => Joint::py(name, inertia, xt),
+        JointDef::Pz => Joint::pz(name, inertia, xt),
+        JointDef::Revolute { axis } => Joint::revolute(name, inertia, xt, Vector::from(axis)),
+        JointDef::Prismatic { axis } => Joint::prismatic(name, inertia, xt, Vector::from(axis)),
+        JointDef::Helical { axis, pitch } => {
+            Joint::helical(name, inertia, xt, Vector::from(axis), pitch)
+        }
+        JointDef::Floating => return commands.spawn(Joint::floating(name, inertia, xt)),
+        JointDef::Spherical => return commands.spawn(Joint::spherical(name, inertia, xt)),
+    };
+    joint.q = body.q;
+    joint.qd = body.qd;
+    commands.spawn(joint)
+}
+
+// A startup system that spawns the model (e.g. read with `ModelDef::read` before the app is built),
+// with the component types of the ModelComponents resource
+pub fn model_startup_system(model: ModelDef) -> impl FnMut(Commands, Option<Res<ModelComponents>>) {
+    move |mut commands: Commands, components: Option<Res<ModelComponents>>| {
+        let result = match components {
+            Some(components) => model.spawn_with(&mut commands, &components),
+            None => model.spawn(&mut commands),
+        };
+        if let Err(error) = result {
+            error!("model {}: {error}", model.name);
+        }
+    }
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
@@ -1,14 +1,28 @@
 #![allow(dead_code)]
 
 use crate::{
-    joint::{bevy_joint_positions, Joint},
+    clock::{
+        apply_clock_system, clock_input_system, real_time_factor_system, single_step_system,
+        SimClock,
+    },
+    constraints::{constraint_forces, limit_impacts, record_loop_constraints},
+    joint::{
+        bevy_joint_positions, record_joints, record_state_values, FloatingJoint, Joint,
+        SphericalJoint,
+    },
+    limits::{compliant_limits, record_joint_limits},
     rendering::startup_rendering,
-    structure::{apply_external_forces, loop_1, loop_23},
+    structure::{apply_external_forces, loop_1, loop_23, multi_dof_accelerations},
 };
-use bevy::{app::AppExit, prelude::*};
+use bevy::{app::AppExit, input::InputPlugin, prelude::*, time::TimeSystem};
 use bevy_integrator::{
-    initialize_state, integrator_schedule, ExitEvent, PhysicsSchedule, PhysicsScheduleExt, SimTime,
-    Solver,
+    checkpoint::{record_checkpoint_system, rewind, CheckpointHistory},
+    initial_evaluation_system, integrator_schedule,
+    recorder::{record_system, write_recording_system, Recorder, RecorderAppExt},
+    sampled::{sampled_controller_system, SampledControllers},
+    trim::{trim_system, TrimSettings},
+    ExitEvent, InitializeStateSet, PhysicsSchedule, PhysicsScheduleExt, SimTime, Solver,
+    StatefulAppExt, StepSchedule,
 };
 use bevy_obj::ObjPlugin;
 
@@ -19,16 +33,66 @@ pub struct RigidBodyPlugin {
     pub environment_setup: Vec<fn(&mut App)>,
     pub solver: Solver,
     pub name: String,
+    // no window, rendering or assets. The physics is stepped as fast as possible (see `run_headless`)
+    pub headless: bool,
 }
 
 impl RigidBodyPlugin {
     pub fn setup_physics_simulation(&self, app: &mut App) {
         let schedule = create_physics_schedule();
         app.add_schedule(schedule)
+            .add_stateful::<Joint>()
+            .add_stateful::<FloatingJoint>()
+            .add_stateful::<SphericalJoint>()
             .insert_resource(self.time.clone())
             .insert_resource(self.solver)
-            .insert_resource(Time::<Fixed>::from_seconds(self.time.dt as f64))
-            .add_systems(FixedUpdate, integrator_schedule::<Joint>);
+            .add_recorder_source(record_joints)
+            .add_recorder_source(record_state_values::<FloatingJoint>)
+            .add_recorder_source(record_state_values::<SphericalJoint>)
+            .add_recorder_source(record_loop_constraints)
+            .add_recorder_source(record_joint_limits)
+            .add_systems(
+                PostStartup,
+                // the state storage is inserted with commands
+                (
+                    apply_deferred,
+                    trim_system.run_if(resource_exists::<TrimSettings>()),
+                    // the samples at the start time
+                    sampled_controller_system.run_if(resource_exists::<SampledControllers>()),
+                    initial_evaluation_system,
+                    record_system.run_if(resource_exists::<Recorder>()),
+                )
+                    .chain()
+                    .after(InitializeStateSet),
+            )
+            .add_systems(
+                StepSchedule,
+                // the recording includes the controller outputs applied at the end of the step
+                (
+                    limit_impacts,
+                    sampled_controller_system.run_if(resource_exists::<SampledControllers>()),
+                    record_system.run_if(resource_exists::<Recorder>()),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Last,
+                write_recording_system.run_if(resource_exists::<Recorder>()),
+            );
+
+        if self.headless {
+            // one physics step per app update, not paced by the wall clock
+            app.add_systems(Update, integrator_schedule);
+        } else {
+            // keep a history of checkpoints, so the viewer can rewind the simulation
+            app.insert_resource(Time::<Fixed>::from_seconds(self.time.dt as f64))
+                .init_resource::<SimClock>()
+                .init_resource::<CheckpointHistory>()
+                .add_systems(
+                    FixedUpdate,
+                    (integrator_schedule, record_checkpoint_system).chain(),
+                );
+        }
     }
 }
 
@@ -37,14 +101,40 @@ impl Plugin for RigidBodyPlugin {
         self.setup_physics_simulation(app);
         app.add_event::<ExitEvent>();
 
+        for setup in self.simulation_setup.iter() {
+            setup(app);
+        }
+
+        if self.headless {
+            // exit in the same update as the last step, so the exit is seen by `run_headless`
+            app.add_systems(
+                Update,
+                (time_exit_system, exit_system)
+                    .chain()
+                    .after(integrator_schedule),
+            );
+            // input is only added so that control systems can still run (without any input)
+            app.add_plugins((MinimalPlugins, InputPlugin));
+            return;
+        }
+
         app.add_systems(
             Update,
             (time_exit_system, esc_exit_system, exit_system).chain(),
-        );
+        )
+        .add_systems(Update, rewind_system)
+        .add_systems(
+            Update,
+            (
+                clock_input_system,
+                single_step_system,
+                real_time_factor_system,
+            )
+                .chain(),
+        )
+        // before the virtual time is advanced, so a pause takes effect in the same frame
+        .add_systems(First, apply_clock_system.before(TimeSystem));
 
-        for setup in self.simulation_setup.iter() {
-            setup(app);
-        }
         for setup in self.environment_setup.iter() {
             setup(app);
         }
@@ -63,15 +153,22 @@ impl Plugin for RigidBodyPlugin {
         ));
         app.add_systems(PostStartup, startup_rendering)
             .add_systems(Update, bevy_joint_positions);
-
-        app.add_systems(PostStartup, initialize_state::<Joint>);
     }
 }
 
 fn create_physics_schedule() -> Schedule {
     let mut physics_schedule = Schedule::new(PhysicsSchedule);
-    physics_schedule
-        .add_physics_systems::<Joint, _, _>((loop_1,), (apply_external_forces, loop_23).chain());
+    physics_schedule.add_physics_systems(
+        (loop_1,),
+        (
+            compliant_limits,
+            apply_external_forces,
+            loop_23,
+            constraint_forces,
+            multi_dof_accelerations,
+        )
+            .chain(),
+    );
 
     physics_schedule
 }
@@ -98,6 +195,15 @@ fn esc_exit_system(
     }
 }
 
+// rewind the simulation (by `CheckpointHistory::rewind_time`) with the R key
+fn rewind_system(world: &mut World) {
+    if world.resource::<Input<KeyCode>>().just_pressed(KeyCode::R) {
+        if let Some(time) = rewind(world) {
+            info!("rewound to t = {time:.2} s");
+        }
+    }
+}
+
 fn exit_system(mut exit: EventWriter<AppExit>, exit_request: EventReader<ExitEvent>) {
     if !exit_request.is_empty() {
         exit.send(AppExit);
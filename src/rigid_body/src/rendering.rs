@@ -39,6 +39,16 @@ pub fn startup_rendering(
                 });
                 entity_commands.set_parent(entity);
             }
+            RigidBodyMesh::Sphere(sphere_mesh) => {
+                let mesh = meshes.add(sphere_mesh.to_bevy_mesh());
+                let mut entity_commands = commands.spawn(PbrBundle {
+                    mesh,
+                    material: materials.add(mesh_def.color.into()),
+                    transform: Transform::from(&mesh_def.transform),
+                    ..Default::default()
+                });
+                entity_commands.set_parent(entity);
+            }
             RigidBodyMesh::File(file_name) => add_obj_mesh(
                 &mut commands,
                 entity,
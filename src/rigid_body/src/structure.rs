@@ -1,13 +1,35 @@
-use crate::joint::{Base, Joint};
+use crate::joint::{Base, FloatingJoint, Joint, SphericalJoint};
+use crate::sva::{Motion, Vector};
 use bevy::prelude::*;
 
-use crate::algorithms::{apply_external_update, loop_1_update, loop_2_update, loop_3_update};
+use crate::algorithms::{
+    apply_external_update, articulated_reset_update, loop_1_update, loop_2_update, loop_3_update,
+};
 
 pub fn loop_1(
     base_query: Query<Entity, With<Base>>,
     joint_children_query: Query<&Children, With<Joint>>,
     mut joint_query: Query<&mut Joint>,
+    floating_query: Query<(Entity, &FloatingJoint)>,
+    spherical_query: Query<(Entity, &SphericalJoint)>,
 ) {
+    // the joint transforms and velocities of the multi degree of freedom joints come from their state
+    for (entity, floating) in floating_query.iter() {
+        if let Ok(mut joint) = joint_query.get_mut(entity) {
+            joint.xj = floating.xj();
+            joint.vj = floating.v;
+        }
+    }
+    for (entity, spherical) in spherical_query.iter() {
+        if let Ok(mut joint) = joint_query.get_mut(entity) {
+            joint.xj = spherical.xj();
+            joint.vj = Motion {
+                v: Vector::zeros(),
+                w: spherical.w,
+            };
+        }
+    }
+
     base_loop(
         &base_query,
         &joint_children_query,
@@ -53,6 +75,82 @@ pub fn loop_23(
     );
 }
 
+// Loops 2 and 3 again, after the joint forces have changed (e.g. by constraint forces). The
+// articulated inertias and bias forces are reset first, without updating the kinematics.
+pub fn forward_dynamics(
+    base_query: &Query<Entity, With<Base>>,
+    joint_children_query: &Query<&Children, With<Joint>>,
+    joint_query: &mut Query<&mut Joint>,
+) {
+    base_loop(
+        base_query,
+        joint_children_query,
+        joint_query,
+        Some(articulated_reset_update),
+        None,
+    );
+    base_loop(
+        base_query,
+        joint_children_query,
+        joint_query,
+        None,
+        Some(loop_2_update),
+    );
+    base_loop(
+        base_query,
+        joint_children_query,
+        joint_query,
+        Some(loop_3_update),
+        None,
+    );
+}
+
+// The state derivatives of the multi degree of freedom joints, from their joint accelerations (after
+// loop 3, and the loop constraints)
+pub fn multi_dof_accelerations(
+    joint_query: Query<&Joint>,
+    mut floating_query: Query<(Entity, &mut FloatingJoint)>,
+    mut spherical_query: Query<(Entity, &mut SphericalJoint)>,
+) {
+    for (entity, mut floating) in floating_query.iter_mut() {
+        if let Ok(joint) = joint_query.get(entity) {
+            floating.a = joint.aj;
+        }
+    }
+    for (entity, mut spherical) in spherical_query.iter_mut() {
+        if let Ok(joint) = joint_query.get(entity) {
+            spherical.dw = joint.aj.w;
+        }
+    }
+}
+
+// The joints of the trees, parents before their children, with the index of their parent (None for
+// the joints attached to a base)
+pub fn tree_order(
+    base_query: &Query<Entity, With<Base>>,
+    joint_children_query: &Query<&Children, With<Joint>>,
+    joint_query: &Query<&mut Joint>,
+) -> Vec<(Entity, Option<usize>)> {
+    let mut order = Vec::new();
+    let mut stack: Vec<(Entity, Option<usize>)> = Vec::new();
+    for base_entity in base_query.iter() {
+        if let Ok(children) = joint_children_query.get(base_entity) {
+            stack.extend(children.iter().rev().map(|child| (*child, None)));
+        }
+        while let Some((entity, parent)) = stack.pop() {
+            if !joint_query.contains(entity) {
+                continue;
+            }
+            order.push((entity, parent));
+            if let Ok(children) = joint_children_query.get(entity) {
+                let index = order.len() - 1;
+                stack.extend(children.iter().rev().map(|child| (*child, Some(index))));
+            }
+        }
+    }
+    order
+}
+
 pub fn base_loop(
     base_query: &Query<Entity, With<Base>>,
     joint_children_query: &Query<&Children, With<Joint>>,
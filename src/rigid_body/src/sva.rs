@@ -48,6 +48,12 @@ pub fn rz(angle: f64) -> Matrix {
     )
 }
 
+// rotation about a unit axis, like rx, ry and rz
+pub fn r_axis(axis: &Vector, angle: f64) -> Matrix {
+    let (sin, cos) = angle.sin_cos();
+    cos * Matrix::identity() + (1. - cos) * axis * axis.transpose() - sin * axis.cross_matrix()
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Velocity {
     pub vel: Vector,
@@ -123,6 +129,32 @@ impl Xform {
             ..Default::default()
         }
     }
+    pub fn rot_axis(axis: &Vector, angle: f64) -> Self {
+        Self {
+            rotation: r_axis(axis, angle),
+            ..Default::default()
+        }
+    }
+    pub fn pos_axis(axis: &Vector, distance: f64) -> Self {
+        Self {
+            position: distance * axis,
+            ..Default::default()
+        }
+    }
+    // screw motion: rotation about a unit axis, and translation of pitch * angle along it
+    pub fn screw(axis: &Vector, angle: f64, pitch: f64) -> Self {
+        Self {
+            position: (pitch * angle) * axis,
+            rotation: r_axis(axis, angle),
+        }
+    }
+    // a frame at a position, with an orientation (from the frame to the parent)
+    pub fn from_unit_quaternion(position: Vector, rotation: &UnitQuaternion<f64>) -> Self {
+        Self {
+            position,
+            rotation: rotation.to_rotation_matrix().matrix().transpose(),
+        }
+    }
 
     pub fn quaternion(x: f64, y: f64, z: f64, w: f64) -> Self {
         let quaternion = Quaternion::new(x, y, z, w).normalize();
@@ -140,7 +172,6 @@ impl Xform {
     pub fn transform_point(self, point: Vector) -> Vector {
         self.rotation * (point - self.position)
     }
-
 }
 
 impl Mul<Xform> for Xform {
@@ -454,6 +485,17 @@ impl Inertia {
             moi: Matrix::zeros(),
         }
     }
+    pub fn mass(&self) -> f64 {
+        self.m
+    }
+    // center of mass
+    pub fn com(&self) -> Vector {
+        self.c
+    }
+    // moment of inertia about the center of mass
+    pub fn moi(&self) -> Matrix {
+        self.moi
+    }
 }
 
 impl Mul<Motion> for Inertia {
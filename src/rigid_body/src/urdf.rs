@@ -0,0 +1,859 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    f64::consts::FRAC_PI_2,
+    fmt::Write,
+    fs, io,
+    path::Path,
+};
+
+use bevy::prelude::*;
+use nalgebra::{Rotation3, UnitQuaternion};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    definitions::{MeshDef, MeshTypeDef, TransformDef},
+    joint::{Base, Joint, JointType},
+    limits::{JointLimit, LimitType},
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+// the color of visuals without a material
+const DEFAULT_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+
+// URDF limits require an effort and a velocity, which aren't modeled: they are written large
+// enough not to limit anything
+const EFFORT_LIMIT: f64 = 1e6;
+const VELOCITY_LIMIT: f64 = 1e6;
+
+// A robot description (URDF): links connected into a tree by joints
+#[derive(Debug)]
+pub struct Urdf {
+    pub name: String,
+    pub links: Vec<UrdfLink>,
+    pub joints: Vec<UrdfJoint>,
+    // the end stops of the limited joints, none by default (URDF limits are often only the range
+    // for planning)
+    pub limit_type: Option<LimitType>,
+}
+
+#[derive(Debug)]
+pub struct UrdfLink {
+    pub name: String,
+    pub inertia: Inertia, // in the link frame
+    // the visual geometry (or the collision geometry, if the link has no visuals)
+    pub visuals: Vec<MeshDef>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrdfJointType {
+    Revolute,
+    Continuous,
+    Prismatic,
+    Fixed,
+    Floating,
+}
+
+#[derive(Debug)]
+pub struct UrdfJoint {
+    pub name: String,
+    pub joint_type: UrdfJointType,
+    pub parent: String,
+    pub child: String,
+    pub origin: Xform, // from the parent link frame to the joint (child link) frame
+    pub axis: Vector,
+    pub limit: Option<(f64, f64)>, // lower and upper, if the range isn't empty
+}
+
+impl Urdf {
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let robot = Element::parse(text)?;
+        if robot.name != "robot" {
+            return Err(invalid_data(format!(
+                "expected a <robot> element, found <{}>",
+                robot.name
+            )));
+        }
+
+        // materials defined at the top level, which visuals can refer to by name
+        let mut materials = HashMap::new();
+        for material in robot.children_named("material") {
+            if let Some(color) = material.child("color") {
+                materials.insert(material.attribute("name")?, parse_color(color)?);
+            }
+        }
+
+        let links = robot
+            .children_named("link")
+            .map(|link| parse_link(link, &materials))
+            .collect::<io::Result<_>>()?;
+        let joints = robot
+            .children_named("joint")
+            .map(parse_joint)
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            name: robot.attribute("name")?.to_string(),
+            links,
+            joints,
+            limit_type: None,
+        })
+    }
+
+    // give the revolute and prismatic joints with a limit a JointLimit of `limit_type`
+    pub fn with_limits(mut self, limit_type: LimitType) -> Self {
+        self.limit_type = Some(limit_type);
+        self
+    }
+
+    // Spawn the joint tree, and return the base entity. The root link is the base, fixed to the
+    // world with the acceleration `base_acceleration` (e.g. gravity), so its inertia is unused (a free
+    // robot is attached to a root link by a floating joint). Links attached by fixed joints are merged
+    // into their parent body. The limited revolute and prismatic joints get end stops if a
+    // `limit_type` is set (see `with_limits`). Every body gets a UrdfBody, with the names of its
+    // links, so `write_urdf` writes the same links back.
+    pub fn spawn(&self, commands: &mut Commands, base_acceleration: Motion) -> io::Result<Entity> {
+        let bodies = self.bodies()?;
+
+        let mut entities: Vec<Entity> = Vec::with_capacity(bodies.len());
+        for body in bodies {
+            let urdf_body = UrdfBody {
+                link: body.link.to_string(),
+                parent_link: body.parent_link.to_string(),
+                limit: body.joint.and_then(|joint| joint.limit),
+                merged: body.merged,
+            };
+            let mut entity_commands = match body.joint {
+                None => commands.spawn((Joint::base(base_acceleration), Base)),
+                Some(joint) => {
+                    let name = joint.name.clone();
+                    let mut entity_commands = match joint.joint_type {
+                        UrdfJointType::Revolute | UrdfJointType::Continuous => {
+                            commands.spawn(Joint::revolute(name, body.inertia, body.xt, joint.axis))
+                        }
+                        UrdfJointType::Prismatic => commands.spawn(Joint::prismatic(
+                            name,
+                            body.inertia,
+                            body.xt,
+                            joint.axis,
+                        )),
+                        UrdfJointType::Floating => {
+                            commands.spawn(Joint::floating(name, body.inertia, body.xt))
+                        }
+                        UrdfJointType::Fixed => unreachable!("fixed joints are merged"),
+                    };
+                    if let (Some((lower, upper)), Some(limit_type)) = (joint.limit, self.limit_type)
+                    {
+                        entity_commands.insert(match limit_type {
+                            LimitType::Compliant { stiffness, damping } => {
+                                JointLimit::compliant(lower, upper, stiffness, damping)
+                            }
+                            LimitType::Hard { restitution } => {
+                                JointLimit::hard(lower, upper, restitution)
+                            }
+                        });
+                    }
+                    if let Some(parent) = body.parent {
+                        entity_commands.set_parent(entities[parent]);
+                    }
+                    entity_commands
+                }
+            };
+            if let Some(mesh_def) = body.mesh_def {
+                entity_commands.insert(mesh_def);
+            }
+            entity_commands.insert(urdf_body);
+            entities.push(entity_commands.id());
+        }
+        Ok(entities[0])
+    }
+
+    // The bodies of the tree, parents before children (the base is first)
+    fn bodies(&self) -> io::Result<Vec<Body<'_>>> {
+        let links: HashMap<&str, &UrdfLink> = self
+            .links
+            .iter()
+            .map(|link| (link.name.as_str(), link))
+            .collect();
+
+        // the root is the only link that isn't the child of a joint
+        let roots: Vec<&UrdfLink> = self
+            .links
+            .iter()
+            .filter(|link| !self.joints.iter().any(|joint| joint.child == link.name))
+            .collect();
+        let [root] = roots[..] else {
+            return Err(invalid_data(format!(
+                "expected one root link, found {}",
+                roots.len()
+            )));
+        };
+
+        let mut bodies = vec![Body {
+            joint: None,
+            parent: None,
+            link: &root.name,
+            parent_link: "",
+            xt: Xform::identity(),
+            inertia: Inertia::zero(),
+            mesh_def: None,
+            merged: Vec::new(),
+        }];
+        let mut visited = HashSet::new();
+        self.add_link(
+            &links,
+            root,
+            0,
+            Xform::identity(),
+            &mut bodies,
+            &mut visited,
+        )?;
+        Ok(bodies)
+    }
+
+    // Add a link to a body (`x` is the transform from the body frame to the link frame), and the
+    // links below it
+    fn add_link<'a>(
+        &'a self,
+        links: &HashMap<&str, &'a UrdfLink>,
+        link: &'a UrdfLink,
+        body: usize,
+        x: Xform,
+        bodies: &mut Vec<Body<'a>>,
+        visited: &mut HashSet<&'a str>,
+    ) -> io::Result<()> {
+        let rotation = x.rotation.transpose(); // link to body
+        let inertia = Inertia::new(
+            link.inertia.mass(),
+            x.position + rotation * link.inertia.com(),
+            rotation * link.inertia.moi() * x.rotation,
+        );
+        bodies[body].inertia = combine_inertias(&bodies[body].inertia, &inertia);
+
+        if let Some(visual) = link.visuals.first() {
+            if bodies[body].mesh_def.is_some() {
+                warn!("link {}: only one visual per body is rendered", link.name);
+            } else {
+                let transform = Xform::from(&visual.transform) * x;
+                bodies[body].mesh_def = Some(MeshDef {
+                    transform: pose(&transform),
+                    ..visual.clone()
+                });
+            }
+        }
+        if link.visuals.len() > 1 {
+            warn!("link {}: only the first visual is rendered", link.name);
+        }
+
+        for joint in self.joints.iter().filter(|joint| joint.parent == link.name) {
+            let Some(child) = links.get(joint.child.as_str()) else {
+                return Err(invalid_data(format!(
+                    "joint {}: no link named {}",
+                    joint.name, joint.child
+                )));
+            };
+            if !visited.insert(joint.child.as_str()) {
+                return Err(invalid_data(format!(
+                    "link {} has more than one parent",
+                    joint.child
+                )));
+            }
+
+            // from the body frame to the joint frame
+            let xt = joint.origin * x;
+            if joint.joint_type == UrdfJointType::Fixed {
+                bodies[body].merged.push(MergedLink {
+                    joint: joint.name.clone(),
+                    parent: link.name.clone(),
+                    link: child.name.clone(),
+                    origin: joint.origin,
+                    x: xt,
+                });
+                self.add_link(links, child, body, xt, bodies, visited)?;
+            } else {
+                bodies.push(Body {
+                    joint: Some(joint),
+                    parent: Some(body),
+                    link: &child.name,
+                    parent_link: &link.name,
+                    xt,
+                    inertia: Inertia::zero(),
+                    mesh_def: None,
+                    merged: Vec::new(),
+                });
+                let index = bodies.len() - 1;
+                self.add_link(links, child, index, Xform::identity(), bodies, visited)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A rigid body of the joint tree: links merged by fixed joints, moving with a joint
+struct Body<'a> {
+    joint: Option<&'a UrdfJoint>, // None for the base
+    parent: Option<usize>,
+    link: &'a str,        // the child link of the joint
+    parent_link: &'a str, // the parent link of the joint
+    xt: Xform,
+    inertia: Inertia,
+    mesh_def: Option<MeshDef>,
+    merged: Vec<MergedLink>,
+}
+
+// The URDF links of a body spawned by `Urdf::spawn`, and the limit of its joint (which has no
+// JointLimit unless the limits are enabled), for `write_urdf`
+#[derive(Component, Clone, Debug)]
+pub struct UrdfBody {
+    pub link: String,        // the child link of the joint
+    pub parent_link: String, // the parent link of the joint (empty for the base)
+    pub limit: Option<(f64, f64)>,
+    pub merged: Vec<MergedLink>,
+}
+
+// a link merged into a body by a fixed joint
+#[derive(Clone, Debug)]
+pub struct MergedLink {
+    pub joint: String,
+    pub parent: String, // link
+    pub link: String,
+    pub origin: Xform, // of the fixed joint, from the parent link frame to the link frame
+    pub x: Xform,      // from the body frame to the link frame
+}
+
+// the inertia of two rigidly connected bodies (in the same frame)
+fn combine_inertias(a: &Inertia, b: &Inertia) -> Inertia {
+    let mass = a.mass() + b.mass();
+    if mass == 0. {
+        return Inertia::new(0., Vector::zeros(), a.moi() + b.moi());
+    }
+    let com = (a.mass() * a.com() + b.mass() * b.com()) / mass;
+    // parallel axis theorem, from the center of mass of each body to the combined center of mass
+    let moi = [a, b]
+        .iter()
+        .map(|inertia| {
+            let d = inertia.com() - com;
+            inertia.moi()
+                + inertia.mass() * (d.norm_squared() * Matrix::identity() - d * d.transpose())
+        })
+        .sum();
+    Inertia::new(mass, com, moi)
+}
+
+// the pose of a frame (`x` transforms to the frame) as a mesh transform
+fn pose(x: &Xform) -> TransformDef {
+    let rotation = UnitQuaternion::from_matrix(&x.rotation.transpose());
+    TransformDef::Pose {
+        position: x.position.into(),
+        quaternion: [rotation.i, rotation.j, rotation.k, rotation.w],
+    }
+}
+
+fn parse_link(link: &Element, materials: &HashMap<&str, Color>) -> io::Result<UrdfLink> {
+    let name = link.attribute("name")?.to_string();
+
+    let inertia = match link.child("inertial") {
+        Some(inertial) => {
+            // the inertia tensor is about the center of mass, in the axes of the inertial frame
+            let (com, rotation) = parse_origin(inertial)?;
+            let mass = inertial.required_child("mass")?.value("value")?;
+            let tensor = inertial.required_child("inertia")?;
+            let mut values = [0.; 6];
+            for (value, name) in values
+                .iter_mut()
+                .zip(["ixx", "ixy", "ixz", "iyy", "iyz", "izz"])
+            {
+                *value = tensor.value(name)?;
+            }
+            let [ixx, ixy, ixz, iyy, iyz, izz] = values;
+            let moi = Matrix::new(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz);
+            Inertia::new(mass, com, rotation * moi * rotation.transpose())
+        }
+        None => Inertia::zero(),
+    };
+
+    let mut visuals = Vec::new();
+    for visual in link.children_named("visual") {
+        visuals.extend(parse_visual(&name, visual, materials)?);
+    }
+    if visuals.is_empty() {
+        for collision in link.children_named("collision") {
+            visuals.extend(parse_visual(&name, collision, materials)?);
+        }
+    }
+
+    Ok(UrdfLink {
+        name,
+        inertia,
+        visuals,
+    })
+}
+
+// a visual or collision element (collisions have no material)
+fn parse_visual(
+    link_name: &str,
+    visual: &Element,
+    materials: &HashMap<&str, Color>,
+) -> io::Result<Option<MeshDef>> {
+    let (position, mut rotation) = parse_origin(visual)?;
+    let Some(geometry) = visual.required_child("geometry")?.children.first() else {
+        return Err(invalid_data(format!("link {link_name}: empty geometry")));
+    };
+
+    let mesh_type = match geometry.name.as_str() {
+        "box" => {
+            let [x, y, z] = geometry.values("size", [0.; 3])?;
+            MeshTypeDef::Box {
+                dimensions: [x as f32, y as f32, z as f32],
+            }
+        }
+        "cylinder" => {
+            // URDF cylinders are along the z axis, and the meshes along the y axis
+            rotation *= rx_rotation(FRAC_PI_2);
+            MeshTypeDef::Cylinder {
+                height: geometry.value("length")? as f32,
+                radius: geometry.value("radius")? as f32,
+            }
+        }
+        "sphere" => MeshTypeDef::Sphere {
+            radius: geometry.value("radius")? as f32,
+        },
+        "mesh" => {
+            if geometry.values("scale", [1.; 3])? != [1.; 3] {
+                warn!("link {link_name}: mesh scale is not supported");
+            }
+            // relative to the asset folder
+            let file_name = geometry.attribute("filename")?;
+            let file_name = file_name
+                .strip_prefix("package://")
+                .or_else(|| file_name.strip_prefix("file://"))
+                .unwrap_or(file_name);
+            MeshTypeDef::File {
+                file_name: file_name.to_string(),
+            }
+        }
+        other => {
+            warn!("link {link_name}: {other} geometry is not supported");
+            return Ok(None);
+        }
+    };
+
+    let color = match visual.child("material") {
+        Some(material) => match material.child("color") {
+            Some(color) => parse_color(color)?,
+            None => material
+                .attributes
+                .get("name")
+                .and_then(|name| materials.get(name.as_str()))
+                .copied()
+                .unwrap_or(DEFAULT_COLOR),
+        },
+        None => DEFAULT_COLOR,
+    };
+
+    Ok(Some(MeshDef {
+        mesh_type,
+        transform: pose(&Xform::new(position, rotation.transpose())),
+        color,
+    }))
+}
+
+fn parse_joint(joint: &Element) -> io::Result<UrdfJoint> {
+    let name = joint.attribute("name")?.to_string();
+    let joint_type = match joint.attribute("type")? {
+        "revolute" => UrdfJointType::Revolute,
+        "continuous" => UrdfJointType::Continuous,
+        "prismatic" => UrdfJointType::Prismatic,
+        "fixed" => UrdfJointType::Fixed,
+        "floating" => UrdfJointType::Floating,
+        other => {
+            return Err(invalid_data(format!(
+                "joint {name}: {other} joints are not supported"
+            )))
+        }
+    };
+
+    let (position, rotation) = parse_origin(joint)?;
+    let axis = match joint.child("axis") {
+        Some(axis) => Vector::from(axis.values("xyz", [1., 0., 0.])?),
+        None => Vector::x(),
+    };
+    let limit = match (joint_type, joint.child("limit")) {
+        (UrdfJointType::Revolute | UrdfJointType::Prismatic, Some(limit)) => {
+            let [lower] = limit.values("lower", [0.])?;
+            let [upper] = limit.values("upper", [0.])?;
+            (lower < upper).then_some((lower, upper))
+        }
+        _ => None,
+    };
+
+    Ok(UrdfJoint {
+        parent: joint
+            .required_child("parent")?
+            .attribute("link")?
+            .to_string(),
+        child: joint
+            .required_child("child")?
+            .attribute("link")?
+            .to_string(),
+        name,
+        joint_type,
+        origin: Xform::new(position, rotation.transpose()),
+        axis,
+        limit,
+    })
+}
+
+// The position and orientation (from the frame to the parent) of an <origin> element
+fn parse_origin(element: &Element) -> io::Result<(Vector, Matrix)> {
+    let Some(origin) = element.child("origin") else {
+        return Ok((Vector::zeros(), Matrix::identity()));
+    };
+    let position = Vector::from(origin.values("xyz", [0.; 3])?);
+    let [roll, pitch, yaw] = origin.values("rpy", [0.; 3])?;
+    let rotation = Rotation3::from_euler_angles(roll, pitch, yaw);
+    Ok((position, *rotation.matrix()))
+}
+
+fn parse_color(color: &Element) -> io::Result<Color> {
+    let [r, g, b, a] = color.values("rgba", [1.; 4])?;
+    Ok(Color::rgba(r as f32, g as f32, b as f32, a as f32))
+}
+
+// a rotation about x (from the frame to the parent)
+fn rx_rotation(angle: f64) -> Matrix {
+    *Rotation3::from_axis_angle(&Vector::x_axis(), angle).matrix()
+}
+
+// Write the joint tree (below the Base entity) as a URDF. Bodies spawned from a URDF keep the names
+// of their links, and the links merged into them are written back with their fixed joints (see
+// UrdfBody); other links are named after their joints ("base" for the base link). Revolute joints
+// are continuous unless they have a JointLimit (or a URDF limit), and helical and spherical joints
+// can't be written.
+pub fn write_urdf(world: &World, name: &str) -> io::Result<String> {
+    let bases: Vec<Entity> = world
+        .iter_entities()
+        .filter(|entity| entity.contains::<Base>())
+        .map(|entity| entity.id())
+        .collect();
+    let [base] = bases[..] else {
+        return Err(invalid_data(format!(
+            "expected one base, found {}",
+            bases.len()
+        )));
+    };
+
+    let mut urdf = format!(
+        "<?xml version=\"1.0\"?>\n<robot name=\"{}\">\n",
+        escape(name)
+    );
+    write_link(world, base, "base", &mut urdf)?;
+    urdf.push_str("</robot>\n");
+    Ok(urdf)
+}
+
+fn write_link(world: &World, entity: Entity, name: &str, urdf: &mut String) -> io::Result<()> {
+    let joint = world.get::<Joint>(entity).ok_or_else(missing_joint)?;
+    let body = world.get::<UrdfBody>(entity);
+    let name = body.map_or(name, |body| body.link.as_str());
+
+    let _ = writeln!(urdf, "  <link name=\"{}\">", escape(name));
+    if joint.i.mass() > 0. {
+        let moi = joint.i.moi();
+        let _ = writeln!(
+            urdf,
+            "    <inertial>\n      <origin xyz=\"{}\"/>\n      <mass value=\"{}\"/>\n      \
+             <inertia ixx=\"{}\" ixy=\"{}\" ixz=\"{}\" iyy=\"{}\" iyz=\"{}\" izz=\"{}\"/>\n    \
+             </inertial>",
+            values(&joint.i.com()),
+            joint.i.mass(),
+            moi[(0, 0)],
+            moi[(0, 1)],
+            moi[(0, 2)],
+            moi[(1, 1)],
+            moi[(1, 2)],
+            moi[(2, 2)],
+        );
+    }
+    if let Some(mesh_def) = world.get::<MeshDef>(entity) {
+        write_visual(mesh_def, name, urdf);
+    }
+    urdf.push_str("  </link>\n");
+
+    // the merged links are empty, the body inertia and visual are in the first link
+    let merged = body.map_or(&[][..], |body| &body.merged[..]);
+    for link in merged {
+        let _ = writeln!(
+            urdf,
+            "  <joint name=\"{}\" type=\"fixed\">\n    <parent link=\"{}\"/>\n    \
+             <child link=\"{}\"/>\n    {}\n  </joint>\n  <link name=\"{}\"/>",
+            escape(&link.joint),
+            escape(&link.parent),
+            escape(&link.link),
+            origin(&link.origin),
+            escape(&link.link),
+        );
+    }
+
+    let Some(children) = world.get::<Children>(entity) else {
+        return Ok(());
+    };
+    for &child in children.iter() {
+        let Some(child_joint) = world.get::<Joint>(child) else {
+            continue;
+        };
+        // the joint origin is relative to the link it is attached to
+        let parent_link = world
+            .get::<UrdfBody>(child)
+            .and_then(|child| merged.iter().find(|link| link.link == child.parent_link));
+        let (parent, origin) = match parent_link {
+            Some(link) => (link.link.as_str(), child_joint.xt * link.x.inverse()),
+            None => (name, child_joint.xt),
+        };
+        write_joint(world, child, parent, &origin, urdf)?;
+        write_link(world, child, &child_joint.name, urdf)?;
+    }
+    Ok(())
+}
+
+fn write_joint(
+    world: &World,
+    entity: Entity,
+    parent: &str,
+    joint_origin: &Xform,
+    urdf: &mut String,
+) -> io::Result<()> {
+    let joint = world.get::<Joint>(entity).ok_or_else(missing_joint)?;
+    let body = world.get::<UrdfBody>(entity);
+    let child = body.map_or(joint.name.as_str(), |body| body.link.as_str());
+    let limit = match world.get::<JointLimit>(entity) {
+        Some(limit) => Some((limit.lower, limit.upper)),
+        None => body.and_then(|body| body.limit),
+    };
+
+    let revolute = if limit.is_some() {
+        "revolute"
+    } else {
+        "continuous"
+    };
+    let (joint_type, axis) = match &joint.joint_type {
+        JointType::Rx => (revolute, Some(Vector::x())),
+        JointType::Ry => (revolute, Some(Vector::y())),
+        JointType::Rz => (revolute, Some(Vector::z())),
+        JointType::Revolute(axis) => (revolute, Some(*axis)),
+        JointType::Px => ("prismatic", Some(Vector::x())),
+        JointType::Py => ("prismatic", Some(Vector::y())),
+        JointType::Pz => ("prismatic", Some(Vector::z())),
+        JointType::Prismatic(axis) => ("prismatic", Some(*axis)),
+        JointType::Floating => ("floating", None),
+        other => {
+            return Err(invalid_data(format!(
+                "joint {}: {other:?} joints can't be written to URDF",
+                joint.name
+            )))
+        }
+    };
+
+    let _ = writeln!(
+        urdf,
+        "  <joint name=\"{}\" type=\"{joint_type}\">\n    <parent link=\"{}\"/>\n    \
+         <child link=\"{}\"/>\n    {}",
+        escape(&joint.name),
+        escape(parent),
+        escape(child),
+        origin(joint_origin)
+    );
+    if let Some(axis) = axis {
+        let _ = writeln!(urdf, "    <axis xyz=\"{}\"/>", values(&axis));
+    }
+    match limit {
+        Some((lower, upper)) => {
+            let _ = writeln!(
+                urdf,
+                "    <limit lower=\"{lower}\" upper=\"{upper}\" effort=\"{EFFORT_LIMIT}\" \
+                 velocity=\"{VELOCITY_LIMIT}\"/>",
+            );
+        }
+        // the limit element is required for prismatic joints
+        None if joint_type == "prismatic" => {
+            let _ = writeln!(
+                urdf,
+                "    <limit effort=\"{EFFORT_LIMIT}\" velocity=\"{VELOCITY_LIMIT}\"/>"
+            );
+        }
+        None => {}
+    }
+    urdf.push_str("  </joint>\n");
+    Ok(())
+}
+
+// the visual of a link, with a material named after the link
+fn write_visual(mesh_def: &MeshDef, link: &str, urdf: &mut String) {
+    let transform = Xform::from(&mesh_def.transform);
+    let (geometry, transform) = match &mesh_def.mesh_type {
+        MeshTypeDef::Box {
+            dimensions: [x, y, z],
+        } => (format!("<box size=\"{x} {y} {z}\"/>"), transform),
+        // URDF cylinders are along the z axis, and the meshes along the y axis
+        MeshTypeDef::Cylinder { height, radius } => (
+            format!("<cylinder radius=\"{radius}\" length=\"{height}\"/>"),
+            Xform::new(Vector::zeros(), rx_rotation(FRAC_PI_2)) * transform,
+        ),
+        // the wheel mesh ignores the transform
+        MeshTypeDef::Wheel { radius, width } => (
+            format!("<cylinder radius=\"{radius}\" length=\"{width}\"/>"),
+            Xform::new(Vector::zeros(), rx_rotation(FRAC_PI_2)),
+        ),
+        MeshTypeDef::Sphere { radius } => (format!("<sphere radius=\"{radius}\"/>"), transform),
+        MeshTypeDef::File { file_name } => (
+            format!("<mesh filename=\"{}\"/>", escape(file_name)),
+            transform,
+        ),
+    };
+    let [r, g, b, a] = mesh_def.color.as_rgba_f32();
+    let _ = writeln!(
+        urdf,
+        "    <visual>\n      {}\n      <geometry>\n        {geometry}\n      </geometry>\n      \
+         <material name=\"{}_material\">\n        <color rgba=\"{r} {g} {b} {a}\"/>\n      \
+         </material>\n    </visual>",
+        origin(&transform),
+        escape(link)
+    );
+}
+
+// an <origin> element for the frame that `x` transforms to
+fn origin(x: &Xform) -> String {
+    let rotation = Rotation3::from_matrix_unchecked(x.rotation.transpose());
+    let (roll, pitch, yaw) = rotation.euler_angles();
+    let (roll, pitch, yaw) = (number(roll), number(pitch), number(yaw));
+    format!(
+        "<origin xyz=\"{}\" rpy=\"{roll} {pitch} {yaw}\"/>",
+        values(&x.position)
+    )
+}
+
+fn values(vector: &Vector) -> String {
+    format!(
+        "{} {} {}",
+        number(vector.x),
+        number(vector.y),
+        number(vector.z)
+    )
+}
+
+// text as an XML attribute value
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+// without negative zeros (written as "-0")
+fn number(value: f64) -> f64 {
+    value + 0.
+}
+
+fn missing_joint() -> io::Error {
+    invalid_data("an entity of the joint tree has no Joint".to_string())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// A minimal XML element tree (the text content is unused by URDF)
+struct Element {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn parse(text: &str) -> io::Result<Self> {
+        let mut stack: Vec<Element> = Vec::new();
+        for event in EventReader::from_str(text) {
+            match event.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|attribute| (attribute.name.local_name, attribute.value))
+                        .collect(),
+                    children: Vec::new(),
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().expect("the reader checks the element nesting");
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(invalid_data("no root element".to_string()))
+    }
+
+    fn attribute(&self, name: &str) -> io::Result<&str> {
+        self.attributes
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| invalid_data(format!("<{}> has no {name} attribute", self.name)))
+    }
+
+    // a number attribute
+    fn value(&self, name: &str) -> io::Result<f64> {
+        let [value] = self.values(name, [f64::NAN])?;
+        if value.is_nan() {
+            return Err(invalid_data(format!(
+                "<{}> has no {name} attribute",
+                self.name
+            )));
+        }
+        Ok(value)
+    }
+
+    // an optional attribute of N numbers, separated by whitespace
+    fn values<const N: usize>(&self, name: &str, default: [f64; N]) -> io::Result<[f64; N]> {
+        let Some(text) = self.attributes.get(name) else {
+            return Ok(default);
+        };
+        let invalid = || invalid_data(format!("<{}> {name}: expected {N} numbers", self.name));
+        let numbers = text
+            .split_whitespace()
+            .map(|number| number.parse::<f64>().map_err(|_| invalid()))
+            .collect::<io::Result<Vec<f64>>>()?;
+        numbers.try_into().map_err(|_| invalid())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn required_child(&self, name: &str) -> io::Result<&Element> {
+        self.child(name)
+            .ok_or_else(|| invalid_data(format!("<{}> has no <{name}>", self.name)))
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
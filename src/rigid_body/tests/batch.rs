@@ -0,0 +1,75 @@
+use bevy::prelude::*;
+use rigid_body::batch::{Batch, BatchResults, BatchRun};
+
+#[derive(Clone, Default)]
+struct Parameters {
+    mass: f64,
+    length: f64,
+}
+
+// the variants are only drawn, nothing is simulated
+fn build(_: Parameters) -> App {
+    App::new()
+}
+
+fn batch() -> Batch<Parameters> {
+    Batch::new(Parameters::default(), build, 1.)
+        .with_values("mass", |parameters, mass| parameters.mass = mass, &[1., 2.])
+        .with_samples(3)
+}
+
+#[test]
+fn variants_combine_listed_and_random_values() {
+    let batch = batch().with_normal("length", |p, length| p.length = length, 1., 0.1);
+    let variants = batch.variants().unwrap();
+    assert_eq!(variants.len(), 6);
+    assert!(variants[..3].iter().all(|variant| variant[0] == 1.));
+    assert!(variants[3..].iter().all(|variant| variant[0] == 2.));
+    // drawn from the seed
+    assert_eq!(variants, batch.variants().unwrap());
+}
+
+#[test]
+fn invalid_distribution_is_an_error() {
+    let negative = batch().with_normal("length", |p, length| p.length = length, 1., -0.1);
+    let error = negative.variants().unwrap_err();
+    assert!(error.to_string().contains("length"), "{error}");
+    assert!(negative.run().is_err());
+
+    let infinite = batch().with_normal("length", |p, length| p.length = length, 1., f64::INFINITY);
+    let error = infinite.variants().unwrap_err();
+    assert!(error.to_string().contains("length"), "{error}");
+}
+
+#[test]
+fn csv_names_are_escaped() {
+    let results = BatchResults {
+        parameters: vec!["mass, kg".to_string()],
+        metrics: vec!["max \"roll\"".to_string()],
+        runs: vec![
+            BatchRun {
+                parameters: vec![1.],
+                metrics: vec![0.5],
+                wall_time: 0.25,
+                error: None,
+            },
+            BatchRun {
+                parameters: vec![2.],
+                metrics: vec![f64::NAN],
+                wall_time: 0.5,
+                error: Some("diverged, at t = 1".to_string()),
+            },
+        ],
+    };
+    let path = std::env::temp_dir().join(format!("batch_test_{}.csv", std::process::id()));
+    results.write_csv(&path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(
+        lines[0],
+        "run,\"mass, kg\",\"max \"\"roll\"\"\",wall_time,error"
+    );
+    assert_eq!(lines[1], "0,1,0.5,0.25,");
+    assert_eq!(lines[2], "1,2,NaN,0.5,\"diverged, at t = 1\"");
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy_integrator::{linearize::linearize, SimTime, Solver, SolverError, SolverFailure};
+use nalgebra::UnitQuaternion;
+use rigid_body::{
+    headless::{floating_joints, run_headless},
+    joint::{Base, FloatingJoint, Joint},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+const DT: f64 = 0.002;
+
+// box of 1 kg, 0.6 x 0.3 x 0.1 m, about its center
+fn moment_of_inertia() -> Matrix {
+    let (x, y, z) = (0.6, 0.3, 0.1);
+    Matrix::from_diagonal(&Vector::new(y * y + z * z, x * x + z * z, x * x + y * y)) / 12.
+}
+
+fn box_inertia() -> Inertia {
+    Inertia::new(1., Vector::zeros(), moment_of_inertia())
+}
+
+// an app with a single floating body, under the base acceleration `gravity`, until `end_time`
+fn floating_app(gravity: f64, inertia: Inertia, body: FloatingJoint, end_time: f64) -> App {
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(DT, 0., Some(end_time)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![],
+        name: "floating".to_string(),
+        headless: true,
+    })
+    .add_systems(Startup, move |mut commands: Commands| {
+        // gravity is an upward acceleration of the base
+        let base = Joint::base(Motion::new([0., 0., gravity], [0., 0., 0.]));
+        let base_id = commands.spawn((base, Base)).id();
+        let (joint, _) = Joint::floating(body.name.clone(), inertia, Xform::identity());
+        commands.spawn((joint, body.clone())).set_parent(base_id);
+    });
+    app
+}
+
+// a single floating body, under the base acceleration `gravity`, simulated past `end_time`: the
+// final state, and its time
+fn simulate(
+    gravity: f64,
+    inertia: Inertia,
+    body: FloatingJoint,
+    end_time: f64,
+) -> (FloatingJoint, f64) {
+    let mut app = floating_app(gravity, inertia, body, end_time);
+    run_headless(&mut app);
+    let time = app.world.resource::<SimTime>().time();
+    (floating_joints(&mut app.world).remove(0), time)
+}
+
+#[test]
+fn free_fall() {
+    let mut body = FloatingJoint::new("body".to_string());
+    body.position = Vector::new(1., 2., 10.);
+    body.rotation = UnitQuaternion::from_euler_angles(0.3, -0.2, 1.);
+    let (end, t) = simulate(GRAVITY, box_inertia(), body.clone(), 1.);
+
+    let fallen = body.position - Vector::z() * GRAVITY * t * t / 2.;
+    assert!((end.position - fallen).norm() < 1e-9, "{:?}", end.position);
+    assert!(end.rotation.angle_to(&body.rotation) < 1e-9);
+    // the velocity is in body coordinates
+    let velocity = end.rotation * end.v.v;
+    assert!(
+        (velocity + Vector::z() * GRAVITY * t).norm() < 1e-9,
+        "{velocity:?}"
+    );
+    assert!(end.v.w.norm() < 1e-12);
+}
+
+#[test]
+fn spin_about_a_principal_axis() {
+    // pitching at 2 rad/s, through a pitch of 90 degrees (the gimbal lock of a chain of single axis
+    // joints) and over the top, while moving along x
+    let mut body = FloatingJoint::new("body".to_string());
+    body.v = Motion::new([0.5, 0., 0.], [0., 2., 0.]);
+    let (end, t) = simulate(0., box_inertia(), body.clone(), 1.5);
+
+    // the angular velocity (in body coordinates) is constant
+    assert!((end.v.w - body.v.w).norm() < 1e-9, "{:?}", end.v.w);
+    let rotation = UnitQuaternion::from_scaled_axis(body.v.w * t);
+    assert!(end.rotation.angle_to(&rotation) < 1e-8);
+
+    // the kinetic energy and the linear and angular momentum (in the parent frame) are conserved
+    let moi = moment_of_inertia();
+    let energy = |body: &FloatingJoint| {
+        0.5 * body.v.w.dot(&(moi * body.v.w)) + 0.5 * body.v.v.norm_squared()
+    };
+    let momentum = |body: &FloatingJoint| body.rotation * (moi * body.v.w);
+    assert!((energy(&end) - energy(&body)).abs() < 1e-9);
+    assert!((momentum(&end) - momentum(&body)).norm() < 1e-9);
+    let velocity = end.rotation * end.v.v;
+    assert!((velocity - body.v.v).norm() < 1e-9, "{velocity:?}");
+    // the body origin is at its center of mass, so it moves in a straight line
+    assert!((end.position - body.v.v * t).norm() < 1e-8);
+}
+
+#[test]
+fn massless_body_stops_the_simulation() {
+    // the articulated inertia of the joint is singular: its accelerations are NaN, rather than
+    // the inertia being dropped, and the first step fails
+    let body = FloatingJoint::new("body".to_string());
+    let inertia = Inertia::new(0., Vector::zeros(), Matrix::zeros());
+    let mut app = floating_app(GRAVITY, inertia, body, 0.1);
+    run_headless(&mut app);
+    let failure = app
+        .world
+        .get_resource::<SolverFailure>()
+        .expect("the step failed");
+    assert_eq!(failure.0, SolverError::NonFinite { time: DT });
+    assert_eq!(app.world.resource::<SimTime>().time(), DT);
+}
+
+#[test]
+fn orientation_cannot_be_linearized() {
+    let body = FloatingJoint::new("body".to_string());
+    let mut app = floating_app(GRAVITY, box_inertia(), body, 1.);
+    app.update();
+    let error = linearize(&mut app.world, 1e-6).unwrap_err();
+    assert!(error.contains("body.q"), "{error}");
+}
@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use bevy_integrator::{SimTime, Solver, StepSchedule};
+use rigid_body::{
+    constraints::LoopConstraint,
+    headless::run_headless,
+    joint::{Base, Joint},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, InertiaAB, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+const GROUND: f64 = 1.0;
+const CRANK: f64 = 0.4;
+const COUPLER: f64 = 1.0;
+const ROCKER: f64 = 0.6;
+
+// the largest constraint violation after `settle` seconds, and the energy after each step
+#[derive(Resource, Default)]
+struct Drift {
+    settle: f64,
+    position_error: f64,
+    velocity_error: f64,
+    energies: Vec<f64>,
+}
+
+fn drift_system(
+    time: Res<SimTime>,
+    joint_query: Query<&Joint>,
+    constraint_query: Query<&LoopConstraint>,
+    mut drift: ResMut<Drift>,
+) {
+    let energy = joint_query
+        .iter()
+        .filter_map(|joint| {
+            let length = link_length(&joint.name)?;
+            let inertia: InertiaAB = joint.i.into();
+            let center =
+                joint.x.position + joint.x.rotation.transpose() * Vector::new(length / 2., 0., 0.);
+            Some(0.5 * (&joint.v * &(inertia * joint.v)) + GRAVITY * center.z)
+        })
+        .sum();
+    drift.energies.push(energy);
+
+    if time.time() >= drift.settle {
+        for constraint in constraint_query.iter() {
+            drift.position_error = drift.position_error.max(constraint.position_error);
+            drift.velocity_error = drift.velocity_error.max(constraint.velocity_error);
+        }
+    }
+}
+
+fn link_length(name: &str) -> Option<f64> {
+    match name {
+        "crank" => Some(CRANK),
+        "coupler" => Some(COUPLER),
+        "rocker" => Some(ROCKER),
+        _ => None,
+    }
+}
+
+// a slender link of 1 kg along the x axis of the joint, at `angle` (upwards) in the x-z plane
+fn link(name: &str, length: f64, xt: Xform, angle: f64) -> Joint {
+    let moi = 1. / 12. * length.powi(2);
+    let inertia = Inertia::new(
+        1.,
+        Vector::new(length / 2., 0., 0.),
+        Matrix::from_diagonal(&Vector::new(0., moi, moi)),
+    );
+    let mut joint = Joint::ry(name.to_string(), inertia, xt);
+    joint.q = -angle;
+    joint
+}
+
+// the angles of the coupler and the rocker (from the x axis) with the crank at `crank_angle`
+fn assembly_angles(crank_angle: f64) -> (f64, f64) {
+    let crank_end = Vector::new(CRANK * crank_angle.cos(), 0., CRANK * crank_angle.sin());
+    let diagonal = Vector::new(GROUND, 0., 0.) - crank_end;
+    let d = diagonal.norm();
+    let along = (COUPLER.powi(2) - ROCKER.powi(2) + d.powi(2)) / (2. * d);
+    let across = (COUPLER.powi(2) - along.powi(2)).sqrt();
+    let normal = Vector::new(-diagonal.z, 0., diagonal.x) / d;
+    let coupler = diagonal / d * along + normal * across;
+    let rocker = crank_end + coupler - Vector::new(GROUND, 0., 0.);
+    (coupler.z.atan2(coupler.x), rocker.z.atan2(rocker.x))
+}
+
+// A four-bar linkage swinging under gravity for `end_time`, assembled with the rocker rotated by
+// `rocker_error` (rad)
+fn simulate(rocker_error: f64, settle: f64, end_time: f64) -> Drift {
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.002, 0., Some(end_time)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![],
+        name: "four_bar".to_string(),
+        headless: true,
+    })
+    .insert_resource(Drift {
+        settle,
+        ..default()
+    })
+    .add_systems(StepSchedule, drift_system)
+    .add_systems(Startup, move |mut commands: Commands| {
+        let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+        let base_id = commands.spawn((base, Base)).id();
+        let crank_angle = 60_f64.to_radians();
+        let (coupler_angle, rocker_angle) = assembly_angles(crank_angle);
+        let crank = link("crank", CRANK, Xform::identity(), crank_angle);
+        let crank_id = commands.spawn(crank).set_parent(base_id).id();
+        let coupler = link(
+            "coupler",
+            COUPLER,
+            Xform::posx(CRANK),
+            coupler_angle - crank_angle,
+        );
+        let coupler_id = commands.spawn(coupler).set_parent(crank_id).id();
+        let rocker = link(
+            "rocker",
+            ROCKER,
+            Xform::posx(GROUND),
+            rocker_angle + rocker_error,
+        );
+        let rocker_id = commands.spawn(rocker).set_parent(base_id).id();
+        commands.spawn(LoopConstraint::point(
+            "coupler_rocker",
+            coupler_id,
+            Vector::new(COUPLER, 0., 0.),
+            rocker_id,
+            Vector::new(ROCKER, 0., 0.),
+        ));
+    });
+    run_headless(&mut app);
+    app.world.remove_resource::<Drift>().unwrap()
+}
+
+#[test]
+fn drift_is_bounded() {
+    let drift = simulate(0., 0., 10.);
+    assert!(drift.position_error < 1e-6, "{}", drift.position_error);
+    assert!(drift.velocity_error < 1e-4, "{}", drift.velocity_error);
+    // the constraint forces do no work
+    let (first, last) = (drift.energies[0], *drift.energies.last().unwrap());
+    assert!((last - first).abs() < 1e-4 * first.abs(), "{first} {last}");
+}
+
+#[test]
+fn assembly_error_decays() {
+    // 1 cm at the end of the rocker, corrected with the default time constant of 0.02 s
+    let drift = simulate(0.01 / ROCKER, 0.3, 1.);
+    assert!(drift.position_error < 1e-6, "{}", drift.position_error);
+    assert!(drift.velocity_error < 1e-4, "{}", drift.velocity_error);
+}
@@ -0,0 +1,65 @@
+use bevy::{ecs::system::SystemState, prelude::*};
+use bevy_integrator::{SimTime, Solver};
+use rigid_body::{
+    headless::run_headless,
+    joint::{Base, Joint},
+    kinematics::{world_acceleration, world_proper_acceleration, Kinematics},
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const GRAVITY: f64 = 9.81;
+
+// the acceleration and proper acceleration of a point, and of its body
+fn accelerations(world: &mut World, name: &str, point: Vector) -> [Vector; 4] {
+    let mut state = SystemState::<(Kinematics, Query<(Entity, &Joint)>)>::new(world);
+    let (kinematics, joints) = state.get(world);
+    let (entity, joint) = joints.iter().find(|(_, joint)| joint.name == name).unwrap();
+    let motion = kinematics.point_motion(entity, point).unwrap();
+    let base = kinematics.base_acceleration();
+    [
+        motion.acceleration,
+        motion.proper_acceleration,
+        world_acceleration(joint, &base).v,
+        world_proper_acceleration(joint).v,
+    ]
+}
+
+#[test]
+fn acceleration_conventions() {
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(0.001, 0., Some(0.001)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![],
+        name: "kinematics".to_string(),
+        headless: true,
+    })
+    .add_systems(Startup, |mut commands: Commands| {
+        let base = Joint::base(Motion::new([0., 0., GRAVITY], [0., 0., 0.]));
+        let base_id = commands.spawn((base, Base)).id();
+        let inertia = Inertia::new(1., Vector::zeros(), Matrix::identity());
+        // a body sliding freely along z, and a balanced wheel at rest
+        let slider = Joint::pz("slider".to_string(), inertia, Xform::identity());
+        commands.spawn(slider).set_parent(base_id);
+        let wheel = Joint::rx("wheel".to_string(), inertia, Xform::posx(1.));
+        commands.spawn(wheel).set_parent(base_id);
+    });
+    run_headless(&mut app);
+
+    let point = Vector::new(0.1, 0.2, 0.3);
+    let falling = Vector::new(0., 0., -GRAVITY);
+    let [a, proper, body_a, body_proper] = accelerations(&mut app.world, "slider", point);
+    assert!((a - falling).norm() < 1e-9, "{a:?}");
+    assert!(proper.norm() < 1e-9, "{proper:?}");
+    assert!((body_a - falling).norm() < 1e-9, "{body_a:?}");
+    assert!(body_proper.norm() < 1e-9, "{body_proper:?}");
+
+    let up = Vector::new(0., 0., GRAVITY);
+    let [a, proper, body_a, body_proper] = accelerations(&mut app.world, "wheel", point);
+    assert!(a.norm() < 1e-9, "{a:?}");
+    assert!((proper - up).norm() < 1e-9, "{proper:?}");
+    assert!(body_a.norm() < 1e-9, "{body_a:?}");
+    assert!((body_proper - up).norm() < 1e-9, "{body_proper:?}");
+}
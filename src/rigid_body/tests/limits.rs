@@ -0,0 +1,86 @@
+use bevy::prelude::*;
+use bevy_integrator::{SimTime, Solver, StepSchedule};
+use rigid_body::{
+    constraints::limit_impacts,
+    headless::run_headless,
+    joint::{Base, Joint},
+    limits::JointLimit,
+    plugin::RigidBodyPlugin,
+    sva::{Inertia, Matrix, Motion, Vector, Xform},
+};
+
+const DT: f64 = 0.001;
+const UPPER: f64 = 0.5;
+
+// q, qd, the end stop force and impulse after every step
+#[derive(Resource, Default)]
+struct History(Vec<(f64, f64, f64, f64)>);
+
+fn history_system(query: Query<(&Joint, &JointLimit)>, mut history: ResMut<History>) {
+    for (joint, limit) in query.iter() {
+        history
+            .0
+            .push((joint.q, joint.qd, limit.force, limit.impulse));
+    }
+}
+
+// A pendulum of 1 kg and 1 m released from horizontal, swinging down (increasing q) into an end stop
+// at q = UPPER
+fn simulate(limit: JointLimit, end_time: f64) -> Vec<(f64, f64, f64, f64)> {
+    let mut app = App::new();
+    app.add_plugins(RigidBodyPlugin {
+        time: SimTime::new(DT, 0., Some(end_time)),
+        solver: Solver::RK4,
+        simulation_setup: vec![],
+        environment_setup: vec![],
+        name: "limits".to_string(),
+        headless: true,
+    })
+    .init_resource::<History>()
+    .add_systems(StepSchedule, history_system.after(limit_impacts))
+    .add_systems(Startup, move |mut commands: Commands| {
+        let base = Joint::base(Motion::new([0., 0., 9.81], [0., 0., 0.]));
+        let base_id = commands.spawn((base, Base)).id();
+        let inertia = Inertia::new(1., Vector::new(1., 0., 0.), Matrix::zeros());
+        let pendulum = Joint::ry("pendulum".to_string(), inertia, Xform::identity());
+        commands
+            .spawn((pendulum, limit.clone()))
+            .set_parent(base_id);
+    });
+    run_headless(&mut app);
+    app.world.remove_resource::<History>().unwrap().0
+}
+
+#[test]
+fn hard_limit_restitution() {
+    let history = simulate(JointLimit::hard(-10., UPPER, 0.5), 0.6);
+    let impact = history
+        .iter()
+        .position(|(_, _, _, impulse)| *impulse != 0.)
+        .expect("the pendulum hits the end stop");
+    // the velocity before the impact, and after it
+    let before = history[impact - 1].1;
+    let after = history[impact].1;
+    assert!(before > 1., "{before}");
+    let ratio = after / before;
+    assert!(
+        (-0.55..-0.45).contains(&ratio),
+        "rebound {after} after {before}"
+    );
+    // the penetration is corrected, within about one step of the impact velocity
+    let q_max = history.iter().map(|(q, ..)| *q).fold(f64::MIN, f64::max);
+    assert!(q_max - UPPER < before * DT, "{q_max}");
+}
+
+#[test]
+fn compliant_limit_only_pushes() {
+    // the pendulum bounces off the stop (at a damping ratio of 0.3), and while it leaves, the
+    // damping would pull it back into the stop
+    let history = simulate(JointLimit::compliant(-10., UPPER, 1e4, 60.), 1.);
+    assert!(history.iter().all(|(_, _, force, _)| *force <= 0.));
+    assert!(history.iter().any(|(_, _, force, _)| *force < 0.));
+    let rebounding = history
+        .iter()
+        .any(|(q, qd, force, _)| *q > UPPER && *qd < 0. && *force == 0.);
+    assert!(rebounding);
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use rigid_body::{
+    joint::{Base, Joint},
+    model::{ComponentDef, JointDef, ModelComponents, ModelDef, ModelFormat},
+};
+use serde::Deserialize;
+
+const FORMATS: [ModelFormat; 3] = [ModelFormat::Ron, ModelFormat::Json, ModelFormat::Toml];
+
+// a component of the application, added to bodies by model files
+#[derive(Component, Deserialize, Debug, PartialEq)]
+struct Spring {
+    stiffness: f64,
+    #[serde(default)]
+    damping: f64,
+}
+
+fn four_bar() -> ModelDef {
+    ModelDef::read("examples/models/four_bar.json").unwrap()
+}
+
+fn spring(stiffness: f64) -> ComponentDef {
+    ComponentDef {
+        kind: "spring".to_string(),
+        fields: serde_json::json!({ "stiffness": stiffness }),
+    }
+}
+
+fn spawn(model: ModelDef, components: ModelComponents) -> World {
+    let mut app = App::new();
+    app.add_systems(Startup, move |mut commands: Commands| {
+        model.spawn_with(&mut commands, &components).unwrap();
+    });
+    app.update();
+    app.world
+}
+
+// the joints (but the base) and their springs, sorted by name
+fn joints(world: &mut World) -> Vec<(Joint, Option<f64>)> {
+    let mut joints: Vec<_> = world
+        .query_filtered::<(&Joint, Option<&Spring>), Without<Base>>()
+        .iter(world)
+        .map(|(joint, spring)| {
+            let joint = Joint {
+                name: joint.name.clone(),
+                xt: joint.xt,
+                i: joint.i,
+                s: joint.s,
+                q: joint.q,
+                qd: joint.qd,
+                ..default()
+            };
+            (joint, spring.map(|spring| spring.stiffness))
+        })
+        .collect();
+    joints.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    joints
+}
+
+#[test]
+fn model_round_trip() {
+    let mut model = four_bar();
+    model.bodies[0].components.push(spring(100.));
+    let components = || ModelComponents::default().register::<Spring>("spring");
+    let mut world = spawn(model.clone(), components());
+
+    for format in FORMATS {
+        let text = model.to_string(format).unwrap();
+        let round_trip = ModelDef::parse(&text, format).unwrap();
+        assert_eq!(round_trip.to_string(format).unwrap(), text, "{format:?}");
+
+        let mut round_trip = spawn(round_trip, components());
+        let (before, after) = (joints(&mut world), joints(&mut round_trip));
+        assert_eq!(before.len(), 3);
+        assert_eq!(before.len(), after.len());
+        for ((a, a_spring), (b, b_spring)) in before.iter().zip(after.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a_spring, b_spring);
+            assert!((a.xt.position - b.xt.position).norm() < 1e-12);
+            assert!((a.xt.rotation - b.xt.rotation).norm() < 1e-12);
+            assert!((a.i.mass() - b.i.mass()).abs() < 1e-12);
+            assert!((a.i.com() - b.i.com()).norm() < 1e-12);
+            assert!((a.i.moi() - b.i.moi()).norm() < 1e-12);
+            assert!((a.s.v - b.s.v).norm() + (a.s.w - b.s.w).norm() < 1e-12);
+            assert_eq!((a.q, a.qd), (b.q, b.qd));
+        }
+    }
+    assert_eq!(joints(&mut world)[1].1, Some(100.));
+}
+
+#[test]
+fn zero_axis_is_invalid() {
+    let mut model = four_bar();
+    model.bodies[1].joint = JointDef::Revolute { axis: [0., 0., 0.] };
+    let error = model.validate().unwrap_err().to_string();
+    assert!(
+        error.contains("body coupler") && error.contains("axis"),
+        "{error}"
+    );
+}
+
+#[test]
+fn multi_dof_joints_have_no_q() {
+    let mut model = four_bar();
+    model.constraints.clear();
+    model.bodies[0].joint = JointDef::Spherical;
+    let error = model.validate().unwrap_err().to_string();
+    assert!(error.contains("body crank"), "{error}");
+    model.bodies[0].q = 0.;
+    model.validate().unwrap();
+}
+
+#[test]
+fn unregistered_components_are_invalid() {
+    let mut model = four_bar();
+    model.bodies[2].components.push(spring(100.));
+    let mut app = App::new();
+    app.add_systems(Startup, move |mut commands: Commands| {
+        let error = model.spawn(&mut commands).unwrap_err().to_string();
+        assert!(
+            error.contains("body rocker") && error.contains("spring"),
+            "{error}"
+        );
+        // the component fields are checked against the registered type
+        model.bodies[2].components[0].fields = serde_json::json!({ "damping": 1. });
+        let components = ModelComponents::default().register::<Spring>("spring");
+        let error = model
+            .spawn_with(&mut commands, &components)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("stiffness"), "{error}");
+    });
+    app.update();
+    // nothing is spawned
+    assert_eq!(app.world.query::<&Joint>().iter(&app.world).count(), 0);
+}
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use rigid_body::{
+    joint::Joint,
+    limits::{JointLimit, LimitType},
+    sva::{Motion, Xform},
+    urdf::{write_urdf, Urdf, UrdfBody},
+};
+
+// An arm with a gripper: the palm is fixed to the forearm, and the finger slides on the palm (a
+// joint attached to a merged link). The names need escaping.
+const ARM_URDF: &str = r#"<?xml version="1.0"?>
+<robot name="arm &amp; &quot;gripper&quot;">
+  <link name="world"/>
+  <joint name="shoulder" type="revolute">
+    <parent link="world"/>
+    <child link="upper_arm"/>
+    <origin xyz="0 0 0.5" rpy="0.1 0 0"/>
+    <axis xyz="0 1 0"/>
+    <limit lower="-1.2" upper="1.2" effort="10" velocity="2"/>
+  </joint>
+  <link name="upper_arm">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="1"/>
+      <inertia ixx="0.001" ixy="0" ixz="0" iyy="0.08" iyz="0" izz="0.08"/>
+    </inertial>
+    <visual>
+      <origin xyz="0.5 0 0"/>
+      <geometry>
+        <box size="1 0.05 0.05"/>
+      </geometry>
+      <material name="red">
+        <color rgba="1 0 0 1"/>
+      </material>
+    </visual>
+  </link>
+  <joint name="elbow" type="continuous">
+    <parent link="upper_arm"/>
+    <child link="fore&lt;arm&gt;"/>
+    <origin xyz="1 0 0"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+  <link name="fore&lt;arm&gt;">
+    <inertial>
+      <origin xyz="0.4 0 0"/>
+      <mass value="0.8"/>
+      <inertia ixx="0.0001" ixy="0" ixz="0" iyy="0.04" iyz="0" izz="0.04"/>
+    </inertial>
+  </link>
+  <joint name="wrist" type="fixed">
+    <parent link="fore&lt;arm&gt;"/>
+    <child link="palm"/>
+    <origin xyz="0.8 0 0" rpy="0 0 0.5"/>
+  </joint>
+  <link name="palm">
+    <inertial>
+      <mass value="0.5"/>
+      <inertia ixx="0.0005" ixy="0" ixz="0" iyy="0.0005" iyz="0" izz="0.0005"/>
+    </inertial>
+  </link>
+  <joint name="finger" type="prismatic">
+    <parent link="palm"/>
+    <child link="finger_tip"/>
+    <origin xyz="0.1 0 0"/>
+    <axis xyz="1 0 0"/>
+    <limit lower="0" upper="0.05" effort="5" velocity="0.1"/>
+  </joint>
+  <link name="finger_tip">
+    <inertial>
+      <mass value="0.1"/>
+      <inertia ixx="0.0001" ixy="0" ixz="0" iyy="0.0001" iyz="0" izz="0.0001"/>
+    </inertial>
+  </link>
+</robot>
+"#;
+
+fn spawn(urdf: Urdf) -> World {
+    let mut app = App::new();
+    app.add_systems(Startup, move |mut commands: Commands| {
+        urdf.spawn(&mut commands, Motion::zero()).unwrap();
+    });
+    app.update();
+    app.world
+}
+
+// the joints, sorted by name
+fn joints(world: &mut World) -> Vec<(Joint, Option<UrdfBody>, bool)> {
+    let mut joints: Vec<_> = world
+        .query::<(&Joint, Option<&UrdfBody>, Option<&JointLimit>)>()
+        .iter(world)
+        .map(|(joint, body, limit)| {
+            let joint = Joint {
+                name: joint.name.clone(),
+                xt: joint.xt,
+                i: joint.i,
+                s: joint.s,
+                ..default()
+            };
+            (joint, body.cloned(), limit.is_some())
+        })
+        .collect();
+    joints.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    joints
+}
+
+fn assert_xform_eq(a: &Xform, b: &Xform) {
+    assert!((a.position - b.position).norm() < 1e-12, "{a:?} {b:?}");
+    assert!((a.rotation - b.rotation).norm() < 1e-12, "{a:?} {b:?}");
+}
+
+#[test]
+fn urdf_round_trip() {
+    let mut world = spawn(Urdf::parse(ARM_URDF).unwrap());
+    let name = "arm & \"gripper\"";
+    let text = write_urdf(&world, name).unwrap();
+    assert!(!text.contains("name=\"\""), "{text}");
+    assert!(!text.contains("effort=\"0\""), "{text}");
+
+    let urdf = Urdf::parse(&text).unwrap();
+    assert_eq!(urdf.name, name);
+    let mut link_names: Vec<_> = urdf.links.iter().map(|link| link.name.as_str()).collect();
+    link_names.sort();
+    assert_eq!(
+        link_names,
+        ["finger_tip", "fore<arm>", "palm", "upper_arm", "world"]
+    );
+
+    let mut round_trip = spawn(urdf);
+    let (before, after) = (joints(&mut world), joints(&mut round_trip));
+    assert_eq!(before.len(), after.len());
+    for ((a, a_body, a_limit), (b, b_body, b_limit)) in before.iter().zip(after.iter()) {
+        assert_eq!(a.name, b.name);
+        assert_xform_eq(&a.xt, &b.xt);
+        assert!((a.i.mass() - b.i.mass()).abs() < 1e-12);
+        assert!((a.i.com() - b.i.com()).norm() < 1e-12);
+        assert!((a.i.moi() - b.i.moi()).norm() < 1e-12);
+        assert!((a.s.v - b.s.v).norm() + (a.s.w - b.s.w).norm() < 1e-12);
+        let (a_body, b_body) = (a_body.as_ref().unwrap(), b_body.as_ref().unwrap());
+        assert_eq!(a_body.link, b_body.link);
+        assert_eq!(a_body.parent_link, b_body.parent_link);
+        assert_eq!(a_body.limit, b_body.limit);
+        assert_eq!(a_body.merged.len(), b_body.merged.len());
+        for (a, b) in a_body.merged.iter().zip(b_body.merged.iter()) {
+            assert_eq!(
+                (&a.joint, &a.parent, &a.link),
+                (&b.joint, &b.parent, &b.link)
+            );
+            assert_xform_eq(&a.origin, &b.origin);
+            assert_xform_eq(&a.x, &b.x);
+        }
+        // the limits are only end stops when they are enabled
+        assert!(!a_limit && !b_limit);
+    }
+}
+
+#[test]
+fn limits_are_opt_in() {
+    let limited = |world: &mut World| world.query::<&JointLimit>().iter(world).count();
+    let mut world = spawn(Urdf::parse(ARM_URDF).unwrap());
+    assert_eq!(limited(&mut world), 0);
+    let urdf = Urdf::parse(ARM_URDF)
+        .unwrap()
+        .with_limits(LimitType::Hard { restitution: 0. });
+    let mut world = spawn(urdf);
+    assert_eq!(limited(&mut world), 2);
+}